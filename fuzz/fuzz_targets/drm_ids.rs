@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pssh_box::{DRMKeyId, DRMSystemId};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = DRMSystemId::try_from(data);
+    let _ = DRMKeyId::try_from(data);
+    if let Ok(s) = std::str::from_utf8(data) {
+        let _ = DRMSystemId::try_from(s);
+        let _ = DRMKeyId::try_from(s);
+        let _ = DRMKeyId::from_guid_str(s);
+        let _ = DRMKeyId::from_base64url(s);
+        let _ = DRMKeyId::from_playready_base64(s);
+    }
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use std::io::Cursor;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    pssh_box::find_boxes_stream(Cursor::new(data)).for_each(drop);
+});
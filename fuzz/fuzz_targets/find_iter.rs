@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    for pos in pssh_box::find_iter(data) {
+        assert!(pos < data.len());
+    }
+});
@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pssh_box::ParseOptions;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = pssh_box::from_bytes(data);
+    let strict = ParseOptions { strict: true, ..Default::default() };
+    let _ = pssh_box::from_bytes_with_options(data, &strict);
+});
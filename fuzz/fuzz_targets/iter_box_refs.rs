@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    for bx in pssh_box::iter_box_refs(data).flatten() {
+        assert!(bx.as_bytes().len() <= data.len());
+        let _ = bx.decode_pssh_data();
+    }
+});
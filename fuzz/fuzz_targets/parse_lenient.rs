@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let (boxes, diagnostics) = pssh_box::parse_lenient(data);
+    for d in &diagnostics {
+        assert!(d.offset().is_some_and(|o| o <= data.len() as u64));
+    }
+    let _ = boxes.to_string();
+});
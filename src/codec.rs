@@ -0,0 +1,357 @@
+//! Pluggable decoders and encoders for DRM-specific PSSH data.
+//
+// Each DRM system whose PSSH data we know how to decode is handled by a DrmPayloadCodec, registered
+// in a CodecRegistry under its system ID. The built-in systems are registered in exactly the same
+// way as codecs supplied by the caller, so a caller can also replace a built-in codec. A PsshBox
+// obtained by parsing keeps the codec which decoded its PSSH data, and uses it to encode, display
+// and extract key IDs from the PSSH data.
+
+use std::fmt;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, PoisonError, RwLock};
+use serde::{Serialize, Deserialize};
+use prost::Message;
+use tracing::trace;
+use crate::{irdeto, nagra, playready, wiseplay};
+use crate::widevine::WidevinePsshData;
+use crate::{DRMKeyId, DRMSystemId, PsshData, ToBytes};
+use crate::{COMMON_SYSTEM_ID, FAIRPLAYNFLX_SYSTEM_ID, IRDETO_SYSTEM_ID, MARLIN_SYSTEM_ID,
+            MOBI_SYSTEM_ID, NAGRA_SYSTEM_ID, PLAYREADY_SYSTEM_ID, WIDEVINE_SYSTEM_ID,
+            WISEPLAY_SYSTEM_ID};
+use crate::error::{PsshError, Result};
+
+
+/// Decoding, encoding and inspection of the PSSH data for a DRM system.
+///
+/// Codecs for DRM systems which this crate doesn't know about store their decoded PSSH data in
+/// [`PsshData::Custom`].
+pub trait DrmPayloadCodec: Send + Sync {
+    /// Decode the PSSH data of a box with the given system ID. Error offsets are relative to the
+    /// start of `pssh_data`.
+    fn decode(&self, system_id: DRMSystemId, pssh_data: &[u8]) -> Result<PsshData>;
+
+    /// Encode PSSH data, as returned by [`DrmPayloadCodec::decode`], to octets. For
+    /// `PsshData::Custom` values, this must not call `to_bytes` or `try_to_bytes` on `pssh_data`,
+    /// which encode `Custom` values by looking up a codec again and would recurse without end.
+    fn encode(&self, pssh_data: &PsshData) -> Result<Vec<u8>>;
+
+    /// Write a single-line description of the PSSH data, used by the `Display` implementations of
+    /// `PsshData` and `PsshBox`. For `PsshData::Custom` values, this must not call the `Display`
+    /// implementation of `pssh_data`.
+    fn display(&self, pssh_data: &PsshData, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+
+    /// The key IDs contained in the PSSH data.
+    fn key_ids(&self, pssh_data: &PsshData) -> Vec<DRMKeyId>;
+}
+
+/// PSSH data decoded by a codec registered by the caller, for a DRM system which this crate doesn't
+/// know about.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomPsshData {
+    pub system_id: DRMSystemId,
+    pub value: serde_json::Value,
+}
+
+
+// The encoder of the built-in codecs. PsshData::Custom values are encoded by looking up a codec in
+// the global registry, which would call the built-in codec again.
+fn encode_builtin(pssh_data: &PsshData) -> Result<Vec<u8>> {
+    match pssh_data {
+        PsshData::Custom(pd) => Err(PsshError::Encode {
+            system: pd.system_id,
+            source: "custom PSSH data can't be encoded by a built-in codec".into(),
+        }),
+        _ => pssh_data.try_to_bytes(),
+    }
+}
+
+fn display_builtin(pssh_data: &PsshData, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match pssh_data {
+        PsshData::Custom(pd) => write!(f, "CustomPSSHData<{}>", pd.value),
+        _ => fmt::Display::fmt(pssh_data, f),
+    }
+}
+
+// Display PSSH data using a codec.
+pub(crate) struct CodecDisplay<'a>(pub &'a dyn DrmPayloadCodec, pub &'a PsshData);
+
+impl fmt::Display for CodecDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.display(self.1, f)
+    }
+}
+
+
+/// The codec for Widevine PSSH data (protobuf).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WidevineCodec;
+
+impl DrmPayloadCodec for WidevineCodec {
+    fn decode(&self, system_id: DRMSystemId, pssh_data: &[u8]) -> Result<PsshData> {
+        let wv = WidevinePsshData::decode(pssh_data)
+            .map_err(|e| PsshError::payload(system_id, 0, e))?;
+        Ok(PsshData::Widevine(wv))
+    }
+
+    fn encode(&self, pssh_data: &PsshData) -> Result<Vec<u8>> {
+        encode_builtin(pssh_data)
+    }
+
+    fn display(&self, pssh_data: &PsshData, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        display_builtin(pssh_data, f)
+    }
+
+    fn key_ids(&self, pssh_data: &PsshData) -> Vec<DRMKeyId> {
+        match pssh_data {
+            PsshData::Widevine(wv) => wv.key_ids(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// The codec for PlayReady PSSH data (a PlayReady Header Object).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlayReadyCodec;
+
+impl DrmPayloadCodec for PlayReadyCodec {
+    fn decode(&self, _system_id: DRMSystemId, pssh_data: &[u8]) -> Result<PsshData> {
+        Ok(PsshData::PlayReady(playready::parse_pssh_data(pssh_data)?))
+    }
+
+    fn encode(&self, pssh_data: &PsshData) -> Result<Vec<u8>> {
+        encode_builtin(pssh_data)
+    }
+
+    fn display(&self, pssh_data: &PsshData, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        display_builtin(pssh_data, f)
+    }
+
+    fn key_ids(&self, pssh_data: &PsshData) -> Vec<DRMKeyId> {
+        match pssh_data {
+            PsshData::PlayReady(pr) => pr.key_ids(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// The codec for Irdeto PSSH data.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IrdetoCodec;
+
+impl DrmPayloadCodec for IrdetoCodec {
+    fn decode(&self, _system_id: DRMSystemId, pssh_data: &[u8]) -> Result<PsshData> {
+        Ok(PsshData::Irdeto(irdeto::parse_pssh_data(pssh_data)?))
+    }
+
+    fn encode(&self, pssh_data: &PsshData) -> Result<Vec<u8>> {
+        encode_builtin(pssh_data)
+    }
+
+    fn display(&self, pssh_data: &PsshData, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        display_builtin(pssh_data, f)
+    }
+
+    fn key_ids(&self, _pssh_data: &PsshData) -> Vec<DRMKeyId> {
+        Vec::new()
+    }
+}
+
+/// The codec for Nagra PSSH data (base64-encoded JSON).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NagraCodec;
+
+impl DrmPayloadCodec for NagraCodec {
+    fn decode(&self, _system_id: DRMSystemId, pssh_data: &[u8]) -> Result<PsshData> {
+        Ok(PsshData::Nagra(nagra::parse_pssh_data(pssh_data)?))
+    }
+
+    fn encode(&self, pssh_data: &PsshData) -> Result<Vec<u8>> {
+        encode_builtin(pssh_data)
+    }
+
+    fn display(&self, pssh_data: &PsshData, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        display_builtin(pssh_data, f)
+    }
+
+    fn key_ids(&self, pssh_data: &PsshData) -> Vec<DRMKeyId> {
+        match pssh_data {
+            PsshData::Nagra(n) => n.key_ids(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// The codec for WisePlay PSSH data (JSON).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WisePlayCodec;
+
+impl DrmPayloadCodec for WisePlayCodec {
+    fn decode(&self, _system_id: DRMSystemId, pssh_data: &[u8]) -> Result<PsshData> {
+        Ok(PsshData::WisePlay(wiseplay::parse_pssh_data(pssh_data)?))
+    }
+
+    fn encode(&self, pssh_data: &PsshData) -> Result<Vec<u8>> {
+        encode_builtin(pssh_data)
+    }
+
+    fn display(&self, pssh_data: &PsshData, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        display_builtin(pssh_data, f)
+    }
+
+    fn key_ids(&self, pssh_data: &PsshData) -> Vec<DRMKeyId> {
+        match pssh_data {
+            PsshData::WisePlay(wp) => wp.key_ids(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// The codec for DRM systems whose PSSH data is kept as raw octets (Marlin, Common Encryption,
+/// FairPlay, MobiDRM, and any system without a registered codec).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RawCodec;
+
+impl DrmPayloadCodec for RawCodec {
+    fn decode(&self, system_id: DRMSystemId, pssh_data: &[u8]) -> Result<PsshData> {
+        let octets = pssh_data.to_vec();
+        let pd = match system_id {
+            MARLIN_SYSTEM_ID => PsshData::Marlin(octets),
+            COMMON_SYSTEM_ID => PsshData::CommonEnc(octets),
+            FAIRPLAYNFLX_SYSTEM_ID => PsshData::FairPlay(octets),
+            MOBI_SYSTEM_ID => PsshData::Mobi(octets),
+            _ => {
+                trace!("Keeping PSSH data for unrecognized system_id {system_id:?}");
+                PsshData::Unknown(octets)
+            },
+        };
+        Ok(pd)
+    }
+
+    fn encode(&self, pssh_data: &PsshData) -> Result<Vec<u8>> {
+        encode_builtin(pssh_data)
+    }
+
+    fn display(&self, pssh_data: &PsshData, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        display_builtin(pssh_data, f)
+    }
+
+    fn key_ids(&self, _pssh_data: &PsshData) -> Vec<DRMKeyId> {
+        Vec::new()
+    }
+}
+
+
+/// A mapping from DRM system IDs to the codec which handles their PSSH data.
+///
+/// PSSH data for a system ID without a registered codec is kept verbatim in
+/// [`PsshData::Unknown`].
+#[derive(Clone, Default)]
+pub struct CodecRegistry {
+    codecs: HashMap<DRMSystemId, Arc<dyn DrmPayloadCodec>>,
+}
+
+impl CodecRegistry {
+    /// An empty registry, in which no DRM system has a codec.
+    pub fn new() -> CodecRegistry {
+        CodecRegistry::default()
+    }
+
+    /// A registry containing the codecs for the DRM systems supported by this crate.
+    pub fn with_builtins() -> CodecRegistry {
+        let mut registry = CodecRegistry::new();
+        registry.register(WIDEVINE_SYSTEM_ID, WidevineCodec);
+        registry.register(PLAYREADY_SYSTEM_ID, PlayReadyCodec);
+        registry.register(IRDETO_SYSTEM_ID, IrdetoCodec);
+        registry.register(NAGRA_SYSTEM_ID, NagraCodec);
+        registry.register(WISEPLAY_SYSTEM_ID, WisePlayCodec);
+        registry.register(MARLIN_SYSTEM_ID, RawCodec);
+        registry.register(COMMON_SYSTEM_ID, RawCodec);
+        registry.register(FAIRPLAYNFLX_SYSTEM_ID, RawCodec);
+        registry.register(MOBI_SYSTEM_ID, RawCodec);
+        registry
+    }
+
+    /// Register `codec` for `system_id`, returning the codec it replaces.
+    pub fn register(&mut self, system_id: DRMSystemId, codec: impl DrmPayloadCodec + 'static)
+        -> Option<Arc<dyn DrmPayloadCodec>>
+    {
+        self.codecs.insert(system_id, Arc::new(codec))
+    }
+
+    /// Remove the codec for `system_id`, returning it.
+    pub fn unregister(&mut self, system_id: DRMSystemId) -> Option<Arc<dyn DrmPayloadCodec>> {
+        self.codecs.remove(&system_id)
+    }
+
+    /// The codec registered for `system_id`.
+    pub fn get(&self, system_id: DRMSystemId) -> Option<Arc<dyn DrmPayloadCodec>> {
+        self.codecs.get(&system_id).cloned()
+    }
+
+    /// The system IDs which have a registered codec.
+    pub fn system_ids(&self) -> impl Iterator<Item = DRMSystemId> + '_ {
+        self.codecs.keys().copied()
+    }
+
+    /// Decode `pssh_data` using the codec registered for `system_id`, or keep it as
+    /// `PsshData::Unknown` if there is none. Error offsets are relative to the start of `pssh_data`.
+    pub fn decode(&self, system_id: DRMSystemId, pssh_data: &[u8]) -> Result<PsshData> {
+        match self.codecs.get(&system_id) {
+            Some(codec) => codec.decode(system_id, pssh_data),
+            None => RawCodec.decode(system_id, pssh_data),
+        }
+    }
+}
+
+impl fmt::Debug for CodecRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.codecs.keys()).finish()
+    }
+}
+
+
+// The registry used when ParseOptions doesn't specify one, initialized with the built-in codecs.
+fn global() -> &'static RwLock<CodecRegistry> {
+    static GLOBAL: OnceLock<RwLock<CodecRegistry>> = OnceLock::new();
+    GLOBAL.get_or_init(|| RwLock::new(CodecRegistry::with_builtins()))
+}
+
+/// Register `codec` for `system_id` in the global registry, which is used by the parsing and
+/// scanning functions unless [`crate::ParseOptions::registry`] is set, and to serialize and display
+/// `PsshData::Custom` values. Returns the codec it replaces.
+pub fn register_codec(system_id: DRMSystemId, codec: impl DrmPayloadCodec + 'static)
+    -> Option<Arc<dyn DrmPayloadCodec>>
+{
+    global().write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .register(system_id, codec)
+}
+
+/// Remove the codec for `system_id` from the global registry, returning it.
+pub fn unregister_codec(system_id: DRMSystemId) -> Option<Arc<dyn DrmPayloadCodec>> {
+    global().write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .unregister(system_id)
+}
+
+/// The codec registered for `system_id` in the global registry.
+pub fn codec_for(system_id: DRMSystemId) -> Option<Arc<dyn DrmPayloadCodec>> {
+    global().read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(system_id)
+}
+
+/// A copy of the global registry, for example as a starting point for a registry passed in
+/// [`crate::ParseOptions`].
+pub fn global_registry() -> CodecRegistry {
+    global().read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+pub(crate) fn decode_global(system_id: DRMSystemId, pssh_data: &[u8]) -> Result<PsshData> {
+    match codec_for(system_id) {
+        Some(codec) => codec.decode(system_id, pssh_data),
+        None => RawCodec.decode(system_id, pssh_data),
+    }
+}
@@ -0,0 +1,200 @@
+//! Field-level comparison of two sets of PSSH boxes, for example those produced by two packaging runs
+//! of the same content.
+
+use std::fmt;
+use crate::{PsshBox, PsshBoxVec, PsshData, DRMKeyId, DRMSystemId};
+use crate::widevine::WidevinePsshData;
+use crate::widevine::widevine_pssh_data::ProtectionScheme;
+use crate::playready::{PlayReadyRecord, WRMData};
+
+
+/// A difference between two sets of PSSH boxes, reported by [`PsshBoxVec::diff`].
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// A box for `system_id` is present only in the new set (or the new set contains more boxes for
+    /// `system_id` than the old one).
+    SystemAdded { system_id: DRMSystemId },
+    /// A box for `system_id` is present only in the old set.
+    SystemRemoved { system_id: DRMSystemId },
+    VersionChanged { system_id: DRMSystemId, old: u8, new: u8 },
+    FlagsChanged { system_id: DRMSystemId, old: u32, new: u32 },
+    /// The boxes for `system_id` in the new set reference `key_id`, which those in the old set don't.
+    KeyIdAdded { system_id: DRMSystemId, key_id: DRMKeyId },
+    /// The boxes for `system_id` in the old set reference `key_id`, which those in the new set don't.
+    KeyIdRemoved { system_id: DRMSystemId, key_id: DRMKeyId },
+    /// The value of `field` in the DRM-specific PSSH data changed, where `None` means the field is
+    /// absent.
+    FieldChanged { system_id: DRMSystemId, field: &'static str, old: Option<String>, new: Option<String> },
+}
+
+impl Change {
+    /// The DRM system whose boxes changed.
+    pub fn system_id(&self) -> DRMSystemId {
+        match self {
+            Change::SystemAdded { system_id } |
+            Change::SystemRemoved { system_id } |
+            Change::VersionChanged { system_id, .. } |
+            Change::FlagsChanged { system_id, .. } |
+            Change::KeyIdAdded { system_id, .. } |
+            Change::KeyIdRemoved { system_id, .. } |
+            Change::FieldChanged { system_id, .. } => *system_id,
+        }
+    }
+}
+
+// The name of a DRM system, or its UUID if it is unknown.
+fn system_name(system_id: &DRMSystemId) -> String {
+    system_id.drm_system().map_or_else(|| system_id.to_uuid_string(), |s| String::from(s.name))
+}
+
+fn field_value(v: &Option<String>) -> &str {
+    v.as_deref().unwrap_or("(absent)")
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let system = system_name(&self.system_id());
+        match self {
+            Change::SystemAdded { .. } => write!(f, "+ {system}: box added"),
+            Change::SystemRemoved { .. } => write!(f, "- {system}: box removed"),
+            Change::VersionChanged { old, new, .. } => write!(f, "~ {system}: version {old} -> {new}"),
+            Change::FlagsChanged { old, new, .. } => write!(f, "~ {system}: flags {old:#x} -> {new:#x}"),
+            Change::KeyIdAdded { key_id, .. } =>
+                write!(f, "+ {system}: key ID {}", key_id.to_uuid_string()),
+            Change::KeyIdRemoved { key_id, .. } =>
+                write!(f, "- {system}: key ID {}", key_id.to_uuid_string()),
+            Change::FieldChanged { field, old, new, .. } =>
+                write!(f, "~ {system}: {field} {} -> {}", field_value(old), field_value(new)),
+        }
+    }
+}
+
+/// The differences between two sets of PSSH boxes. The `Display` implementation prints one change
+/// per line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PsshDiff {
+    pub changes: Vec<Change>,
+}
+
+impl PsshDiff {
+    /// Whether the two sets of boxes have no differences in the fields which are compared.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for PsshDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in &self.changes {
+            writeln!(f, "{c}")?;
+        }
+        Ok(())
+    }
+}
+
+
+fn playready_fields(data: &WRMData) -> [(&'static str, Option<String>); 4] {
+    [("LA_URL", data.la_url.clone()),
+     ("LUI_URL", data.lui_url.clone()),
+     ("DS_ID", data.ds_id.clone()),
+     ("CUSTOMATTRIBUTES", data.custom_attributes.clone())]
+}
+
+fn widevine_fields(wv: &WidevinePsshData) -> [(&'static str, Option<String>); 4] {
+    let scheme = wv.protection_scheme.map(|ps| match ProtectionScheme::try_from(ps) {
+        Ok(s) => s.as_str_name().to_lowercase(),
+        Err(_) => format!("unknown ({ps})"),
+    });
+    [("provider", wv.provider.clone()),
+     ("content_id", wv.content_id.as_ref().map(hex::encode)),
+     ("protection_scheme", scheme),
+     ("crypto_period_index", wv.crypto_period_index.map(|cpi| cpi.to_string()))]
+}
+
+// The compared fields of the DRM-specific PSSH data, with the PlayReady fields taken from each
+// record in turn.
+fn payload_fields(bx: &PsshBox) -> Vec<(&'static str, Option<String>)> {
+    match &bx.pssh_data {
+        PsshData::Widevine(wv) => widevine_fields(wv).to_vec(),
+        PsshData::PlayReady(pr) => pr.record.iter()
+            .filter_map(PlayReadyRecord::wrm_header)
+            .flat_map(|wrmh| playready_fields(&wrmh.data))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn diff_boxes(old: &PsshBox, new: &PsshBox, changes: &mut Vec<Change>) {
+    let system_id = new.system_id;
+    if old.version != new.version {
+        changes.push(Change::VersionChanged { system_id, old: old.version, new: new.version });
+    }
+    if old.flags != new.flags {
+        changes.push(Change::FlagsChanged { system_id, old: old.flags, new: new.flags });
+    }
+    let old_fields = payload_fields(old);
+    let new_fields = payload_fields(new);
+    for i in 0..old_fields.len().max(new_fields.len()) {
+        let (field, old_value) = old_fields.get(i).cloned().unwrap_or((new_fields[i].0, None));
+        let new_value = new_fields.get(i).and_then(|(_, v)| v.clone());
+        if old_value != new_value {
+            changes.push(Change::FieldChanged { system_id, field, old: old_value, new: new_value });
+        }
+    }
+}
+
+fn key_ids_for(boxes: &[&PsshBox]) -> Vec<DRMKeyId> {
+    let mut kids = Vec::new();
+    for bx in boxes {
+        for t in bx.all_key_ids() {
+            if !kids.contains(&t.key_id) {
+                kids.push(t.key_id);
+            }
+        }
+    }
+    kids
+}
+
+impl PsshBoxVec {
+    /// The differences between these boxes and the boxes in `new`. Boxes are matched by system ID,
+    /// and in order of appearance when several boxes are for the same DRM system. Key IDs are
+    /// compared over all the boxes for a DRM system.
+    pub fn diff(&self, new: &PsshBoxVec) -> PsshDiff {
+        let mut systems: Vec<DRMSystemId> = Vec::new();
+        for bx in self.iter().chain(new.iter()) {
+            if !systems.contains(&bx.system_id) {
+                systems.push(bx.system_id);
+            }
+        }
+        let mut changes = Vec::new();
+        for system_id in systems {
+            let old_boxes: Vec<&PsshBox> = self.for_system(system_id).collect();
+            let new_boxes: Vec<&PsshBox> = new.for_system(system_id).collect();
+            for (o, n) in old_boxes.iter().zip(new_boxes.iter()) {
+                diff_boxes(o, n, &mut changes);
+            }
+            for _ in new_boxes.len()..old_boxes.len() {
+                changes.push(Change::SystemRemoved { system_id });
+            }
+            for _ in old_boxes.len()..new_boxes.len() {
+                changes.push(Change::SystemAdded { system_id });
+            }
+            if !old_boxes.is_empty() && !new_boxes.is_empty() {
+                let old_kids = key_ids_for(&old_boxes);
+                let new_kids = key_ids_for(&new_boxes);
+                for key_id in &new_kids {
+                    if !old_kids.contains(key_id) {
+                        changes.push(Change::KeyIdAdded { system_id, key_id: *key_id });
+                    }
+                }
+                for key_id in &old_kids {
+                    if !new_kids.contains(key_id) {
+                        changes.push(Change::KeyIdRemoved { system_id, key_id: *key_id });
+                    }
+                }
+            }
+        }
+        PsshDiff { changes }
+    }
+}
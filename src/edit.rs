@@ -0,0 +1,311 @@
+//! Modifications which are applied consistently to the box header and to the DRM-specific PSSH data,
+//! such as changing the license acquisition URL or rotating a key ID.
+//!
+//! The PlayReady KID checksums can't be recomputed without the content key, so a checksum which
+//! refers to a modified key ID is removed, and the key ID is reported in
+//! [`KeyIdReplacement::removed_checksums`]. It can be recomputed with
+//! [`crate::playready::compute_checksum`].
+
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use serde_json::Value;
+use crate::{PsshBox, PsshBoxVec, PsshData, PsshError, DRMKeyId, DRMSystemId, KeyIdLocation, Result};
+use crate::playready::{PlayReadyKid, PlayReadyKids, PlayReadyPsshData, PlayReadyRecord, ProtectInfo};
+use crate::wiseplay::WisePlayPsshData;
+
+
+/// The result of replacing a key ID with [`PsshBox::replace_key_id`] or
+/// [`PsshBoxVec::replace_key_id`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyIdReplacement {
+    /// The number of occurrences of the key ID which were replaced.
+    pub replaced: usize,
+    /// The new key IDs whose PlayReady checksum was removed because it referred to the old key ID.
+    /// Their checksum can be recomputed from the content key with
+    /// [`crate::playready::compute_checksum`].
+    pub removed_checksums: Vec<DRMKeyId>,
+}
+
+impl KeyIdReplacement {
+    fn merge(&mut self, other: KeyIdReplacement) {
+        self.replaced += other.replaced;
+        for kid in other.removed_checksums {
+            if !self.removed_checksums.contains(&kid) {
+                self.removed_checksums.push(kid);
+            }
+        }
+    }
+}
+
+fn unsupported(bx: &PsshBox, operation: &'static str, reason: &'static str) -> PsshError {
+    PsshError::UnsupportedOperation { system: bx.system_id, operation, reason }
+}
+
+// Replace the key ID in a KID element, returning whether it was replaced and whether its checksum
+// was removed.
+fn replace_playready_kid(k: &mut PlayReadyKid, old: DRMKeyId, new: DRMKeyId) -> (bool, bool) {
+    if k.key_id() != Some(old) {
+        return (false, false);
+    }
+    let guid = new.to_guid_bytes();
+    if k.content.len() == 16 {
+        k.content = guid.to_vec();
+    } else {
+        k.value = Some(BASE64_STANDARD.encode(guid));
+    }
+    (true, k.checksum.take().is_some())
+}
+
+fn replace_in_playready(pr: &mut PlayReadyPsshData, old: DRMKeyId, new: DRMKeyId) -> KeyIdReplacement {
+    let mut replacement = KeyIdReplacement::default();
+    let mut removed_checksum = false;
+    for wrmh in pr.record.iter_mut().filter_map(PlayReadyRecord::wrm_header_mut) {
+        let data = &mut wrmh.data;
+        let mut replaced_data_kid = false;
+        let pi_kids = data.protect_info.iter_mut().flat_map(ProtectInfo::kids_mut);
+        for k in data.kids.iter_mut().chain(pi_kids) {
+            let (replaced, removed) = replace_playready_kid(k, old, new);
+            if replaced {
+                replacement.replaced += 1;
+            }
+            replaced_data_kid |= replaced;
+            removed_checksum |= removed;
+        }
+        // In a version 4.0 header the checksum of the KID is in the CHECKSUM element of DATA.
+        if replaced_data_kid && data.checksum.take().is_some() {
+            removed_checksum = true;
+        }
+    }
+    if removed_checksum {
+        replacement.removed_checksums.push(new);
+    }
+    replacement
+}
+
+fn insert_in_playready(bx: &PsshBox, pr: &mut PlayReadyPsshData, kid: DRMKeyId) -> Result<()> {
+    let mut found_record = false;
+    for wrmh in pr.record.iter_mut().filter_map(PlayReadyRecord::wrm_header_mut) {
+        found_record = true;
+        let version = wrmh.version.clone();
+        let data = &mut wrmh.data;
+        let present = data.kids.iter()
+            .chain(data.protect_info.iter().flat_map(ProtectInfo::kids))
+            .any(|k| k.key_id() == Some(kid));
+        if present {
+            continue;
+        }
+        if version.starts_with("4.0.") {
+            if !data.kids.is_empty() {
+                return Err(unsupported(bx, "add key ID", "a PlayReady 4.0 header holds a single KID"));
+            }
+            data.kids.push(PlayReadyKid { content: kid.to_guid_bytes().to_vec(), ..Default::default() });
+        } else {
+            let pi = data.protect_info.get_or_insert_with(Default::default);
+            let algid = pi.kids().next().and_then(|k| k.algid.clone()).or_else(|| pi.algid.clone());
+            let new_kid = PlayReadyKid {
+                value: Some(BASE64_STANDARD.encode(kid.to_guid_bytes())),
+                algid,
+                ..Default::default()
+            };
+            if version.starts_with("4.1.") {
+                // A 4.1 header holds a single KID, directly in PROTECTINFO.
+                if pi.kid.is_some() {
+                    return Err(unsupported(bx, "add key ID", "a PlayReady 4.1 header holds a single KID"));
+                }
+                pi.kid = Some(new_kid);
+            } else {
+                pi.kids.get_or_insert_with(PlayReadyKids::default).kid.push(new_kid);
+            }
+        }
+    }
+    if !found_record {
+        return Err(unsupported(bx, "add key ID", "no PlayReady Rights Management record"));
+    }
+    Ok(())
+}
+
+// The entries of the WisePlay `kids` array contain the base64 encoding either of the 16 octets of
+// the KID or of its 32 hex characters. The encoding used by an entry is retained when it's replaced.
+fn replace_in_wiseplay(wp: &mut WisePlayPsshData, old: DRMKeyId, new: DRMKeyId) -> usize {
+    let Some(kids) = wp.json.get_mut("kids").and_then(Value::as_array_mut) else {
+        return 0;
+    };
+    let mut count = 0;
+    for entry in kids.iter_mut() {
+        let Some(octets) = entry.as_str().and_then(|k| BASE64_STANDARD.decode(k).ok()) else {
+            continue;
+        };
+        if octets.len() == 16 && octets == old.as_bytes() {
+            *entry = Value::String(BASE64_STANDARD.encode(new.as_bytes()));
+            count += 1;
+        } else if octets.len() == 32 && octets.eq_ignore_ascii_case(old.to_hex().as_bytes()) {
+            *entry = Value::String(BASE64_STANDARD.encode(new.to_hex()));
+            count += 1;
+        }
+    }
+    count
+}
+
+fn insert_in_wiseplay(bx: &PsshBox, wp: &mut WisePlayPsshData, kid: DRMKeyId) -> Result<()> {
+    if wp.key_ids().contains(&kid) {
+        return Ok(());
+    }
+    let Some(obj) = wp.json.as_object_mut() else {
+        return Err(unsupported(bx, "add key ID", "WisePlay PSSH data is not a JSON object"));
+    };
+    let kids = obj.entry("kids").or_insert_with(|| Value::Array(Vec::new()));
+    let Some(kids) = kids.as_array_mut() else {
+        return Err(unsupported(bx, "add key ID", "WisePlay kids field is not an array"));
+    };
+    kids.push(Value::String(BASE64_STANDARD.encode(kid.as_bytes())));
+    Ok(())
+}
+
+impl PsshBox {
+    /// Set the license acquisition URL in the PSSH data. Only PlayReady PSSH data contains a license
+    /// URL (the `LA_URL` of each Rights Management record); for other DRM systems this fails with
+    /// [`PsshError::UnsupportedOperation`].
+    pub fn set_license_url(&mut self, url: &str) -> Result<()> {
+        let PsshData::PlayReady(pr) = &mut self.pssh_data else {
+            return Err(PsshError::UnsupportedOperation {
+                system: self.system_id,
+                operation: "set license URL",
+                reason: "the PSSH data doesn't contain a license URL",
+            });
+        };
+        let mut found_record = false;
+        for wrmh in pr.record.iter_mut().filter_map(PlayReadyRecord::wrm_header_mut) {
+            wrmh.data.la_url = Some(String::from(url));
+            found_record = true;
+        }
+        if !found_record {
+            return Err(PsshError::UnsupportedOperation {
+                system: self.system_id,
+                operation: "set license URL",
+                reason: "no PlayReady Rights Management record",
+            });
+        }
+        Ok(())
+    }
+
+    /// Replace the key ID `old` by `new` in the box header and in the DRM-specific PSSH data,
+    /// returning the number of occurrences replaced and the key IDs whose PlayReady checksum was
+    /// removed. Fails with [`PsshError::UnsupportedOperation`], leaving the box unchanged, if `old`
+    /// is present in PSSH data that we don't know how to modify.
+    pub fn replace_key_id(&mut self, old: DRMKeyId, new: DRMKeyId) -> Result<KeyIdReplacement> {
+        let mut bx = self.clone();
+        let mut replacement = KeyIdReplacement::default();
+        for kid in &mut bx.key_ids {
+            if *kid == old {
+                *kid = new;
+                replacement.replaced += 1;
+            }
+        }
+        match &mut bx.pssh_data {
+            PsshData::Widevine(wv) => {
+                for kid in &mut wv.key_id {
+                    if kid.as_slice() == old.as_bytes() {
+                        *kid = new.as_bytes().to_vec();
+                        replacement.replaced += 1;
+                    }
+                }
+            },
+            PsshData::PlayReady(pr) => replacement.merge(replace_in_playready(pr, old, new)),
+            PsshData::Nagra(n) => {
+                if n.key_ids() == [old] {
+                    n.key_id = new.to_uuid_string();
+                    replacement.replaced += 1;
+                }
+            },
+            PsshData::WisePlay(wp) => replacement.replaced += replace_in_wiseplay(wp, old, new),
+            _ => {
+                let in_payload = self.key_id_locations().iter()
+                    .any(|(location, kid)| *location != KeyIdLocation::Header && *kid == old);
+                if in_payload {
+                    return Err(unsupported(self, "replace key ID", "the PSSH data can't be modified"));
+                }
+            },
+        }
+        *self = bx;
+        Ok(replacement)
+    }
+
+    /// Add the key ID `kid` to the header of a v1 box and to the DRM-specific PSSH data, unless it
+    /// is already present. Unlike [`PsshBox::add_key_id`], which only modifies the box header, this
+    /// keeps the header and PSSH data consistent. Fails with [`PsshError::UnsupportedOperation`],
+    /// leaving the box unchanged, if the PSSH data can't hold another key ID (PlayReady 4.0 and 4.1
+    /// headers and Nagra PSSH data hold a single KID), or if this is a v0 box whose PSSH data
+    /// can't hold key IDs.
+    pub fn insert_key_id(&mut self, kid: DRMKeyId) -> Result<()> {
+        let mut bx = self.clone();
+        if bx.version == 1 && !bx.key_ids.contains(&kid) {
+            bx.key_ids.push(kid);
+        }
+        match &mut bx.pssh_data {
+            PsshData::Widevine(wv) => {
+                if !wv.key_id.iter().any(|k| k.as_slice() == kid.as_bytes()) {
+                    wv.key_id.push(kid.as_bytes().to_vec());
+                }
+            },
+            PsshData::PlayReady(pr) => insert_in_playready(self, pr, kid)?,
+            PsshData::Nagra(n) => {
+                if n.key_id.is_empty() {
+                    n.key_id = kid.to_uuid_string();
+                } else if n.key_ids() != [kid] {
+                    return Err(unsupported(self, "add key ID", "Nagra PSSH data holds a single key ID"));
+                }
+            },
+            PsshData::WisePlay(wp) => insert_in_wiseplay(self, wp, kid)?,
+            _ => {
+                if bx.version == 0 {
+                    return Err(unsupported(self, "add key ID", "the PSSH data can't hold key IDs"));
+                }
+            },
+        }
+        *self = bx;
+        Ok(())
+    }
+}
+
+impl PsshBoxVec {
+    /// Set the license acquisition URL in the boxes for the DRM system `system_id`, as for
+    /// [`PsshBox::set_license_url`]. Fails with [`PsshError::UnsupportedOperation`] if there is no
+    /// box for `system_id`. On error, no box is modified.
+    pub fn set_license_url(&mut self, system_id: DRMSystemId, url: &str) -> Result<()> {
+        if !self.iter().any(|bx| bx.system_id == system_id) {
+            return Err(PsshError::UnsupportedOperation {
+                system: system_id,
+                operation: "set license URL",
+                reason: "there is no box for this DRM system",
+            });
+        }
+        let mut boxes = self.0.clone();
+        for bx in boxes.iter_mut().filter(|bx| bx.system_id == system_id) {
+            bx.set_license_url(url)?;
+        }
+        self.0 = boxes;
+        Ok(())
+    }
+
+    /// Replace the key ID `old` by `new` in all the boxes, as for [`PsshBox::replace_key_id`].
+    /// On error, no box is modified.
+    pub fn replace_key_id(&mut self, old: DRMKeyId, new: DRMKeyId) -> Result<KeyIdReplacement> {
+        let mut boxes = self.0.clone();
+        let mut replacement = KeyIdReplacement::default();
+        for bx in &mut boxes {
+            replacement.merge(bx.replace_key_id(old, new)?);
+        }
+        self.0 = boxes;
+        Ok(replacement)
+    }
+
+    /// Add the key ID `kid` to all the boxes, as for [`PsshBox::insert_key_id`]. On error, no box is
+    /// modified.
+    pub fn insert_key_id(&mut self, kid: DRMKeyId) -> Result<()> {
+        let mut boxes = self.0.clone();
+        for bx in &mut boxes {
+            bx.insert_key_id(kid)?;
+        }
+        self.0 = boxes;
+        Ok(())
+    }
+}
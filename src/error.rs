@@ -0,0 +1,216 @@
+//! Error type returned by the parsing and serialization functions in this crate.
+
+use std::fmt;
+use std::io;
+use std::error::Error as StdError;
+use crate::{DRMKeyId, DRMSystemId};
+
+
+/// A boxed error from an underlying decoder or encoder (protobuf, XML, JSON, base64 and so on).
+pub type BoxedError = Box<dyn StdError + Send + Sync + 'static>;
+
+/// A `Result` whose error type defaults to [`PsshError`].
+pub type Result<T, E = PsshError> = std::result::Result<T, E>;
+
+/// An error encountered while parsing or serializing PSSH boxes or DRM-specific PSSH data.
+///
+/// Each parsing variant carries the byte `offset` at which parsing failed. Offsets are counted from the start
+/// of the octets being parsed: the decoded initialization data for the `from_*` functions, or the
+/// start of the PSSH data for the `parse_pssh_data` functions in the DRM-specific modules. For
+/// [`PsshError::InvalidEncoding`] the offset is a position in the base64 or hex text.
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum PsshError {
+    /// The input ended early: `needed` octets were expected at `offset`, but fewer were available.
+    Truncated { offset: u64, needed: u64 },
+    /// The ISO BMFF box type at `offset` is not `pssh`.
+    BadBoxType { offset: u64, found: [u8; 4] },
+    /// The box starting at `offset` declares a `size` which is too small to hold a box header.
+    BadBoxSize { offset: u64, size: u64 },
+    /// The box starting at `offset` declares a size of `declared` octets in its header, but its
+    /// contents occupy `consumed` octets.
+    BoxSizeMismatch { offset: u64, declared: u64, consumed: u64 },
+    /// The PSSH box version at `offset` is neither 0 nor 1.
+    UnsupportedVersion { offset: u64, version: u8 },
+    /// The DRM-specific PSSH data starting at `offset` could not be decoded.
+    PayloadDecode { offset: u64, system: DRMSystemId, source: BoxedError },
+    /// The base64 or hex text is invalid at `offset`.
+    InvalidEncoding { offset: u64, source: BoxedError },
+    /// A value has `found` octets (or characters) instead of the `expected` number.
+    InvalidLength { offset: u64, expected: usize, found: usize },
+    /// `length` octets starting at `offset` do not belong to any PSSH box and were skipped.
+    UnexpectedData { offset: u64, length: u64 },
+    /// The `value` of `limit` for the input at `offset` exceeds the `max` configured in
+    /// [`crate::ParseLimits`].
+    LimitExceeded { offset: u64, limit: &'static str, value: u64, max: u64 },
+    /// While serializing, the value `length` of `field` exceeds the `max` allowed by the binary
+    /// format.
+    LengthOverflow { field: &'static str, length: u64, max: u64 },
+    /// While serializing, the DRM-specific PSSH data for `system` could not be encoded.
+    Encode { system: DRMSystemId, source: BoxedError },
+    /// While serializing, writing the output failed.
+    Io(io::Error),
+    /// Converting a box for `system` to version 0 would lose the header `key_ids`, which are not
+    /// present in its PSSH data.
+    KeyIdsNotInPayload { system: DRMSystemId, key_ids: Vec<DRMKeyId> },
+    /// The modification `operation` can't be applied to the PSSH data of a box for `system`, for
+    /// the given `reason`.
+    UnsupportedOperation { system: DRMSystemId, operation: &'static str, reason: &'static str },
+    /// `key_id` was supplied for an operation on the PSSH data of a box for `system`, but is not
+    /// present in that PSSH data.
+    KeyIdNotFound { system: DRMSystemId, key_id: DRMKeyId },
+}
+
+impl PsshError {
+    /// The byte offset at which the error was detected, if the error relates to a position in the
+    /// input.
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            PsshError::Truncated { offset, .. } |
+            PsshError::BadBoxType { offset, .. } |
+            PsshError::BadBoxSize { offset, .. } |
+            PsshError::BoxSizeMismatch { offset, .. } |
+            PsshError::UnsupportedVersion { offset, .. } |
+            PsshError::PayloadDecode { offset, .. } |
+            PsshError::InvalidEncoding { offset, .. } |
+            PsshError::InvalidLength { offset, .. } |
+            PsshError::UnexpectedData { offset, .. } |
+            PsshError::LimitExceeded { offset, .. } => Some(*offset),
+            PsshError::LengthOverflow { .. } |
+            PsshError::Encode { .. } |
+            PsshError::Io(_) |
+            PsshError::KeyIdsNotInPayload { .. } |
+            PsshError::UnsupportedOperation { .. } |
+            PsshError::KeyIdNotFound { .. } => None,
+        }
+    }
+
+    /// Shift the offset of this error by `base` octets, for errors detected in a sub-slice of the
+    /// input.
+    pub(crate) fn rebase(mut self, base: u64) -> PsshError {
+        match &mut self {
+            PsshError::Truncated { offset, .. } |
+            PsshError::BadBoxType { offset, .. } |
+            PsshError::BadBoxSize { offset, .. } |
+            PsshError::BoxSizeMismatch { offset, .. } |
+            PsshError::UnsupportedVersion { offset, .. } |
+            PsshError::PayloadDecode { offset, .. } |
+            PsshError::InvalidEncoding { offset, .. } |
+            PsshError::InvalidLength { offset, .. } |
+            PsshError::UnexpectedData { offset, .. } |
+            PsshError::LimitExceeded { offset, .. } => *offset += base,
+            PsshError::LengthOverflow { .. } |
+            PsshError::Encode { .. } |
+            PsshError::Io(_) |
+            PsshError::KeyIdsNotInPayload { .. } |
+            PsshError::UnsupportedOperation { .. } |
+            PsshError::KeyIdNotFound { .. } => (),
+        }
+        self
+    }
+
+    pub(crate) fn payload(system: DRMSystemId, offset: u64, source: impl Into<BoxedError>) -> PsshError {
+        PsshError::PayloadDecode { offset, system, source: source.into() }
+    }
+}
+
+impl fmt::Display for PsshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PsshError::Truncated { offset, needed } =>
+                write!(f, "truncated input: {needed} octets needed at offset {offset}"),
+            PsshError::BadBoxType { offset, found } =>
+                write!(f, "expecting BMFF header 'pssh' at offset {offset}, found {:?}",
+                       String::from_utf8_lossy(found)),
+            PsshError::BadBoxSize { offset, size } =>
+                write!(f, "invalid box size {size} at offset {offset}"),
+            PsshError::BoxSizeMismatch { offset, declared, consumed } =>
+                write!(f, "box at offset {offset} declares size {declared} but its contents occupy {consumed} octets"),
+            PsshError::UnsupportedVersion { offset, version } =>
+                write!(f, "unknown PSSH version {version} at offset {offset}"),
+            PsshError::PayloadDecode { offset, system, source } =>
+                write!(f, "decoding PSSH data for {system} at offset {offset}: {source}"),
+            PsshError::InvalidEncoding { offset, source } =>
+                write!(f, "invalid encoding at offset {offset}: {source}"),
+            PsshError::InvalidLength { offset, expected, found } =>
+                write!(f, "invalid length at offset {offset}: expected {expected}, found {found}"),
+            PsshError::UnexpectedData { offset, length } =>
+                write!(f, "skipped {length} octets of non-PSSH data at offset {offset}"),
+            PsshError::LimitExceeded { offset, limit, value, max } =>
+                write!(f, "{limit} {value} at offset {offset} exceeds the configured maximum of {max}"),
+            PsshError::LengthOverflow { field, length, max } =>
+                write!(f, "{field} {length} exceeds maximum of {max}"),
+            PsshError::Encode { system, source } =>
+                write!(f, "encoding PSSH data for {system}: {source}"),
+            PsshError::Io(e) => write!(f, "writing PSSH data: {e}"),
+            PsshError::KeyIdsNotInPayload { system, key_ids } => {
+                let kids: Vec<String> = key_ids.iter().map(DRMKeyId::to_uuid_string).collect();
+                write!(f, "can't convert {system} box to version 0: key IDs [{}] are not present in the PSSH data",
+                       kids.join(", "))
+            },
+            PsshError::UnsupportedOperation { system, operation, reason } =>
+                write!(f, "can't {operation} in {system} box: {reason}"),
+            PsshError::KeyIdNotFound { system, key_id } =>
+                write!(f, "key ID {} is not present in the {system} PSSH data", key_id.to_uuid_string()),
+        }
+    }
+}
+
+impl From<io::Error> for PsshError {
+    fn from(e: io::Error) -> Self {
+        PsshError::Io(e)
+    }
+}
+
+impl StdError for PsshError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            PsshError::PayloadDecode { source, .. } |
+            PsshError::InvalidEncoding { source, .. } |
+            PsshError::Encode { source, .. } => Some(source.as_ref()),
+            PsshError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+
+/// How serious a problem reported by [`crate::parse_lenient`] or by validation is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// For `parse_lenient`, some octets were ignored but no PSSH box was lost. For validation, the
+    /// content is suspicious or insecure but usable.
+    Warning,
+    /// For `parse_lenient`, a PSSH box could not be parsed and is missing from the results. For
+    /// validation, the content is expected to prevent playback on some devices.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem encountered by [`crate::parse_lenient`], which continued parsing after it.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub error: PsshError,
+}
+
+impl Diagnostic {
+    /// The byte offset in the input at which the problem was detected.
+    pub fn offset(&self) -> Option<u64> {
+        self.error.offset()
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.error)
+    }
+}
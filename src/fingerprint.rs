@@ -0,0 +1,70 @@
+//! Semantic comparison and fingerprinting of PSSH boxes, which ignore differences in encoding.
+//!
+//! Two boxes are semantically equal when they are for the same DRM system, have the same flags,
+//! reference the same set of key IDs (whether in the v1 box header or in the PSSH data) and have the
+//! same PSSH data once decoded. XML attribute order and whitespace in PlayReady headers, the order
+//! of fields in Widevine protobuf data, the box version and the order of key IDs in the header are
+//! not significant.
+
+use sha2::{Digest, Sha256};
+use crate::{PsshBox, PsshBoxVec, DRMKeyId, Result};
+
+
+impl PsshBox {
+    /// The key IDs referenced by this box, sorted and without duplicates.
+    fn canonical_key_ids(&self) -> Vec<DRMKeyId> {
+        let mut kids: Vec<DRMKeyId> = self.all_key_ids().into_iter().map(|t| t.key_id).collect();
+        kids.sort();
+        kids
+    }
+
+    // The system ID, flags, sorted key IDs and PSSH data re-encoded by the DRM-specific encoder.
+    fn canonical_form(&self) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&self.system_id.id);
+        out.extend_from_slice(&self.flags.to_be_bytes());
+        let kids = self.canonical_key_ids();
+        out.extend_from_slice(&(kids.len() as u64).to_be_bytes());
+        for kid in &kids {
+            out.extend_from_slice(kid.as_bytes());
+        }
+        out.extend_from_slice(&self.encode_pssh_data()?);
+        Ok(out)
+    }
+
+    /// Whether this box and `other` have the same meaning, ignoring differences in encoding (see
+    /// the [module documentation](crate::fingerprint)).
+    pub fn semantically_eq(&self, other: &PsshBox) -> bool {
+        match (self.canonical_form(), other.canonical_form()) {
+            (Ok(a), Ok(b)) => a == b,
+            // PSSH data which can't be encoded is compared by value.
+            _ => self.system_id == other.system_id &&
+                self.flags == other.flags &&
+                self.canonical_key_ids() == other.canonical_key_ids() &&
+                self.pssh_data == other.pssh_data,
+        }
+    }
+
+    /// A SHA-256 digest of the canonical form of this box, which is equal for boxes which are
+    /// [`semantically_eq`](PsshBox::semantically_eq). Fails if the PSSH data can't be encoded.
+    pub fn fingerprint(&self) -> Result<[u8; 32]> {
+        Ok(Sha256::digest(self.canonical_form()?).into())
+    }
+}
+
+impl PsshBoxVec {
+    /// A SHA-256 digest of the fingerprints of these boxes, which doesn't depend on the order of the
+    /// boxes or on duplicated boxes.
+    pub fn fingerprint(&self) -> Result<[u8; 32]> {
+        let mut fingerprints = self.iter()
+            .map(PsshBox::fingerprint)
+            .collect::<Result<Vec<_>>>()?;
+        fingerprints.sort();
+        fingerprints.dedup();
+        let mut hasher = Sha256::new();
+        for fp in &fingerprints {
+            hasher.update(fp);
+        }
+        Ok(hasher.finalize().into())
+    }
+}
@@ -5,8 +5,8 @@ use std::fmt;
 use std::io::{Read, Cursor};
 use byteorder::{LittleEndian, ReadBytesExt};
 use serde::{Serialize, Deserialize};
-use anyhow::Result;
-use crate::ToBytes;
+use crate::{ToBytes, IRDETO_SYSTEM_ID};
+use crate::error::{PsshError, Result};
 
 
 #[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -21,20 +21,24 @@ impl fmt::Debug for IrdetoPsshData {
 }
 
 impl ToBytes for IrdetoPsshData {
-    fn to_bytes(&self) -> Vec<u8> {
-        self.xml.clone().into_bytes()
+    fn write_to<W: std::io::Write>(&self, out: &mut W) -> Result<()> {
+        Ok(out.write_all(self.xml.as_bytes())?)
     }
 }
 
 
 pub fn parse_pssh_data(buf: &[u8]) -> Result<IrdetoPsshData> {
     let mut rdr = Cursor::new(buf);
-    let _ignore1 = rdr.read_u32::<LittleEndian>()?;
-    let _ignore2 = rdr.read_u32::<LittleEndian>()?;
-    let _ignore3 = rdr.read_u8()?;
+    let _ignore1 = rdr.read_u32::<LittleEndian>()
+        .map_err(|e| PsshError::payload(IRDETO_SYSTEM_ID, 0, e))?;
+    let _ignore2 = rdr.read_u32::<LittleEndian>()
+        .map_err(|e| PsshError::payload(IRDETO_SYSTEM_ID, 4, e))?;
+    let _ignore3 = rdr.read_u8()
+        .map_err(|e| PsshError::payload(IRDETO_SYSTEM_ID, 8, e))?;
     let mut utf8buf = Vec::new();
-    let xmllen = (buf.len() - 9) as u64;
-    rdr.take(xmllen).read_to_end(&mut utf8buf)?;
-    let xml = String::from_utf8(utf8buf)?;
+    rdr.read_to_end(&mut utf8buf)
+        .map_err(|e| PsshError::payload(IRDETO_SYSTEM_ID, 9, e))?;
+    let xml = String::from_utf8(utf8buf)
+        .map_err(|e| PsshError::payload(IRDETO_SYSTEM_ID, 9 + e.utf8_error().valid_up_to() as u64, e))?;
     Ok(IrdetoPsshData { xml })
 }
@@ -24,6 +24,9 @@
 //! - FairPlay (the unofficial version used by Netflix)
 //! - Common Encryption
 //!
+//! PSSH boxes for other DRM systems (ClearKey, Adobe Primetime, VisionCrypt and so on) are also
+//! parsed, with their PSSH data retained as raw octets in `PsshData::Unknown`.
+//!
 //! PSSH boxes contain (depending on the DRM system) information on the key_ID for which to obtain a
 //! content key, the encryption scheme used (e.g. cenc, cbc1, cens or cbcs), the URL of the licence
 //! server, and checksum data.
@@ -34,23 +37,38 @@ pub mod widevine;
 pub mod irdeto;
 pub mod nagra;
 pub mod wiseplay;
+pub mod error;
+pub mod codec;
+pub mod systems;
+pub mod serde_uuid;
+pub mod validate;
+pub mod fingerprint;
+pub mod diff;
+pub mod edit;
 
 use std::fmt;
+use std::str::FromStr;
+use std::collections::BTreeMap;
 use std::io::{self, Cursor, Read, Write};
+use std::sync::Arc;
 use hex_literal::hex;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use zerocopy::FromBytes;
+use zerocopy::{FromBytes, Immutable, KnownLayout};
 use serde::{Serialize, Deserialize};
-use prost::Message;
 use base64::prelude::{Engine as _, BASE64_STANDARD};
 use base64::engine;
-use anyhow::{Result, Context, anyhow};
 use tracing::trace;
 use crate::widevine::WidevinePsshData;
 use crate::playready::PlayReadyPsshData;
 use crate::irdeto::IrdetoPsshData;
 use crate::nagra::NagraPsshData;
 use crate::wiseplay::WisePlayPsshData;
+pub use crate::error::{PsshError, Result, Diagnostic, Severity};
+pub use crate::codec::{CodecRegistry, CustomPsshData, DrmPayloadCodec, register_codec};
+pub use crate::systems::DrmSystem;
+pub use crate::validate::{Finding, Lint};
+pub use crate::diff::{Change, PsshDiff};
+pub use crate::edit::KeyIdReplacement;
 
 
 /// The version of this crate.
@@ -58,8 +76,113 @@ pub fn version() -> &'static str {
     env!("CARGO_PKG_VERSION")
 }
 
+// Convert an error from the hex crate, keeping the position of the invalid character.
+fn hex_error(e: hex::FromHexError) -> PsshError {
+    let offset = match e {
+        hex::FromHexError::InvalidHexCharacter { index, .. } => index as u64,
+        _ => 0,
+    };
+    PsshError::InvalidEncoding { offset, source: Box::new(e) }
+}
+
+// Convert an error from the base64 crate, keeping the position of the invalid character.
+fn base64_error(e: base64::DecodeError) -> PsshError {
+    let offset = match e {
+        base64::DecodeError::InvalidByte(index, _) |
+        base64::DecodeError::InvalidLastSymbol(index, _) => index as u64,
+        _ => 0,
+    };
+    PsshError::InvalidEncoding { offset, source: Box::new(e) }
+}
+
+// Accepts base64 with either the standard or the URL-safe alphabet, padded or not.
+const BASE64_FORGIVING: engine::GeneralPurpose = engine::GeneralPurpose::new(
+    &base64::alphabet::STANDARD,
+    engine::GeneralPurposeConfig::new()
+        .with_decode_padding_mode(engine::DecodePaddingMode::Indifferent));
+const BASE64_URL_FORGIVING: engine::GeneralPurpose = engine::GeneralPurpose::new(
+    &base64::alphabet::URL_SAFE,
+    engine::GeneralPurposeConfig::new()
+        .with_decode_padding_mode(engine::DecodePaddingMode::Indifferent));
+
+// Parse a 16-octet identifier from 32 hex digits, a UUID with dashes, a urn:uuid: URN (ignoring
+// case) or base64. Error offsets are positions in s.
+fn parse_uuid(s: &str) -> Result<[u8; 16]> {
+    let (offset, uuid) = match s.get(..9) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("urn:uuid:") => (9, &s[9..]),
+        _ => (0, s),
+    };
+    match uuid.len() {
+        32 | 36 => decode_uuid_hex(uuid).map_err(|e| e.rebase(offset)),
+        22 | 24 if offset == 0 => {
+            let octets = BASE64_FORGIVING.decode(uuid)
+                .or_else(|_| BASE64_URL_FORGIVING.decode(uuid))
+                .map_err(base64_error)?;
+            octets.as_slice().try_into()
+                .map_err(|_| PsshError::InvalidLength { offset: 0, expected: 16, found: octets.len() })
+        },
+        _ => Err(PsshError::InvalidLength { offset: 0, expected: 32, found: s.len() }),
+    }
+}
+
+// Lowercase hex with dashes, like edef8ba9-79d6-4ace-a3c8-27dcd51d21ed.
+fn uuid_string(id: &[u8; 16]) -> String {
+    let hex = hex::encode(id);
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+// Convert between the big-endian byte order of a UUID and the Microsoft GUID byte order, in which
+// the first three fields are little endian. The conversion is its own inverse.
+fn swap_guid_bytes(mut id: [u8; 16]) -> [u8; 16] {
+    id[0..4].reverse();
+    id[4..6].reverse();
+    id[6..8].reverse();
+    id
+}
+
+// Decode 32 hex digits, which may be separated by dashes as in a UUID (8-4-4-4-12).
+fn decode_uuid_hex(s: &str) -> Result<[u8; 16]> {
+    let dashed = s.len() == 36;
+    let positions: Vec<usize> = (0..s.len())
+        .filter(|i| !(dashed && matches!(i, 8 | 13 | 18 | 23)))
+        .collect();
+    if dashed && [8, 13, 18, 23].iter().any(|i| s.as_bytes()[*i] != b'-') {
+        return Err(PsshError::InvalidLength { offset: 0, expected: 32, found: s.len() });
+    }
+    let digits: Vec<u8> = positions.iter().map(|i| s.as_bytes()[*i]).collect();
+    let mut id = [0u8; 16];
+    hex::decode_to_slice(digits, &mut id)
+        .map_err(|e| match e {
+            hex::FromHexError::InvalidHexCharacter { index, .. } =>
+                PsshError::InvalidEncoding { offset: positions[index] as u64, source: Box::new(e) },
+            _ => hex_error(e),
+        })?;
+    Ok(id)
+}
+
+/// Serialization to the binary format used in PSSH boxes.
 pub trait ToBytes {
-    fn to_bytes(&self) -> Vec<u8>;
+    /// Write the serialized octets to `out`. Returns an error if writing fails, if a length
+    /// doesn't fit in the field of the binary format which holds it, or if DRM-specific data (such
+    /// as PlayReady XML) can't be encoded.
+    fn write_to<W: Write>(&self, out: &mut W) -> Result<()>;
+
+    /// Return the serialized octets, or an error as for [`ToBytes::write_to`].
+    fn try_to_bytes(&self) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        self.write_to(&mut out)?;
+        Ok(out)
+    }
+
+    /// Return the serialized octets.
+    ///
+    /// # Panics
+    ///
+    /// Panics if serialization fails; see [`ToBytes::try_to_bytes`] for a fallible alternative.
+    fn to_bytes(&self) -> Vec<u8> {
+        self.try_to_bytes()
+            .unwrap_or_else(|e| panic!("serializing PSSH data: {e}"))
+    }
 }
 
 /// Data in a PSSH box whose format is dependent on the DRM system used.
@@ -75,20 +198,33 @@ pub enum PsshData {
     CommonEnc(Vec<u8>),
     FairPlay(Vec<u8>),
     Mobi(Vec<u8>),
+    /// PSSH data for a DRM system whose format we don't know how to decode, kept verbatim.
+    Unknown(Vec<u8>),
+    /// PSSH data decoded by a [`DrmPayloadCodec`] registered by the caller.
+    Custom(CustomPsshData),
 }
 
 impl ToBytes for PsshData {
-    fn to_bytes(&self) -> Vec<u8> {
+    fn write_to<W: Write>(&self, out: &mut W) -> Result<()> {
         match self {
-            PsshData::Widevine(wv) => wv.to_bytes(),
-            PsshData::PlayReady(pr) => pr.to_bytes(),
-            PsshData::Irdeto(ir) => ir.to_bytes(),
-            PsshData::WisePlay(c) => c.to_bytes(),
-            PsshData::Nagra(n) => n.to_bytes(),
-            PsshData::Marlin(m) => m.to_vec(),
-            PsshData::CommonEnc(c) => c.to_vec(),
-            PsshData::FairPlay(c) => c.to_vec(),
-            PsshData::Mobi(c) => c.to_vec(),
+            PsshData::Widevine(wv) => wv.write_to(out),
+            PsshData::PlayReady(pr) => pr.write_to(out),
+            PsshData::Irdeto(ir) => ir.write_to(out),
+            PsshData::WisePlay(c) => c.write_to(out),
+            PsshData::Nagra(n) => n.write_to(out),
+            PsshData::Marlin(c) |
+            PsshData::CommonEnc(c) |
+            PsshData::FairPlay(c) |
+            PsshData::Mobi(c) |
+            PsshData::Unknown(c) => Ok(out.write_all(c)?),
+            PsshData::Custom(pd) => {
+                let codec = codec::codec_for(pd.system_id)
+                    .ok_or_else(|| PsshError::Encode {
+                        system: pd.system_id,
+                        source: "no codec registered for this DRM system".into(),
+                    })?;
+                Ok(out.write_all(&codec.encode(self)?)?)
+            },
         }
     }
 }
@@ -148,120 +284,102 @@ impl fmt::Display for PsshData {
             PsshData::CommonEnc(pd) => write!(f, "CommonPSSHData<len {} octets>", pd.len()),
             PsshData::FairPlay(pd) => write!(f, "FairPlayPSSHData<len {} octets>", pd.len()),
             PsshData::Mobi(pd) => write!(f, "MobiPSSHData<len {} octets>", pd.len()),
+            PsshData::Unknown(pd) => write!(f, "UnknownPSSHData<len {} octets>", pd.len()),
+            PsshData::Custom(pd) => match codec::codec_for(pd.system_id) {
+                Some(codec) => codec.display(self, f),
+                None => write!(f, "CustomPSSHData<{}>", pd.value),
+            },
         }
     }
 }
 
 /// The identifier for a DRM system.
-#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, FromBytes)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, FromBytes)]
 pub struct DRMSystemId {
     id: [u8; 16],
 }
 
 impl TryFrom<&[u8]> for DRMSystemId {
-    type Error = ();
+    type Error = PsshError;
 
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
         if let Ok(id) = value.try_into() {
             Ok(DRMSystemId { id })
         } else {
-            Err(())
+            Err(PsshError::InvalidLength { offset: 0, expected: 16, found: value.len() })
         }
     }
 }
 
 impl TryFrom<Vec<u8>> for DRMSystemId {
-    type Error = ();
+    type Error = PsshError;
 
     fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
-        if value.len() == 16 {
-            DRMSystemId::try_from(&value[0..16])
-        } else {
-            Err(())
-        }
+        DRMSystemId::try_from(value.as_slice())
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl From<uuid::Uuid> for DRMSystemId {
+    fn from(uuid: uuid::Uuid) -> Self {
+        DRMSystemId { id: uuid.into_bytes() }
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl From<DRMSystemId> for uuid::Uuid {
+    fn from(system_id: DRMSystemId) -> Self {
+        uuid::Uuid::from_bytes(system_id.id)
     }
 }
 
 impl TryFrom<&str> for DRMSystemId {
-    type Error = ();
+    type Error = PsshError;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        if value.len() == 32 {
-            if let Ok(id) = hex::decode(value) {
-                return DRMSystemId::try_from(id);
-            }
-        }
-        Err(())
+        value.parse()
+    }
+}
+
+/// Parses 32 hex digits, a UUID with dashes, a `urn:uuid:` URN as used in DASH manifests (ignoring
+/// case), or the 16 octets of the system ID encoded in base64 (standard or URL-safe alphabet, with or
+/// without padding).
+impl FromStr for DRMSystemId {
+    type Err = PsshError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(DRMSystemId { id: parse_uuid(s)? })
     }
 }
 
 impl ToBytes for DRMSystemId {
-    fn to_bytes(&self) -> Vec<u8> {
-        self.id.into()
+    fn write_to<W: Write>(&self, out: &mut W) -> Result<()> {
+        Ok(out.write_all(&self.id)?)
+    }
+}
+
+impl DRMSystemId {
+    /// Information on the DRM system identified by this system ID, if it is known.
+    pub fn drm_system(&self) -> Option<&'static DrmSystem> {
+        systems::from_system_id(*self)
+    }
+
+    /// The URN used to identify this DRM system in DASH manifests, like
+    /// `urn:uuid:edef8ba9-79d6-4ace-a3c8-27dcd51d21ed`.
+    pub fn to_urn(&self) -> String {
+        format!("urn:uuid:{}", self.to_uuid_string())
+    }
+
+    /// The system ID as a lowercase UUID, like `edef8ba9-79d6-4ace-a3c8-27dcd51d21ed`.
+    pub fn to_uuid_string(&self) -> String {
+        uuid_string(&self.id)
     }
 }
 
 impl fmt::Display for DRMSystemId {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        // See list at https://dashif.org/identifiers/content_protection/
-        let family = if self.id == hex!("1077efecc0b24d02ace33c1e52e2fb4b") {
-            "Common"
-        } else if self.id == hex!("6770616363656e6364726d746f6f6c31") {
-            // See https://github.com/gpac/testsuite/blob/b1c1f23079431221b582f3c7674706c6b6044cf2/media/encryption/tpl_roll.xml#L6
-            "GPAC"
-        } else if self.id == hex!("edef8ba979d64acea3c827dcd51d21ed") {
-            "Widevine"
-        } else if self.id == hex!("9a04f07998404286ab92e65be0885f95") {
-            "PlayReady"
-        } else if self.id == hex!("6dd8b3c345f44a68bf3a64168d01a4a6") {
-            "ABV"
-        } else if self.id == hex!("f239e769efa348509c16a903c6932efb") {
-            "Adobe Primetime"
-        } else if self.id == hex!("616c7469636173742d50726f74656374") {
-            "Alticast"
-        } else if self.id == hex!("94ce86fb07ff4f43adb893d2fa968ca2") {
-            "Apple FairPlay"
-        } else if self.id == hex!("29701fe43cc74a348c5bae90c7439a47") {
-            // Unofficial FairPlay systemID used by Netflix for DASH streaming,
-            // see https://forums.developer.apple.com/thread/6185
-            "Apple FairPlay-Netflix variant"
-        } else if self.id == hex!("3ea8778f77424bf9b18be834b2acbd47") {
-            "ClearKey AES-128"
-        } else if self.id == hex!("be58615b19c4468488b3c8c57e99e957") {
-            "ClearKey SAMPLE-AES"
-        } else if self.id == hex!("e2719d58a985b3c9781ab030af78d30e") {
-            "ClearKey DASH-IF"
-        } else if self.id == hex!("45d481cb8fe049c0ada9ab2d2455b2f2") {
-            "CoreTrust"
-        } else if self.id == hex!("80a6be7e14484c379e70d5aebe04c8d2") {
-            "Irdeto"
-        } else if self.id == hex!("5e629af538da4063897797ffbd9902d4") {
-            // In fact this is the urn:uuid:<uuid> code used for Marlin, which is not the same as
-            // the Marlin SystemID (whereas for most other DRM systems, the urn:uuid code is the
-            // same as the system ID). However, it seems that there is some confusion in some PSSH
-            // boxes used in practice, so we recognize this system ID as being Marlin.
-            "Marlin"
-        } else if self.id == hex!("69f908af481646ea910ccd5dcccb0a3a") {
-            "Marlin"
-        } else if self.id == hex!("adb41c242dbf4a6d958b4457c0d27b95") {
-            "Nagra"
-        } else if self.id == hex!("1f83e1e86ee94f0dba2f5ec4e3ed1a66") {
-            "SecureMedia"
-        } else if self.id == hex!("3d5e6d359b9a41e8b843dd3c6e72c42c") {
-            // WisePlay (from Huawei) and ChinaDRM are apparently different DRM systems that are
-            // identified by the same system id.
-            "WisePlay-ChinaDRM"
-        } else if self.id == hex!("793b79569f944946a94223e7ef7e44b4") {
-            "VisionCrypt"
-        } else if self.id == hex!("6a99532d869f59229a91113ab7b1e2f3") {
-            "MobiDRM"
-        } else {
-            "Unknown"
-        };
-        let hex = hex::encode(self.id);
-        write!(f, "{}/DRMSystemId<{}-{}-{}-{}-{}>",
-               family,
-               &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+        let family = self.drm_system().map_or("Unknown", |s| s.name);
+        write!(f, "{family}/DRMSystemId<{}>", self.to_uuid_string())
     }
 }
 
@@ -280,76 +398,199 @@ pub const MARLIN_SYSTEM_ID: DRMSystemId = DRMSystemId { id: hex!("69f908af481646
 pub const NAGRA_SYSTEM_ID: DRMSystemId = DRMSystemId { id: hex!("adb41c242dbf4a6d958b4457c0d27b95") };
 pub const WISEPLAY_SYSTEM_ID: DRMSystemId = DRMSystemId { id: hex!("3d5e6d359b9a41e8b843dd3c6e72c42c") };
 pub const MOBI_SYSTEM_ID: DRMSystemId = DRMSystemId { id: hex!("6a99532d869f59229a91113ab7b1e2f3") };
+pub const GPAC_SYSTEM_ID: DRMSystemId = DRMSystemId { id: hex!("6770616363656e6364726d746f6f6c31") };
+pub const ABV_SYSTEM_ID: DRMSystemId = DRMSystemId { id: hex!("6dd8b3c345f44a68bf3a64168d01a4a6") };
+pub const ADOBE_PRIMETIME_SYSTEM_ID: DRMSystemId = DRMSystemId { id: hex!("f239e769efa348509c16a903c6932efb") };
+pub const ALTICAST_SYSTEM_ID: DRMSystemId = DRMSystemId { id: hex!("616c7469636173742d50726f74656374") };
+pub const FAIRPLAY_SYSTEM_ID: DRMSystemId = DRMSystemId { id: hex!("94ce86fb07ff4f43adb893d2fa968ca2") };
+pub const CLEARKEY_AES128_SYSTEM_ID: DRMSystemId = DRMSystemId { id: hex!("3ea8778f77424bf9b18be834b2acbd47") };
+pub const CLEARKEY_SAMPLEAES_SYSTEM_ID: DRMSystemId = DRMSystemId { id: hex!("be58615b19c4468488b3c8c57e99e957") };
+pub const CLEARKEY_DASHIF_SYSTEM_ID: DRMSystemId = DRMSystemId { id: hex!("e2719d58a985b3c9781ab030af78d30e") };
+pub const CORETRUST_SYSTEM_ID: DRMSystemId = DRMSystemId { id: hex!("45d481cb8fe049c0ada9ab2d2455b2f2") };
+pub const SECUREMEDIA_SYSTEM_ID: DRMSystemId = DRMSystemId { id: hex!("1f83e1e86ee94f0dba2f5ec4e3ed1a66") };
+pub const VISIONCRYPT_SYSTEM_ID: DRMSystemId = DRMSystemId { id: hex!("793b79569f944946a94223e7ef7e44b4") };
 
 /// The Content Key or default_KID.
-#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, FromBytes)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, FromBytes, KnownLayout, Immutable)]
 pub struct DRMKeyId {
     id: [u8; 16],
 }
 
 impl TryFrom<&[u8]> for DRMKeyId {
-    type Error = ();
+    type Error = PsshError;
 
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
         if let Ok(id) = value.try_into() {
             Ok(DRMKeyId { id })
         } else {
-            Err(())
+            Err(PsshError::InvalidLength { offset: 0, expected: 16, found: value.len() })
         }
     }
 }
 
 impl TryFrom<Vec<u8>> for DRMKeyId {
-    type Error = ();
+    type Error = PsshError;
 
     fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
-        if value.len() == 16 {
-            DRMKeyId::try_from(&value[0..16])
-        } else {
-            Err(())
-        }
+        DRMKeyId::try_from(value.as_slice())
+    }
+}
+
+impl From<[u8; 16]> for DRMKeyId {
+    fn from(id: [u8; 16]) -> Self {
+        DRMKeyId { id }
+    }
+}
+
+impl From<DRMKeyId> for [u8; 16] {
+    fn from(kid: DRMKeyId) -> Self {
+        kid.id
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl From<uuid::Uuid> for DRMKeyId {
+    fn from(uuid: uuid::Uuid) -> Self {
+        DRMKeyId { id: uuid.into_bytes() }
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl From<DRMKeyId> for uuid::Uuid {
+    fn from(kid: DRMKeyId) -> Self {
+        uuid::Uuid::from_bytes(kid.id)
     }
 }
 
 impl TryFrom<&str> for DRMKeyId {
-    type Error = ();
+    type Error = PsshError;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        if value.len() == 32 {
-            if let Ok(id) = hex::decode(value) {
-                return DRMKeyId::try_from(id);
-            }
+        value.parse()
+    }
+}
+
+/// Parses 32 hex digits, a UUID with dashes (as in a DASH `cenc:default_KID` attribute), a Windows
+/// GUID in braces, or a `urn:uuid:` URN. Base64 is not accepted, because its byte order is ambiguous;
+/// see [`DRMKeyId::from_base64url`] and [`DRMKeyId::from_playready_base64`].
+impl FromStr for DRMKeyId {
+    type Err = PsshError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with('{') {
+            return DRMKeyId::from_guid_str(s);
         }
-        // UUID-style format, like 5ade6a1e-c0d4-43c6-92f2-2d36862ba8dd
-        if value.len() == 36 {
-            let v36 = value.as_bytes();
-            if v36[8] == b'-' &&
-                v36[13] == b'-' &&
-                v36[18] == b'-' &&
-                v36[23] == b'-'
-            {
-                let maybe_hex = value.replace('-', "");
-                if let Ok(id) = hex::decode(maybe_hex) {
-                    return DRMKeyId::try_from(id);
-                }
-            }
+        let (offset, uuid) = match s.get(..9) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("urn:uuid:") => (9, &s[9..]),
+            _ => (0, s),
+        };
+        match uuid.len() {
+            32 | 36 => Ok(DRMKeyId { id: decode_uuid_hex(uuid).map_err(|e| e.rebase(offset))? }),
+            _ => Err(PsshError::InvalidLength { offset: 0, expected: 32, found: s.len() }),
         }
-        Err(())
+    }
+}
+
+impl DRMKeyId {
+    /// The 16 octets of the key ID, in the big-endian order used in PSSH box headers.
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        &self.id
+    }
+
+    /// Parse 32 hex digits.
+    pub fn from_hex(s: &str) -> Result<DRMKeyId> {
+        if s.len() != 32 {
+            return Err(PsshError::InvalidLength { offset: 0, expected: 32, found: s.len() });
+        }
+        Ok(DRMKeyId { id: decode_uuid_hex(s)? })
+    }
+
+    /// The key ID as 32 lowercase hex digits.
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.id)
+    }
+
+    /// Parse a UUID with dashes, like `72c3ed2c-7a5f-4aad-902f-cbef1efe89a9` in a DASH
+    /// `cenc:default_KID` attribute.
+    pub fn from_uuid_str(s: &str) -> Result<DRMKeyId> {
+        if s.len() != 36 {
+            return Err(PsshError::InvalidLength { offset: 0, expected: 36, found: s.len() });
+        }
+        Ok(DRMKeyId { id: decode_uuid_hex(s)? })
+    }
+
+    /// The key ID as a lowercase UUID with dashes, like `72c3ed2c-7a5f-4aad-902f-cbef1efe89a9`.
+    pub fn to_uuid_string(&self) -> String {
+        uuid_string(&self.id)
+    }
+
+    /// Parse a Windows GUID in braces, like `{72C3ED2C-7A5F-4AAD-902F-CBEF1EFE89A9}`. The text of a GUID
+    /// is read in the same (big-endian) order as a UUID.
+    pub fn from_guid_str(s: &str) -> Result<DRMKeyId> {
+        let inner = s.strip_prefix('{')
+            .and_then(|s| s.strip_suffix('}'))
+            .ok_or_else(|| PsshError::InvalidEncoding { offset: 0, source: "expecting a GUID in braces".into() })?;
+        DRMKeyId::from_uuid_str(inner)
+            .map_err(|e| e.rebase(1))
+    }
+
+    /// The key ID as an uppercase Windows GUID in braces, like
+    /// `{72C3ED2C-7A5F-4AAD-902F-CBEF1EFE89A9}`.
+    pub fn to_guid_string(&self) -> String {
+        format!("{{{}}}", self.to_uuid_string().to_uppercase())
+    }
+
+    /// Convert from the in-memory byte order of a Microsoft GUID, whose first three fields are little
+    /// endian, as used in PlayReady headers.
+    pub fn from_guid_bytes(octets: [u8; 16]) -> DRMKeyId {
+        DRMKeyId { id: swap_guid_bytes(octets) }
+    }
+
+    /// The key ID in the in-memory byte order of a Microsoft GUID, as used in PlayReady headers.
+    pub fn to_guid_bytes(&self) -> [u8; 16] {
+        swap_guid_bytes(self.id)
+    }
+
+    /// Parse URL-safe base64, with or without padding, as used in the `kids` of EME `keyids`
+    /// initialization data.
+    pub fn from_base64url(s: &str) -> Result<DRMKeyId> {
+        let octets = BASE64_URL_FORGIVING.decode(s)
+            .map_err(base64_error)?;
+        DRMKeyId::try_from(octets)
+    }
+
+    /// The key ID as URL-safe base64 without padding, as used in EME `keyids` initialization data.
+    pub fn to_base64url(&self) -> String {
+        engine::general_purpose::URL_SAFE_NO_PAD.encode(self.id)
+    }
+
+    /// Parse the standard base64 encoding of a GUID in Microsoft byte order, as found in the `KID`
+    /// elements and attributes of a PlayReady header.
+    pub fn from_playready_base64(s: &str) -> Result<DRMKeyId> {
+        let octets = BASE64_FORGIVING.decode(s)
+            .map_err(base64_error)?;
+        let octets: [u8; 16] = octets.as_slice().try_into()
+            .map_err(|_| PsshError::InvalidLength { offset: 0, expected: 16, found: octets.len() })?;
+        Ok(DRMKeyId::from_guid_bytes(octets))
+    }
+
+    /// The key ID in the format used in a PlayReady header: standard base64 encoding of the GUID in
+    /// Microsoft byte order.
+    pub fn to_playready_base64(&self) -> String {
+        BASE64_STANDARD.encode(self.to_guid_bytes())
     }
 }
 
 impl ToBytes for DRMKeyId {
-    fn to_bytes(&self) -> Vec<u8> {
-        self.id.into()
+    fn write_to<W: Write>(&self, out: &mut W) -> Result<()> {
+        Ok(out.write_all(&self.id)?)
     }
 }
 
 impl fmt::Display for DRMKeyId {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // example: 72c3ed2c-7a5f-4aad-902f-cbef1efe89a9
-        let hex = hex::encode(self.id);
-        write!(f, "DRMKeyId<{}-{}-{}-{}-{}>",
-               &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+        write!(f, "DRMKeyId<{}>", self.to_uuid_string())
     }
 }
 
@@ -361,47 +602,181 @@ impl fmt::Debug for DRMKeyId {
 
 
 /// A PSSH box, also called a ProtectionSystemSpecificHeaderBox in ISO 23001-7:2012.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+///
+/// A box obtained by parsing retains the original octets of its PSSH data, which are serialized
+/// verbatim by `to_bytes()` unless `pssh_data` has since been modified. This means that a box which
+/// is parsed and written back is identical to the original, even when the DRM-specific encoder would
+/// produce different octets (XML attribute order and whitespace for PlayReady, unknown protobuf
+/// fields for Widevine).
+#[derive(Clone, Serialize, Deserialize)]
 pub struct PsshBox {
     pub version: u8,
     pub flags: u32,
     pub system_id: DRMSystemId,
     pub key_ids: Vec<DRMKeyId>,
     pub pssh_data: PsshData,
+    #[serde(skip)]
+    original: Option<OriginalPsshData>,
+    // The codec which decoded the PSSH data, and the system ID it was registered for.
+    #[serde(skip)]
+    codec: Option<(DRMSystemId, Arc<dyn DrmPayloadCodec>)>,
+}
+
+// The PSSH data of a box obtained by parsing: the octets that were parsed, and the system ID and a
+// hash of the encoding of the decoded PSSH data at that time, against which modifications are
+// detected without decoding the octets again or keeping a copy of the decoded PSSH data.
+#[derive(Clone)]
+struct OriginalPsshData {
+    octets: Vec<u8>,
+    system_id: DRMSystemId,
+    encoded_hash: Option<u64>,
+}
+
+// A hash of the encoding of some PSSH data, or None if it can't be encoded.
+fn encoding_hash(encoded: &Result<Vec<u8>>) -> Option<u64> {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    encoded.as_ref().ok().map(|octets| {
+        let mut hasher = DefaultHasher::new();
+        octets.hash(&mut hasher);
+        hasher.finish()
+    })
+}
+
+// The original PSSH data is a serialization detail which is ignored when comparing boxes.
+impl PartialEq for PsshBox {
+    fn eq(&self, other: &Self) -> bool {
+        self.version == other.version &&
+            self.flags == other.flags &&
+            self.system_id == other.system_id &&
+            self.key_ids == other.key_ids &&
+            self.pssh_data == other.pssh_data
+    }
+}
+
+impl fmt::Debug for PsshBox {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PsshBox")
+            .field("version", &self.version)
+            .field("flags", &self.flags)
+            .field("system_id", &self.system_id)
+            .field("key_ids", &self.key_ids)
+            .field("pssh_data", &self.pssh_data)
+            .finish_non_exhaustive()
+    }
 }
 
 impl PsshBox {
+    /// Return a PSSH box with the given contents and flags 0. Use this instead of a struct
+    /// literal, since a `PsshBox` also has private fields.
+    pub fn new(system_id: DRMSystemId, version: u8, key_ids: Vec<DRMKeyId>, pssh_data: PsshData) -> PsshBox {
+        PsshBox {
+            version,
+            flags: 0,
+            system_id,
+            key_ids,
+            pssh_data,
+            original: None,
+            codec: None,
+        }
+    }
+
     /// Return an empty v1 Widevine PSSH box.
     pub fn new_widevine() -> PsshBox {
         let empty = WidevinePsshData {
             provider: None,
             ..Default::default()
         };
-        PsshBox {
-            version: 1,
-            flags: 0,
-            system_id: WIDEVINE_SYSTEM_ID,
-            key_ids: vec![],
-            pssh_data: PsshData::Widevine(empty),
-        }
+        PsshBox::new(WIDEVINE_SYSTEM_ID, 1, vec![], PsshData::Widevine(empty))
     }
 
     /// Return an empty v1 PlayReady PSSH box.
     pub fn new_playready() -> PsshBox {
         let empty = PlayReadyPsshData::new();
-        PsshBox {
-            version: 1,
-            flags: 0,
-            system_id: PLAYREADY_SYSTEM_ID,
-            key_ids: vec![],
-            pssh_data: PsshData::PlayReady(empty),
-        }
+        PsshBox::new(PLAYREADY_SYSTEM_ID, 1, vec![], PsshData::PlayReady(empty))
     }
 
+    /// Add `kid` to the key IDs in the box header only. See [`PsshBox::insert_key_id`] to also add
+    /// it to the DRM-specific PSSH data.
     pub fn add_key_id(&mut self, kid: DRMKeyId) {
         self.key_ids.push(kid);
     }
 
+    /// The octets of the PSSH data as they were parsed, if this box was obtained by parsing.
+    pub fn original_pssh_data(&self) -> Option<&[u8]> {
+        self.original.as_ref().map(|o| o.octets.as_slice())
+    }
+
+    /// Whether the PSSH data (or the system ID which determines how it is decoded) has been modified
+    /// since this box was parsed. This compares the encoding of the PSSH data with a hash of its
+    /// encoding taken when parsing, without decoding the original octets again. Always true for a
+    /// box which was not obtained by parsing.
+    pub fn is_modified(&self) -> bool {
+        match &self.original {
+            Some(original) => original.system_id != self.system_id ||
+                original.encoded_hash != encoding_hash(&self.encode_pssh_data()),
+            None => true,
+        }
+    }
+
+    /// The codec for the PSSH data of this box: the codec which decoded it, if this box was obtained
+    /// by parsing and its system ID is unchanged (the raw codec if there was no codec for the system
+    /// ID), and otherwise the codec registered for its system ID in the global registry. It is used to encode, display and extract key IDs from the PSSH
+    /// data.
+    pub fn codec(&self) -> Option<Arc<dyn DrmPayloadCodec>> {
+        match &self.codec {
+            Some((system_id, codec)) if *system_id == self.system_id => Some(Arc::clone(codec)),
+            _ => codec::codec_for(self.system_id),
+        }
+    }
+
+    // Encode the PSSH data with the codec for this box, or with the built-in encoder if there is
+    // none.
+    pub(crate) fn encode_pssh_data(&self) -> Result<Vec<u8>> {
+        match self.codec() {
+            Some(codec) => codec.encode(&self.pssh_data),
+            None => self.pssh_data.try_to_bytes(),
+        }
+    }
+
+    /// Discard the original octets of the PSSH data, so that `to_bytes()` serializes `pssh_data`
+    /// using the DRM-specific encoder.
+    pub fn forget_original(&mut self) {
+        self.original = None;
+    }
+
+    /// Convert to a version 1 box, whose header lists the key IDs found in the box header and in
+    /// the DRM-specific PSSH data, so that players can read them without decoding the PSSH data.
+    pub fn to_v1(&self) -> PsshBox {
+        let mut bx = self.clone();
+        bx.key_ids = self.all_key_ids().into_iter().map(|t| t.key_id).collect();
+        bx.version = 1;
+        bx
+    }
+
+    /// Convert to a version 0 box, which has no key IDs in its header. Fails with
+    /// [`PsshError::KeyIdsNotInPayload`] if some header key IDs are not present in the PSSH data,
+    /// since the conversion would lose them.
+    pub fn to_v0(&self) -> Result<PsshBox> {
+        let in_payload: Vec<DRMKeyId> = self.key_id_locations().into_iter()
+            .filter(|(location, _)| *location != KeyIdLocation::Header)
+            .map(|(_, kid)| kid)
+            .collect();
+        let mut missing: Vec<DRMKeyId> = Vec::new();
+        for kid in &self.key_ids {
+            if !in_payload.contains(kid) && !missing.contains(kid) {
+                missing.push(*kid);
+            }
+        }
+        if self.version == 1 && !missing.is_empty() {
+            return Err(PsshError::KeyIdsNotInPayload { system: self.system_id, key_ids: missing });
+        }
+        let mut bx = self.clone();
+        bx.key_ids.clear();
+        bx.version = 0;
+        Ok(bx)
+    }
+
     pub fn to_base64(self) -> String {
         BASE64_STANDARD.encode(self.to_bytes())
     }
@@ -409,6 +784,98 @@ impl PsshBox {
     pub fn to_hex(self) -> String {
         hex::encode(self.to_bytes())
     }
+
+    /// All the key IDs in this box, from the header of a v1 box and from the DRM-specific PSSH
+    /// data, without duplicates and in order of first appearance. Each key ID is tagged with all the
+    /// places where it was found. Malformed key IDs (with an incorrect length) are ignored.
+    pub fn all_key_ids(&self) -> Vec<TaggedKeyId> {
+        let mut tagged = Vec::new();
+        for (location, key_id) in self.key_id_locations() {
+            merge_key_id(&mut tagged, key_id, KeyIdSource { system_id: self.system_id, location });
+        }
+        tagged
+    }
+
+    fn key_id_locations(&self) -> Vec<(KeyIdLocation, DRMKeyId)> {
+        let mut found = Vec::new();
+        if self.version == 1 {
+            found.extend(self.key_ids.iter().map(|k| (KeyIdLocation::Header, *k)));
+        }
+        match &self.pssh_data {
+            PsshData::Widevine(wv) =>
+                found.extend(wv.key_ids().into_iter().map(|k| (KeyIdLocation::WidevineKeyId, k))),
+            PsshData::PlayReady(pr) => {
+                for wrmh in pr.record.iter().filter_map(playready::PlayReadyRecord::wrm_header) {
+                    let data = &wrmh.data;
+                    found.extend(data.kids.iter()
+                                 .filter_map(|k| k.key_id())
+                                 .map(|k| (KeyIdLocation::PlayReadyKid, k)));
+                    if let Some(pi) = &data.protect_info {
+                        found.extend(pi.kids()
+                                     .filter_map(|k| k.key_id())
+                                     .map(|k| (KeyIdLocation::PlayReadyProtectInfo, k)));
+                    }
+                }
+            },
+            PsshData::Nagra(n) =>
+                found.extend(n.key_ids().into_iter().map(|k| (KeyIdLocation::NagraKeyId, k))),
+            PsshData::WisePlay(wp) =>
+                found.extend(wp.key_ids().into_iter().map(|k| (KeyIdLocation::WisePlayKids, k))),
+            PsshData::Custom(_) => {
+                if let Some(codec) = self.codec() {
+                    found.extend(codec.key_ids(&self.pssh_data).into_iter().map(|k| (KeyIdLocation::Codec, k)));
+                }
+            },
+            _ => (),
+        }
+        found
+    }
+}
+
+/// Where a key ID was found in a PSSH box.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyIdLocation {
+    /// The `key_ids` in the header of a v1 PSSH box.
+    Header,
+    /// The `key_id` field of Widevine PSSH data.
+    WidevineKeyId,
+    /// A `KID` element or attribute in the `DATA` of a PlayReady header.
+    PlayReadyKid,
+    /// A `KID` in the `PROTECTINFO` of a PlayReady header, or in its `KIDS` element.
+    PlayReadyProtectInfo,
+    /// The `keyId` of Nagra PSSH data.
+    NagraKeyId,
+    /// The `kids` array of WisePlay PSSH data.
+    WisePlayKids,
+    /// Returned by the [`DrmPayloadCodec`] registered for a custom DRM system.
+    Codec,
+}
+
+/// A place where a key ID was found: a location in a PSSH box for a DRM system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyIdSource {
+    pub system_id: DRMSystemId,
+    pub location: KeyIdLocation,
+}
+
+/// A key ID and all the places where it was found, as returned by [`PsshBox::all_key_ids`] and
+/// [`PsshBoxVec::all_key_ids`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaggedKeyId {
+    pub key_id: DRMKeyId,
+    pub sources: Vec<KeyIdSource>,
+}
+
+fn merge_key_id(tagged: &mut Vec<TaggedKeyId>, key_id: DRMKeyId, source: KeyIdSource) {
+    match tagged.iter_mut().find(|t| t.key_id == key_id) {
+        Some(t) => {
+            if !t.sources.contains(&source) {
+                t.sources.push(source);
+            }
+        },
+        None => tagged.push(TaggedKeyId { key_id, sources: vec![source] }),
+    }
 }
 
 /// This to_string() method provides the most compact representation possible on a single line; see
@@ -469,40 +936,76 @@ impl fmt::Display for PsshBox {
             PsshData::CommonEnc(pd) => write!(f, "CommonPSSH<{key_str}pssh data len {} octets>", pd.len()),
             PsshData::FairPlay(pd) => write!(f, "FairPlayPSSH<{key_str}pssh data len {} octets>", pd.len()),
             PsshData::Mobi(pd) => write!(f, "MobiPSSH<{key_str}pssh data len {} octets>", pd.len()),
+            PsshData::Unknown(pd) => write!(f, "PSSH<{}, {key_str}pssh data len {} octets>", self.system_id, pd.len()),
+            PsshData::Custom(_) => match self.codec() {
+                Some(codec) => write!(f, "PSSH<{}, {key_str}{}>", self.system_id,
+                                      codec::CodecDisplay(codec.as_ref(), &self.pssh_data)),
+                None => write!(f, "PSSH<{}, {key_str}{}>", self.system_id, self.pssh_data),
+            },
         }
     }
 }
 
 
 impl ToBytes for PsshBox {
-    #[allow(unused_must_use)]
-    fn to_bytes(self: &PsshBox) -> Vec<u8> {
-        let mut out = Vec::new();
-        let pssh_data_bytes = self.pssh_data.to_bytes();
-        let mut total_length: u32 = 4 // box size
+    fn write_to<W: Write>(&self, out: &mut W) -> Result<()> {
+        // Encode once, both to detect modification (as in is_modified) and to serialize it.
+        let encoded_result = self.encode_pssh_data();
+        let encoded_hash = encoding_hash(&encoded_result);
+        let encoded;
+        let pssh_data_bytes: &[u8] = match &self.original {
+            Some(original) if original.system_id == self.system_id &&
+                original.encoded_hash == encoded_hash => &original.octets,
+            _ => {
+                encoded = encoded_result?;
+                &encoded
+            },
+        };
+        let pssh_data_len = u32::try_from(pssh_data_bytes.len())
+            .map_err(|_| PsshError::LengthOverflow {
+                field: "PSSH data length",
+                length: pssh_data_bytes.len() as u64,
+                max: u32::MAX.into(),
+            })?;
+        let mut total_length: u64 = 4 // box size
             + 4     // BMFF box header 'pssh'
             + 4     // version+flags
             + 16    // system_id
             + 4     // pssh_data length
-            + pssh_data_bytes.len() as u32;
+            + u64::from(pssh_data_len);
+        let mut kid_count = 0;
         if self.version == 1 {
+            kid_count = u32::try_from(self.key_ids.len())
+                .map_err(|_| PsshError::LengthOverflow {
+                    field: "KID count",
+                    length: self.key_ids.len() as u64,
+                    max: u32::MAX.into(),
+                })?;
             total_length += 4 // key_id count
-                + self.key_ids.len() as u32 * 16;
+                + u64::from(kid_count) * 16;
+        }
+        // Boxes too large for a 32-bit size use size 1 followed by a 64-bit largesize (ISO/IEC
+        // 14496-12 section 4.2).
+        if let Ok(size) = u32::try_from(total_length) {
+            out.write_u32::<BigEndian>(size)?;
+            out.write_all(b"pssh")?;
+        } else {
+            out.write_u32::<BigEndian>(1)?;
+            out.write_all(b"pssh")?;
+            out.write_u64::<BigEndian>(total_length + 8)?;
         }
-        out.write_u32::<BigEndian>(total_length);
-        out.write_all(b"pssh");
-        let version_and_flags: u32 = self.flags ^ ((self.version as u32) << 24);
-        out.write_u32::<BigEndian>(version_and_flags);
-        out.write_all(&self.system_id.id);
+        let version_and_flags: u32 = (self.flags & 0x00FF_FFFF) | ((self.version as u32) << 24);
+        out.write_u32::<BigEndian>(version_and_flags)?;
+        out.write_all(&self.system_id.id)?;
         if self.version == 1 {
-            out.write_u32::<BigEndian>(self.key_ids.len() as u32);
+            out.write_u32::<BigEndian>(kid_count)?;
             for k in &self.key_ids {
-                out.write_all(&k.id);
+                out.write_all(&k.id)?;
             }
         }
-        out.write_u32::<BigEndian>(pssh_data_bytes.len() as u32);
-        out.write_all(&pssh_data_bytes);
-        out
+        out.write_u32::<BigEndian>(pssh_data_len)?;
+        out.write_all(pssh_data_bytes)?;
+        Ok(())
     }
 }
 
@@ -535,6 +1038,65 @@ impl PsshBoxVec {
         self.0.iter()
     }
 
+    /// Retain only the boxes for which `f` returns true.
+    pub fn retain<F: FnMut(&PsshBox) -> bool>(&mut self, f: F) {
+        self.0.retain(f);
+    }
+
+    /// The boxes for the DRM system `system_id`.
+    pub fn for_system(&self, system_id: DRMSystemId) -> impl Iterator<Item=&PsshBox> {
+        self.0.iter().filter(move |bx| bx.system_id == system_id)
+    }
+
+    /// The boxes which refer to each key ID (in their header or PSSH data), ordered by key ID.
+    /// Boxes which don't contain any key ID are omitted.
+    pub fn group_by_key_id(&self) -> BTreeMap<DRMKeyId, Vec<&PsshBox>> {
+        let mut groups: BTreeMap<DRMKeyId, Vec<&PsshBox>> = BTreeMap::new();
+        for bx in &self.0 {
+            for t in bx.all_key_ids() {
+                groups.entry(t.key_id).or_default().push(bx);
+            }
+        }
+        groups
+    }
+
+    /// Remove boxes which are semantically equal to an earlier box of the same version, keeping the
+    /// first occurrence. Boxes are compared with [`PsshBox::semantically_eq`], so boxes which differ
+    /// only in the encoding of their PSSH data (for example whitespace or attribute order in
+    /// PlayReady XML) or in the order of the key IDs in their header are duplicates. A v0 and a v1
+    /// box are different on the wire and are both kept.
+    pub fn dedup(&mut self) {
+        let mut kept: Vec<PsshBox> = Vec::with_capacity(self.0.len());
+        for bx in self.0.drain(..) {
+            if !kept.iter().any(|k| k.version == bx.version && k.semantically_eq(&bx)) {
+                kept.push(bx);
+            }
+        }
+        self.0 = kept;
+    }
+
+    /// Sort the boxes into a canonical order, by system ID, then version, then key IDs, then
+    /// serialized octets, so that the serialization of a set of boxes doesn't depend on the order
+    /// in which they were generated. The sort is stable.
+    pub fn sort_canonical(&mut self) {
+        self.0.sort_by_cached_key(|bx| {
+            (bx.system_id, bx.version, bx.key_ids.clone(), bx.try_to_bytes().unwrap_or_default())
+        });
+    }
+
+    /// All the key IDs in these boxes, as for [`PsshBox::all_key_ids`], merged across boxes.
+    pub fn all_key_ids(&self) -> Vec<TaggedKeyId> {
+        let mut tagged = Vec::new();
+        for bx in &self.0 {
+            for t in bx.all_key_ids() {
+                for source in t.sources {
+                    merge_key_id(&mut tagged, t.key_id, source);
+                }
+            }
+        }
+        tagged
+    }
+
     pub fn to_base64(self) -> String {
         let mut buf = Vec::new();
         for bx in self.0 {
@@ -552,6 +1114,16 @@ impl PsshBoxVec {
     }
 }
 
+/// The concatenation of the serialized boxes, as used in DRM initialization data.
+impl ToBytes for PsshBoxVec {
+    fn write_to<W: Write>(&self, out: &mut W) -> Result<()> {
+        for bx in &self.0 {
+            bx.write_to(out)?;
+        }
+        Ok(())
+    }
+}
+
 impl Default for PsshBoxVec {
     fn default() -> Self {
         Self::new()
@@ -567,6 +1139,27 @@ impl IntoIterator for PsshBoxVec {
     }
 }
 
+impl<'a> IntoIterator for &'a PsshBoxVec {
+    type Item = &'a PsshBox;
+    type IntoIter = std::slice::Iter<'a, PsshBox>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl FromIterator<PsshBox> for PsshBoxVec {
+    fn from_iter<I: IntoIterator<Item = PsshBox>>(iter: I) -> Self {
+        PsshBoxVec(iter.into_iter().collect())
+    }
+}
+
+impl Extend<PsshBox> for PsshBoxVec {
+    fn extend<I: IntoIterator<Item = PsshBox>>(&mut self, iter: I) {
+        self.0.extend(iter);
+    }
+}
+
 impl std::ops::Index<usize> for PsshBoxVec {
     type Output = PsshBox;
 
@@ -592,16 +1185,22 @@ impl fmt::Display for PsshBoxVec {
 
 /// Parse one or more PSSH boxes from some initialization data encoded in base64 format.
 pub fn from_base64(init_data: &str) -> Result<PsshBoxVec> {
+    from_base64_with_options(init_data, &ParseOptions::default())
+}
+
+/// Parse one or more PSSH boxes from base64-encoded initialization data, as for [`from_base64`],
+/// with parsing controlled by `options`.
+pub fn from_base64_with_options(init_data: &str, options: &ParseOptions) -> Result<PsshBoxVec> {
     let b64_tolerant_config = engine::GeneralPurposeConfig::new()
         .with_decode_allow_trailing_bits(true)
         .with_decode_padding_mode(engine::DecodePaddingMode::Indifferent);
     let b64_tolerant_engine = engine::GeneralPurpose::new(&base64::alphabet::STANDARD, b64_tolerant_config);
     if init_data.len() < 8 {
-        return Err(anyhow!("insufficient length for init data"));
+        return Err(PsshError::Truncated { offset: 0, needed: 8 });
     }
     // We start by attempting to base64 decode the full string and parse that.
     if let Ok(buf) = b64_tolerant_engine.decode(init_data) {
-        return from_bytes(&buf);
+        return from_bytes_with_options(&buf, options);
     }
     // If that doesn't work, attempt to decode PSSH boxes from subsequences of the init data. We
     // look at a sliding window that starts at start and ends at start + the length we see from the
@@ -609,29 +1208,58 @@ pub fn from_base64(init_data: &str) -> Result<PsshBoxVec> {
     let total_len = init_data.len();
     let mut start = 0;
     let mut boxes = Vec::new();
-    while start < total_len - 1 {
-        let buf = b64_tolerant_engine.decode(&init_data[start..start+7])
-            .context("base64 decoding first 32-bit length word")?;
+    while start + 1 < total_len {
+        // Offset in the decoded octets corresponding to the start of this window.
+        let decoded_start = (start / 4 * 3) as u64;
+        let header = init_data.get(start..start+7)
+            .ok_or(PsshError::Truncated { offset: decoded_start, needed: 4 })?;
+        let buf = b64_tolerant_engine.decode(header)
+            .map_err(|e| base64_error(e).rebase(start as u64))?;
         let mut rdr = Cursor::new(buf);
         let box_size: u32 = rdr.read_u32::<BigEndian>()
-            .context("reading PSSH box size")?;
+            .map_err(|_| PsshError::Truncated { offset: decoded_start, needed: 4 })?;
         trace!("box size from header = {box_size}");
+        let extends_to_end = box_size == 0;
+        let box_size: u64 = match box_size {
+            // The box extends to the end of the input.
+            0 => (total_len - start).div_ceil(4) as u64 * 3,
+            1 => {
+                // A 64-bit largesize follows the box type.
+                let header = init_data.get(start..start+24)
+                    .ok_or(PsshError::Truncated { offset: decoded_start, needed: 16 })?;
+                let buf = b64_tolerant_engine.decode(header)
+                    .map_err(|e| base64_error(e).rebase(start as u64))?;
+                let largesize = buf.get(8..16)
+                    .and_then(|ls| <[u8; 8]>::try_from(ls).ok())
+                    .ok_or(PsshError::Truncated { offset: decoded_start + 8, needed: 8 })?;
+                u64::from_be_bytes(largesize)
+            },
+            size => size.into(),
+        };
+        if box_size < 8 {
+            return Err(PsshError::BadBoxSize { offset: decoded_start, size: box_size });
+        }
         // The number of octets that we obtain from decoding box_size chars worth of base64
-        let wanted_octets = (box_size.div_ceil(3) * 4) as usize;
-        let end = start + wanted_octets;
+        let wanted_octets = usize::try_from(box_size).unwrap_or(usize::MAX).div_ceil(3).saturating_mul(4);
+        let end = if extends_to_end { total_len } else { start.saturating_add(wanted_octets) };
         trace!("attempting to decode {wanted_octets} octets out of {}", init_data.len());
-        if end > init_data.len() {
-            // FIXME actually we shouldn't fail here, but rather break and return any boxes that we
-            // did manage to parse
-            return Err(anyhow!("insufficient length for init data (wanted {end}, have {})", init_data.len()));
+        let Some(window) = init_data.get(start..end) else {
+            // Return the boxes that we did manage to parse, if any.
+            if boxes.is_empty() {
+                return Err(PsshError::Truncated { offset: decoded_start, needed: box_size });
+            }
+            trace!("Ignoring truncated PSSH box at decoded offset {decoded_start}");
+            break;
+        };
+        let buf = b64_tolerant_engine.decode(window)
+            .map_err(|e| base64_error(e).rebase(start as u64))?;
+        let bxs = from_bytes_with_options(&buf, options)
+            .map_err(|e| e.rebase(decoded_start))?;
+        for bx in bxs {
+            trace!("Got one box {bx}");
+            boxes.push(bx);
         }
-        let buf = b64_tolerant_engine.decode(&init_data[start..end])
-            .context("decoding base64")?;
-        let bx = from_bytes(&buf)
-            .context("parsing the PSSH initialization data")?;
-        assert!(bx.len() == 1);
-        trace!("Got one box {}", bx[0].clone());
-        boxes.push(bx[0].clone());
+        options.limits.check_box_count(boxes.len(), decoded_start)?;
         start = end;
     }
     Ok(PsshBoxVec(boxes))
@@ -639,235 +1267,528 @@ pub fn from_base64(init_data: &str) -> Result<PsshBoxVec> {
 
 /// Parse one or more PSSH boxes from some initialization data encoded in hex format.
 pub fn from_hex(init_data: &str) -> Result<PsshBoxVec> {
+    from_hex_with_options(init_data, &ParseOptions::default())
+}
+
+/// Parse one or more PSSH boxes from hex-encoded initialization data, as for [`from_hex`], with
+/// parsing controlled by `options`.
+pub fn from_hex_with_options(init_data: &str, options: &ParseOptions) -> Result<PsshBoxVec> {
     let buf = hex::decode(init_data)
-        .context("decoding hex")?;
-    from_bytes(&buf)
-        .context("parsing the PSSH initialization_data")
+        .map_err(hex_error)?;
+    from_bytes_with_options(&buf, options)
 }
 
-/// Parse a single PSSH box.
-fn read_pssh_box(rdr: &mut Cursor<&[u8]>) -> Result<PsshBox> {
-    let size: u32 = rdr.read_u32::<BigEndian>()
-        .context("reading PSSH box size")?;
+// Read exactly buf.len() octets, or return a Truncated error that points to the current position.
+fn read_exact_at(rdr: &mut Cursor<&[u8]>, buf: &mut [u8]) -> Result<()> {
+    let offset = rdr.position();
+    rdr.read_exact(buf)
+        .map_err(|_| PsshError::Truncated { offset, needed: buf.len() as u64 })
+}
+
+fn read_u32_at(rdr: &mut Cursor<&[u8]>) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    read_exact_at(rdr, &mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+/// Options controlling how PSSH boxes are parsed, accepted by the `*_with_options` variants of the
+/// parsing and scanning functions.
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Reject boxes whose declared size (in the ISO BMFF box header) doesn't match the number of
+    /// octets used by their contents, or which extend beyond the end of the input. When `false`,
+    /// the declared size is only used to skip over any unused octets at the end of a box.
+    pub strict: bool,
+    /// Limits on the resources used when parsing untrusted input.
+    pub limits: ParseLimits,
+    /// The codecs used to decode PSSH data. When `None`, the global registry (see
+    /// [`register_codec`]) is used.
+    pub registry: Option<Arc<CodecRegistry>>,
+}
+
+/// Limits on the resources used when parsing or scanning untrusted input. Exceeding a limit
+/// results in a [`PsshError::LimitExceeded`] error. The default limits are generous for real-world
+/// PSSH boxes; use [`ParseLimits::unlimited`] to disable them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// The maximum number of key IDs in the header of a v1 PSSH box.
+    pub max_key_ids: u32,
+    /// The maximum length in octets of the DRM-specific PSSH data in a box.
+    pub max_pssh_data_len: u32,
+    /// The maximum number of boxes returned by a single call to a parsing or scanning function.
+    pub max_boxes: usize,
+    /// The maximum number of octets that `find_boxes_stream` buffers while waiting for the end of
+    /// a box.
+    pub max_buffered: usize,
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        ParseLimits {
+            max_key_ids: 4096,
+            max_pssh_data_len: 4 * 1024 * 1024,
+            max_boxes: 65_536,
+            max_buffered: 16 * 1024 * 1024,
+        }
+    }
+}
+
+impl ParseLimits {
+    /// No limits other than those imposed by the binary format.
+    pub fn unlimited() -> Self {
+        ParseLimits {
+            max_key_ids: u32::MAX,
+            max_pssh_data_len: u32::MAX,
+            max_boxes: usize::MAX,
+            max_buffered: usize::MAX,
+        }
+    }
+
+    fn check(limit: &'static str, value: u64, max: u64, offset: u64) -> Result<()> {
+        if value > max {
+            return Err(PsshError::LimitExceeded { offset, limit, value, max });
+        }
+        Ok(())
+    }
+
+    // Check that count boxes (including the one starting at offset) is within the limit.
+    fn check_box_count(&self, count: usize, offset: u64) -> Result<()> {
+        ParseLimits::check("box count", count as u64, self.max_boxes as u64, offset)
+    }
+}
+
+// The size of the box starting at offset start in buffer, according to its ISO BMFF box header (a
+// 32-bit size, or a 64-bit largesize if the 32-bit size is 1, or the remainder of the buffer if the
+// size is 0). Returns None if the header is incomplete or the size is too small for the header.
+fn declared_box_size(buffer: &[u8], start: usize) -> Option<u64> {
+    let size_octets: [u8; 4] = buffer.get(start..start.checked_add(4)?)?.try_into().ok()?;
+    match u32::from_be_bytes(size_octets) {
+        0 => Some((buffer.len() - start) as u64),
+        1 => {
+            let large_octets: [u8; 8] = buffer.get(start+8..start.checked_add(16)?)?.try_into().ok()?;
+            let largesize = u64::from_be_bytes(large_octets);
+            (largesize >= 16).then_some(largesize)
+        },
+        size if size >= 8 => Some(size.into()),
+        _ => None,
+    }
+}
+
+// The len octets at offset in input, or a Truncated error.
+fn slice_at(input: &[u8], offset: u64, len: u64) -> Result<&[u8]> {
+    usize::try_from(offset).ok()
+        .zip(usize::try_from(len).ok())
+        .and_then(|(start, len)| input.get(start..start.checked_add(len)?))
+        .ok_or(PsshError::Truncated { offset, needed: len })
+}
+
+/// Parse the header of a single PSSH box, without copying or decoding its PSSH data.
+fn read_pssh_box_ref<'a>(rdr: &mut Cursor<&'a [u8]>, options: &ParseOptions) -> Result<PsshBoxRef<'a>> {
+    let input: &'a [u8] = rdr.get_ref();
+    let box_start = rdr.position();
+    let available = input.len() as u64 - box_start.min(input.len() as u64);
+    let size: u32 = read_u32_at(rdr)?;
     trace!("PSSH box of size {size} octets");
+    let box_header_offset = rdr.position();
     let mut box_header = [0u8; 4];
-    rdr.read_exact(&mut box_header)
-        .context("reading box header")?;
+    read_exact_at(rdr, &mut box_header)?;
     // the ISO BMFF box header
     if !box_header.eq(b"pssh") {
-        return Err(anyhow!("expecting BMFF header"));
+        return Err(PsshError::BadBoxType { offset: box_header_offset, found: box_header });
     }
-    let version_and_flags: u32 = rdr.read_u32::<BigEndian>()
-        .context("reading PSSH version/flags")?;
-    let version: u8 = (version_and_flags >> 24).try_into().unwrap();
+    let declared_size: u64 = match size {
+        // The box extends to the end of the input.
+        0 => available,
+        1 => {
+            let mut largesize = [0u8; 8];
+            read_exact_at(rdr, &mut largesize)?;
+            u64::from_be_bytes(largesize)
+        },
+        _ => size.into(),
+    };
+    let header_len = rdr.position() - box_start;
+    if options.strict {
+        if declared_size < header_len {
+            return Err(PsshError::BadBoxSize { offset: box_start, size: declared_size });
+        }
+        if declared_size > available {
+            return Err(PsshError::Truncated { offset: box_start, needed: declared_size });
+        }
+    }
+    let version_offset = rdr.position();
+    let version_and_flags: u32 = read_u32_at(rdr)?;
+    let version = (version_and_flags >> 24) as u8;
     trace!("PSSH box version {version}");
     if version > 1 {
-        return Err(anyhow!("unknown PSSH version {version}"));
+        return Err(PsshError::UnsupportedVersion { offset: version_offset, version });
     }
     let mut system_id_buf = [0u8; 16];
-    rdr.read_exact(&mut system_id_buf)
-        .context("reading system_id")?;
+    read_exact_at(rdr, &mut system_id_buf)?;
     let system_id = DRMSystemId { id: system_id_buf };
-    let mut key_ids = Vec::new();
+    let mut key_ids: &'a [DRMKeyId] = &[];
     if version == 1 {
-        let mut kid_count = rdr.read_u32::<BigEndian>()
-            .context("reading KID count")?;
+        let kid_count = read_u32_at(rdr)?;
         trace!("PSSH box has {kid_count} KIDs in box header");
-        while kid_count > 0 {
-            let mut key = [0u8; 16];
-            rdr.read_exact(&mut key)
-                .context("reading key_id")?;
-            key_ids.push(DRMKeyId { id: key });
-            kid_count -= 1;
-        }
+        let kids_offset = rdr.position();
+        ParseLimits::check("KID count", kid_count.into(), options.limits.max_key_ids.into(), kids_offset - 4)?;
+        let kids = slice_at(input, kids_offset, u64::from(kid_count) * 16)?;
+        key_ids = <[DRMKeyId]>::ref_from_bytes(kids)
+            .map_err(|_| PsshError::Truncated { offset: kids_offset, needed: kids.len() as u64 })?;
+        rdr.set_position(kids_offset + kids.len() as u64);
     }
-    let pssh_data_len = rdr.read_u32::<BigEndian>()
-        .context("reading PSSH data length")?;
+    let pssh_data_len = read_u32_at(rdr)?;
     trace!("PSSH box data length {pssh_data_len} octets");
-    let mut pssh_data = Vec::new();
-    rdr.take(pssh_data_len.into()).read_to_end(&mut pssh_data)
-        .context("extracting PSSH data")?;
-    match system_id {
-        WIDEVINE_SYSTEM_ID => {
-            let wv_pssh_data = WidevinePsshData::decode(Cursor::new(pssh_data))
-                .context("parsing Widevine PSSH data")?;
-            Ok(PsshBox {
-                version,
-                flags: version_and_flags & 0xF,
-                system_id,
-                key_ids,
-                pssh_data: PsshData::Widevine(wv_pssh_data),
-            })
-        },
-        PLAYREADY_SYSTEM_ID => {
-            let pr_pssh_data = playready::parse_pssh_data(&pssh_data)
-                .context("parsing PlayReady PSSH data")?;
-            Ok(PsshBox {
-                version,
-                flags: version_and_flags & 0xF,
-                system_id,
-                key_ids,
-                pssh_data: PsshData::PlayReady(pr_pssh_data),
-            })
-        },
-        IRDETO_SYSTEM_ID => {
-            let ir_pssh_data = irdeto::parse_pssh_data(&pssh_data)
-                .context("parsing Irdeto PSSH data")?;
-            Ok(PsshBox {
-                version,
-                flags: version_and_flags & 0xF,
-                system_id,
-                key_ids,
-                pssh_data: PsshData::Irdeto(ir_pssh_data),
-            })
-        },
-        MARLIN_SYSTEM_ID => {
-            Ok(PsshBox {
-                version,
-                flags: version_and_flags & 0xF,
-                system_id,
-                key_ids,
-                pssh_data: PsshData::Marlin(pssh_data),
-            })
-        },
-        NAGRA_SYSTEM_ID => {
-            let pd = nagra::parse_pssh_data(&pssh_data)
-                .context("parsing Nagra PSSH data")?;
-            Ok(PsshBox {
-                version,
-                flags: version_and_flags & 0xF,
-                system_id,
-                key_ids,
-                pssh_data: PsshData::Nagra(pd),
-            })
-        },
-        WISEPLAY_SYSTEM_ID => {
-            let cdrm_pssh_data = wiseplay::parse_pssh_data(&pssh_data)
-                .context("parsing WisePlay PSSH data")?;
-            Ok(PsshBox {
-                version,
-                flags: version_and_flags & 0xF,
-                system_id,
-                key_ids,
-                pssh_data: PsshData::WisePlay(cdrm_pssh_data),
-            })
-        },
-        COMMON_SYSTEM_ID => {
-            Ok(PsshBox {
-                version,
-                flags: version_and_flags & 0xF,
-                system_id,
-                key_ids,
-                pssh_data: PsshData::CommonEnc(pssh_data),
-            })
-        },
-        FAIRPLAYNFLX_SYSTEM_ID => {
-            Ok(PsshBox {
-                version,
-                flags: version_and_flags & 0xF,
-                system_id,
-                key_ids,
-                pssh_data: PsshData::FairPlay(pssh_data),
-            })
-        },
-        MOBI_SYSTEM_ID => {
-            Ok(PsshBox {
-                version,
-                flags: version_and_flags & 0xF,
-                system_id,
-                key_ids,
-                pssh_data: PsshData::Mobi(pssh_data),
-            })
-        },
-        _ => Err(anyhow!("can't parse this system_id type: {:?}", system_id)),
+    let pssh_data_offset = rdr.position();
+    ParseLimits::check("PSSH data length", pssh_data_len.into(), options.limits.max_pssh_data_len.into(),
+                       pssh_data_offset - 4)?;
+    let pssh_data = slice_at(input, pssh_data_offset, pssh_data_len.into())?;
+    rdr.set_position(pssh_data_offset + u64::from(pssh_data_len));
+    let consumed = rdr.position() - box_start;
+    if consumed != declared_size {
+        if options.strict {
+            return Err(PsshError::BoxSizeMismatch { offset: box_start, declared: declared_size, consumed });
+        }
+        // Skip over unused octets at the end of the box, rather than reading them as the start of
+        // the next box.
+        if consumed < declared_size && declared_size <= available {
+            trace!("Skipping {} unused octets at end of PSSH box", declared_size - consumed);
+            rdr.set_position(box_start + declared_size);
+        }
+    }
+    Ok(PsshBoxRef {
+        version,
+        flags: version_and_flags & 0x00FF_FFFF,
+        system_id,
+        key_ids,
+        pssh_data,
+        pssh_data_offset,
+        octets: &input[box_start as usize..rdr.position() as usize],
+    })
+}
+
+/// Parse a single PSSH box.
+fn read_pssh_box(rdr: &mut Cursor<&[u8]>, options: &ParseOptions) -> Result<PsshBox> {
+    read_pssh_box_ref(rdr, options)?.decode_with_options(options)
+}
+
+// Decode the DRM-specific PSSH data for system_id with the global codec registry. Error offsets
+// are relative to the start of pssh_data.
+fn decode_pssh_data(system_id: DRMSystemId, pssh_data: &[u8]) -> Result<PsshData> {
+    codec::decode_global(system_id, pssh_data)
+}
+
+/// A PSSH box which borrows from the octets it was parsed from, as returned by [`iter_box_refs`].
+///
+/// The header fields and the raw PSSH data are available without allocation. The DRM-specific
+/// PSSH data is only decoded when [`PsshBoxRef::decode_pssh_data`] or
+/// [`PsshBoxRef::to_pssh_box`] is called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PsshBoxRef<'a> {
+    pub version: u8,
+    pub flags: u32,
+    pub system_id: DRMSystemId,
+    /// The key IDs in the box header (always empty for a v0 box).
+    pub key_ids: &'a [DRMKeyId],
+    /// The undecoded DRM-specific PSSH data.
+    pub pssh_data: &'a [u8],
+    pssh_data_offset: u64,
+    octets: &'a [u8],
+}
+
+impl<'a> PsshBoxRef<'a> {
+    /// The octets of the full box, including the box header.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.octets
+    }
+
+    /// Decode the DRM-specific PSSH data. Error offsets are relative to the start of the input
+    /// that the box was parsed from.
+    pub fn decode_pssh_data(&self) -> Result<PsshData> {
+        decode_pssh_data(self.system_id, self.pssh_data)
+            .map_err(|e| e.rebase(self.pssh_data_offset))
+    }
+
+    /// Decode the DRM-specific PSSH data using the codecs in `registry` instead of the global
+    /// registry.
+    pub fn decode_pssh_data_with(&self, registry: &CodecRegistry) -> Result<PsshData> {
+        registry.decode(self.system_id, self.pssh_data)
+            .map_err(|e| e.rebase(self.pssh_data_offset))
+    }
+
+    /// Convert to an owned `PsshBox`, decoding the DRM-specific PSSH data.
+    pub fn to_pssh_box(&self) -> Result<PsshBox> {
+        self.with_pssh_data(self.decode_pssh_data()?, codec::codec_for(self.system_id))
+    }
+
+    /// Convert to an owned `PsshBox`, decoding the DRM-specific PSSH data using the codecs in
+    /// `registry` instead of the global registry. The box keeps the codec from `registry` to encode
+    /// and display its PSSH data. If `registry` has no codec for the system ID, the PSSH data is
+    /// kept as raw octets and the box doesn't fall back to the global registry.
+    pub fn to_pssh_box_with(&self, registry: &CodecRegistry) -> Result<PsshBox> {
+        self.with_pssh_data(self.decode_pssh_data_with(registry)?, registry.get(self.system_id))
+    }
+
+    fn decode_with_options(&self, options: &ParseOptions) -> Result<PsshBox> {
+        match &options.registry {
+            Some(registry) => self.to_pssh_box_with(registry),
+            None => self.to_pssh_box(),
+        }
+    }
+
+    fn with_pssh_data(&self, pssh_data: PsshData, codec: Option<Arc<dyn DrmPayloadCodec>>) -> Result<PsshBox> {
+        let mut bx = PsshBox {
+            version: self.version,
+            flags: self.flags,
+            system_id: self.system_id,
+            key_ids: self.key_ids.to_vec(),
+            pssh_data,
+            original: None,
+            // The PSSH data of a system without a codec was decoded by the raw codec.
+            codec: Some((self.system_id, codec.unwrap_or_else(|| Arc::new(codec::RawCodec)))),
+        };
+        bx.original = Some(OriginalPsshData {
+            octets: self.pssh_data.to_vec(),
+            system_id: self.system_id,
+            encoded_hash: encoding_hash(&bx.encode_pssh_data()),
+        });
+        Ok(bx)
+    }
+}
+
+impl TryFrom<PsshBoxRef<'_>> for PsshBox {
+    type Error = PsshError;
+
+    fn try_from(bx: PsshBoxRef<'_>) -> Result<Self, Self::Error> {
+        bx.to_pssh_box()
+    }
+}
+
+/// An iterator over the PSSH boxes in a slice of octets, returned by [`iter_box_refs`].
+pub struct PsshBoxRefIter<'a> {
+    rdr: Cursor<&'a [u8]>,
+    options: ParseOptions,
+    count: usize,
+    done: bool,
+}
+
+impl<'a> Iterator for PsshBoxRefIter<'a> {
+    type Item = Result<PsshBoxRef<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let input: &'a [u8] = self.rdr.get_ref();
+        let pos = self.rdr.position() as usize;
+        if self.done || pos + 1 >= input.len() {
+            return None;
+        }
+        // skip over any trailing octets that are NULL
+        if pos > 0 && input[pos..].iter().all(|b| *b == 0) {
+            return None;
+        }
+        self.count += 1;
+        let bx = self.options.limits.check_box_count(self.count, pos as u64)
+            .and_then(|_| read_pssh_box_ref(&mut self.rdr, &self.options));
+        // We can't resynchronize after an error, so stop here.
+        self.done = bx.is_err();
+        Some(bx)
+    }
+}
+
+/// Iterate over the PSSH boxes in some initialization data provided as a slice of octets, without
+/// copying their contents or decoding their DRM-specific PSSH data. Iteration stops after the first
+/// error.
+pub fn iter_box_refs(init_data: &[u8]) -> PsshBoxRefIter<'_> {
+    iter_box_refs_with_options(init_data, &ParseOptions::default())
+}
+
+/// Iterate over the PSSH boxes in a slice of octets, as for [`iter_box_refs`], with parsing
+/// controlled by `options`.
+pub fn iter_box_refs_with_options<'a>(init_data: &'a [u8], options: &ParseOptions) -> PsshBoxRefIter<'a> {
+    PsshBoxRefIter {
+        rdr: Cursor::new(init_data),
+        options: options.clone(),
+        count: 0,
+        done: false,
     }
 }
 
 /// Read one or more PSSH boxes from some initialization data provided as a slice of octets,
 /// returning an error if any non-PSSH data is found in the slice or if the parsing fails.
 pub fn from_bytes(init_data: &[u8]) -> Result<PsshBoxVec> {
+    from_bytes_with_options(init_data, &ParseOptions::default())
+}
+
+/// Read one or more PSSH boxes from a slice of octets, as for [`from_bytes`], with parsing
+/// controlled by `options`.
+pub fn from_bytes_with_options(init_data: &[u8], options: &ParseOptions) -> Result<PsshBoxVec> {
+    if init_data.is_empty() {
+        return Err(PsshError::Truncated { offset: 0, needed: 8 });
+    }
+    let mut boxes = PsshBoxVec::new();
+    for bx in iter_box_refs_with_options(init_data, options) {
+        let bx = bx?.decode_with_options(options)?;
+        trace!("Read one box {bx} from bytes");
+        boxes.add(bx);
+    }
+    Ok(boxes)
+}
+
+/// Read one or more PSSH boxes from a slice of octets, stopping (but not returning an error) when
+/// non-PSSH data is found in the slice. An error is returned if the parsing fails. Use
+/// [`parse_lenient`] to continue past the first invalid box and find out why it was rejected.
+pub fn from_buffer(init_data: &[u8]) -> Result<PsshBoxVec> {
+    from_buffer_with_options(init_data, &ParseOptions::default())
+}
+
+/// Read one or more PSSH boxes from a slice of octets, as for [`from_buffer`], with parsing
+/// controlled by `options`. An error is returned if a limit in `options` is exceeded.
+pub fn from_buffer_with_options(init_data: &[u8], options: &ParseOptions) -> Result<PsshBoxVec> {
     let total_len = init_data.len();
     let mut rdr = Cursor::new(init_data);
     let mut boxes = PsshBoxVec::new();
-    while (rdr.position() as usize) < total_len - 1  {
-        let bx = read_pssh_box(&mut rdr)?;
-        boxes.add(bx.clone());
-        trace!("Read one box {bx} from bytes, remaining {} octets", total_len as u64 - rdr.position());
-        let pos = rdr.position() as usize;
-        if let Some(remaining) = &rdr.get_ref().get(pos..total_len) {
-            // skip over any octets that are NULL
-            if remaining.iter().all(|b| *b == 0) {
+    while (rdr.position() as usize) + 1 < total_len  {
+        options.limits.check_box_count(boxes.len() + 1, rdr.position())?;
+        match read_pssh_box(&mut rdr, options) {
+            Err(e @ PsshError::LimitExceeded { .. }) => return Err(e),
+            Ok(bx) => boxes.add(bx),
+            Err(e) => {
+                trace!("Stopping at invalid PSSH box: {e}");
                 break;
-            }
+            },
         }
     }
     Ok(boxes)
 }
 
-/// Read one or more PSSH boxes from a slice of octets, stopping (but not returning an error) when
-/// non-PSSH data is found in the slice. An error is returned if the parsing fails.
-pub fn from_buffer(init_data: &[u8]) -> Result<PsshBoxVec> {
+/// Read PSSH boxes from a slice of octets, skipping over any boxes that can't be parsed. Returns
+/// the boxes that were parsed successfully, along with a [`Diagnostic`] for each problem
+/// encountered. Offsets in the diagnostics are relative to the start of `init_data`.
+///
+/// After an invalid box, parsing resumes after the end of the box as given by its declared size,
+/// if that is plausible, and otherwise at the next `pssh` box header in the input.
+pub fn parse_lenient(init_data: &[u8]) -> (PsshBoxVec, Vec<Diagnostic>) {
+    parse_lenient_with_options(init_data, &ParseOptions::default())
+}
+
+/// Read PSSH boxes from a slice of octets, as for [`parse_lenient`], with parsing controlled by
+/// `options`. Parsing stops if the limit on the number of boxes is exceeded.
+pub fn parse_lenient_with_options(init_data: &[u8], options: &ParseOptions) -> (PsshBoxVec, Vec<Diagnostic>) {
+    use bstr::ByteSlice;
+
     let total_len = init_data.len();
-    let mut rdr = Cursor::new(init_data);
     let mut boxes = PsshBoxVec::new();
-    while (rdr.position() as usize) < total_len - 1  {
-        if let Ok(bx) = read_pssh_box(&mut rdr) {
-            boxes.add(bx);
-        } else {
+    let mut diagnostics = Vec::new();
+    let mut pos = 0;
+    while pos < total_len {
+        let remaining = &init_data[pos..];
+        // Trailing NUL padding is accepted silently, as in from_bytes().
+        if remaining.iter().all(|b| *b == 0) {
+            break;
+        }
+        if let Err(e) = options.limits.check_box_count(boxes.len() + 1, pos as u64) {
+            diagnostics.push(Diagnostic { severity: Severity::Error, error: e });
             break;
         }
+        let mut rdr = Cursor::new(remaining);
+        match read_pssh_box(&mut rdr, options) {
+            Ok(bx) => {
+                boxes.add(bx);
+                pos += rdr.position() as usize;
+                continue;
+            },
+            Err(e) => diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                error: e.rebase(pos as u64),
+            }),
+        }
+        // Resynchronize using the declared box size, if it is plausible. If it leads to something
+        // other than the end of the input or the start of another PSSH box, we search for the next
+        // pssh box type from that point.
+        let declared = declared_box_size(remaining, 0)
+            .and_then(|size| usize::try_from(size).ok())
+            .unwrap_or(0);
+        if declared > 0 && declared <= remaining.len() {
+            pos += declared;
+            let next = &init_data[pos..];
+            if next.is_empty() || next.get(4..8) == Some(b"pssh") || next.iter().all(|b| *b == 0) {
+                continue;
+            }
+        }
+        let remaining = &init_data[pos..];
+        // The pssh box type is preceded by the 4 octets of the box size.
+        let skip = match remaining.get(5..).and_then(|rest| rest.find(b"pssh")) {
+            Some(idx) => idx + 1,
+            None => remaining.len(),
+        };
+        diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            error: PsshError::UnexpectedData { offset: pos as u64, length: skip as u64 },
+        });
+        pos += skip;
     }
-    Ok(boxes)
+    (boxes, diagnostics)
+}
+
+// The octets of a possible PSSH box whose b"pssh" box type is at offset in buffer, according to
+// the box size which precedes the box type. Returns None if the box size is implausible or the box
+// would extend beyond the end of the buffer.
+fn candidate_box(buffer: &[u8], offset: usize) -> Option<&[u8]> {
+    if offset < 4 || offset + 24 > buffer.len() {
+        return None;
+    }
+    let start = offset - 4;
+    let size = usize::try_from(declared_box_size(buffer, start)?).ok()?;
+    buffer.get(start..start.checked_add(size)?)
 }
 
 /// Locate the positions of PSSH boxes in a buffer, if any are present. Returns an iterator over
 /// start positions for PSSH boxes in the buffer.
 pub fn find_iter(buffer: &[u8]) -> impl Iterator<Item = usize> + '_ {
+    find_iter_with_options(buffer, &ParseOptions::default())
+}
+
+/// Locate the positions of PSSH boxes in a buffer, as for [`find_iter`], with parsing of the
+/// candidate boxes controlled by `options`. At most `options.limits.max_boxes` positions are
+/// returned.
+pub fn find_iter_with_options<'a>(buffer: &'a [u8], options: &ParseOptions) -> impl Iterator<Item = usize> + 'a {
     use bstr::ByteSlice;
 
+    let options = options.clone();
+    let max_boxes = options.limits.max_boxes;
     buffer.find_iter(b"pssh")
-        .filter(|offset| {
-            if offset+24 > buffer.len() {
-                return false;
-            }
-            if offset+4 < 8 {
-                return false;
-            }
-            let start = offset - 4;
-            let mut rdr = Cursor::new(&buffer[start..]);
-            let size: u32 = rdr.read_u32::<BigEndian>().unwrap();
-            let end = start + size as usize;
-            if end > buffer.len() {
-                return false;
-            }
-            from_bytes(&buffer[start..end]).is_ok()
+        .filter(move |offset| {
+            candidate_box(buffer, *offset)
+                .is_some_and(|bx| from_bytes_with_options(bx, &options).is_ok())
         })
         .map(|offset| offset - 4)
+        .take(max_boxes)
 }
 
 
 /// Extract PSSH boxes in a buffer, if any are present. Returns an iterator over PSSH boxes in the
 /// buffer.
 pub fn find_boxes_buffer(buffer: &[u8]) -> impl Iterator<Item = PsshBox> + '_ {
+    find_boxes_buffer_with_options(buffer, &ParseOptions::default())
+}
+
+/// Extract PSSH boxes in a buffer, as for [`find_boxes_buffer`], with parsing of the candidate
+/// boxes controlled by `options`. At most `options.limits.max_boxes` boxes are returned.
+pub fn find_boxes_buffer_with_options<'a>(buffer: &'a [u8], options: &ParseOptions) -> impl Iterator<Item = PsshBox> + 'a {
     use bstr::ByteSlice;
 
     let mut boxes = Vec::new();
     for offset in buffer.find_iter(b"pssh") {
-        if offset+24 > buffer.len() || offset+4 < 8 {
-            break;
+        if let Some(bx) = candidate_box(buffer, offset) {
+            if let Ok(pbv) = from_bytes_with_options(bx, options) {
+                for pb in pbv {
+                    boxes.push(pb);
+                }
+            }
         }
-        let start = offset - 4;
-        let mut rdr = Cursor::new(&buffer[start..]);
-        let size: u32 = rdr.read_u32::<BigEndian>().unwrap();
-        let end = start + size as usize;
-        if end > buffer.len() {
+        if boxes.len() >= options.limits.max_boxes {
+            trace!("Stopping search after {} boxes", boxes.len());
+            boxes.truncate(options.limits.max_boxes);
             break;
         }
-        if let Ok(pbv) = from_bytes(&buffer[start..end]) {
-            for pb in pbv {
-                boxes.push(pb);
-            }
-        }
     }
     boxes.into_iter()
 }
@@ -882,29 +1803,72 @@ pub fn find_boxes_stream<R>(reader: R) -> impl Iterator<Item = Result<PsshBox, i
 where
     R: Read,
 {
-    PsshBoxIterator::new(reader)
+    find_boxes_stream_with_options(reader, &ParseOptions::default())
+}
+
+/// Extract PSSH boxes from a stream of octets, as for [`find_boxes_stream`], with parsing of the
+/// candidate boxes controlled by `options`. If more than `options.limits.max_buffered` octets
+/// would need to be buffered to read a box, the box is skipped and reported as an `io::Error` of
+/// kind `InvalidData` wrapping a [`PsshError::LimitExceeded`]. A box whose header declares a size
+/// above the limit is reported as soon as its header has been read. The iterator ends after an error reporting that
+/// `options.limits.max_boxes` has been exceeded.
+pub fn find_boxes_stream_with_options<R>(reader: R, options: &ParseOptions) -> impl Iterator<Item = Result<PsshBox, io::Error>>
+where
+    R: Read,
+{
+    PsshBoxIterator::new(reader, options)
 }
 
 struct PsshBoxIterator<R> {
     reader: R,
+    options: ParseOptions,
+    // Octets read from the stream but not yet examined, starting at stream position buffer_start.
     buffer: Vec<u8>,
-    buffer_pos: usize,
+    buffer_start: u64,
+    // The position in buffer from which to search for the next pssh box type.
+    scan_pos: usize,
+    eof: bool,
+    count: usize,
     pending_boxes : Vec<PsshBox>,
     read_buffer: Vec<u8>,
 }
 
 impl<R> PsshBoxIterator<R> {
-    fn new(reader: R) -> Self {
+    fn new(reader: R, options: &ParseOptions) -> Self {
         PsshBoxIterator {
             reader,
+            options: options.clone(),
             buffer: Vec::new(),
-            buffer_pos: 0,
+            buffer_start: 0,
+            scan_pos: 0,
+            eof: false,
+            count: 0,
             pending_boxes: Vec::new(),
             read_buffer: vec![0; 8 * 1024],
         }
     }
 }
 
+impl<R: Read> PsshBoxIterator<R> {
+    // Discard the octets before keep_from, and append the next chunk from the reader.
+    fn fill(&mut self, keep_from: usize) -> io::Result<()> {
+        let keep_from = keep_from.min(self.buffer.len());
+        self.buffer.drain(..keep_from);
+        self.buffer_start += keep_from as u64;
+        self.scan_pos -= keep_from.min(self.scan_pos);
+        let bytes_read = self.reader.read(&mut self.read_buffer)?;
+        if bytes_read == 0 {
+            self.eof = true;
+        }
+        self.buffer.extend_from_slice(&self.read_buffer[..bytes_read]);
+        Ok(())
+    }
+
+    fn limit_error(&self, error: PsshError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
 impl<R> Iterator for PsshBoxIterator<R>
 where
     R: Read,
@@ -918,54 +1882,86 @@ where
             return Some(Ok(bx));
         }
         loop {
-            if self.buffer_pos > 0 {
-                self.buffer = self.buffer.split_off(self.buffer_pos);
-            }
-            let bytes_read = match self.reader.read(&mut self.read_buffer) {
-                Ok(n) => n,
-                Err(e) => return Some(Err(e)),
+            let Some(found) = self.buffer[self.scan_pos..].find(b"pssh") else {
+                if self.eof {
+                    return None;
+                }
+                // Keep the last octets of the buffer in case the b"pssh" cookie is at the buffer
+                // boundary, and the box size which would precede it.
+                self.scan_pos = self.buffer.len().saturating_sub(3);
+                if let Err(e) = self.fill(self.scan_pos.saturating_sub(4)) {
+                    return Some(Err(e));
+                }
+                continue;
             };
-            if self.buffer_pos == 0 && bytes_read == 0 {
-                return None;
+            let offset = self.scan_pos + found;
+            trace!("Found pssh cookie at offset {}", self.buffer_start + offset as u64);
+            if offset < 4 {
+                self.scan_pos = offset + 1;
+                continue;
             }
-            self.buffer.extend_from_slice(&self.read_buffer[..bytes_read]);
-            self.buffer_pos = 0;
-            if let Some(offset) = self.buffer.find(b"pssh") {
-                trace!("Found pssh cookie at offset {offset}");
-                if offset + 24 > self.buffer.len() {
-                    self.buffer_pos = offset + 4;
-                    continue;
-                }
-                if offset < 4 {
-                    self.buffer_pos = 4;
-                    continue;
+            let start = offset - 4;
+            // Wait until the box header (including a possible largesize) and the full box are
+            // available, or until the end of the stream for a box of size 0.
+            let to_end_of_stream = self.buffer[start..offset] == [0, 0, 0, 0] && !self.eof;
+            let needed = if to_end_of_stream {
+                usize::MAX
+            } else {
+                match declared_box_size(&self.buffer, start) {
+                    Some(size) => usize::try_from(size).unwrap_or(usize::MAX),
+                    None if self.buffer.len() < start + 16 && !self.eof => 16,
+                    None => {
+                        self.scan_pos = offset + 1;
+                        continue;
+                    },
                 }
-                let start = offset - 4;
-                let buffer_len = self.buffer.len();
-                let mut rdr = Cursor::new(&self.buffer[start..buffer_len]);
-                let size: u32 = rdr.read_u32::<BigEndian>().unwrap();
-                let end = start + size as usize;
-                if end > self.buffer.len() {
-                    self.buffer_pos = offset + 1;
-                    continue;
+            };
+            if start.saturating_add(needed) > self.buffer.len() {
+                let max_buffered = self.options.limits.max_buffered;
+                let buffered = self.buffer.len() - start;
+                // A box whose header declares a size above the limit is rejected without buffering
+                // it first.
+                if self.eof {
+                    self.scan_pos = offset + 1;
+                } else if buffered >= max_buffered || (!to_end_of_stream && needed > max_buffered) {
+                    self.scan_pos = offset + 1;
+                    let error = PsshError::LimitExceeded {
+                        offset: self.buffer_start + start as u64,
+                        limit: "buffered octets",
+                        value: if to_end_of_stream { buffered as u64 } else { needed as u64 },
+                        max: max_buffered as u64,
+                    };
+                    return Some(Err(self.limit_error(error)));
+                } else {
+                    self.scan_pos = offset;
+                    if let Err(e) = self.fill(start) {
+                        return Some(Err(e));
+                    }
                 }
-                if let Ok(pbv) = from_bytes(&self.buffer[start..end]) {
-                    self.buffer_pos = end;
-                    for pb in pbv {
-                        self.pending_boxes.push(pb);
+                continue;
+            }
+            let end = start + needed;
+            match from_bytes_with_options(&self.buffer[start..end], &self.options) {
+                Ok(pbv) => {
+                    self.scan_pos = end;
+                    self.count += pbv.len();
+                    if self.count > self.options.limits.max_boxes {
+                        self.eof = true;
+                        self.scan_pos = self.buffer.len();
+                        let error = PsshError::LimitExceeded {
+                            offset: self.buffer_start + start as u64,
+                            limit: "box count",
+                            value: self.count as u64,
+                            max: self.options.limits.max_boxes as u64,
+                        };
+                        return Some(Err(self.limit_error(error)));
                     }
+                    self.pending_boxes.extend(pbv.into_iter().rev());
                     if let Some(bx) = self.pending_boxes.pop() {
                         return Some(Ok(bx));
                     }
-                } else {
-                    self.buffer_pos = offset + 4;
-                }
-            } else {
-                // Try the last bit of the buffer in the next loop iteration, in case the b"pssh"
-                // cookie is at the buffer boundary.
-                if self.buffer.len() >= 3 {
-                    self.buffer_pos = self.buffer.len() - 3;
-                }
+                },
+                Err(_) => self.scan_pos = offset + 1,
             }
         }
     }
@@ -1027,5 +2023,18 @@ pub fn pprint(pssh: &PsshBox) {
                 println!("{}", String::from_utf8_lossy(&hxbuf));
             }
         },
+        PsshData::Unknown(pd) => {
+            println!("  PSSH data ({} octets)", pd.len());
+            if !pd.is_empty() {
+                println!("== Hexdump of pssh data ==");
+                let mut hxbuf = Vec::new();
+                hxdmp::hexdump(pd, &mut hxbuf).unwrap();
+                println!("{}", String::from_utf8_lossy(&hxbuf));
+            }
+        },
+        PsshData::Custom(pd) => {
+            println!("  {}", pssh.pssh_data);
+            println!("  JSON: {}", pd.value);
+        },
     }
 }
@@ -3,10 +3,11 @@
 use std::fmt;
 use serde::{Serialize, Deserialize};
 use base64::{engine, Engine};
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, Context};
 use serde_json::Value;
 use tracing::warn;
-use crate::ToBytes;
+use crate::{ToBytes, DRMKeyId, NAGRA_SYSTEM_ID};
+use crate::error::{PsshError, Result};
 
 // "Normal" base64 is not suitable for Nagra.
 const BASE64_URL_SAFE_FORGIVING:
@@ -31,17 +32,29 @@ impl fmt::Debug for NagraPsshData {
     }
 }
 
+impl NagraPsshData {
+    /// The key ID, if `key_id` is a well-formed UUID.
+    pub fn key_ids(&self) -> Vec<DRMKeyId> {
+        DRMKeyId::try_from(self.key_id.as_str()).into_iter().collect()
+    }
+}
+
 impl ToBytes for NagraPsshData {
-    fn to_bytes(&self) -> Vec<u8> {
+    fn write_to<W: std::io::Write>(&self, out: &mut W) -> Result<()> {
         // make sure we serialize without any spaces
         let json = format!("{{\"contentId\":\"{}\",\"keyId\":\"{}\"}}",
                            self.content_id, self.key_id);
-        BASE64_URL_SAFE_FORGIVING.encode(json).into_bytes()
+        Ok(out.write_all(BASE64_URL_SAFE_FORGIVING.encode(json).as_bytes())?)
     }
 }
 
 // The structure is similar to a JWT
 pub fn parse_pssh_data(buf: &[u8]) -> Result<NagraPsshData> {
+    decode_pssh_data(buf)
+        .map_err(|e| PsshError::payload(NAGRA_SYSTEM_ID, 0, e))
+}
+
+fn decode_pssh_data(buf: &[u8]) -> anyhow::Result<NagraPsshData> {
     let b64 = String::from_utf8(buf.to_vec())
         .context("decoding UTF-8")?;
     let json = BASE64_URL_SAFE_FORGIVING.decode(b64)
@@ -54,9 +67,11 @@ pub fn parse_pssh_data(buf: &[u8]) -> Result<NagraPsshData> {
                 let keys: Vec<_> = map.keys().collect();
                 warn!("unknown key in Nagra PSSH data, {keys:?}");
             }
-            let cid = map["contentId"].as_str()
+            let cid = map.get("contentId")
+                .and_then(Value::as_str)
                 .context("extracting contentId")?;
-            let kid = map["keyId"].as_str()
+            let kid = map.get("keyId")
+                .and_then(Value::as_str)
                 .context("extracting keyId")?;
             Ok(NagraPsshData {
                 content_id: String::from(cid),
@@ -9,16 +9,21 @@
 
 
 use std::fmt;
-use std::io::{Read, Cursor};
+use std::collections::HashMap;
+use std::io::{self, Read, Cursor};
+use std::ops::Range;
 use std::fmt::{Error, Write};
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use serde::{Serialize, Deserialize};
 use serde_with::{serde_as, skip_serializing_none};
 use serde_with::base64::Base64;
-use num_enum::TryFromPrimitive;
+use num_enum::{FromPrimitive, IntoPrimitive};
+use quick_xml::events::Event;
 use tracing::trace;
-use anyhow::{Result, Context, anyhow};
-use crate::ToBytes;
+use anyhow::{Context, anyhow};
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use crate::{ToBytes, DRMKeyId, PsshBox, PsshData, PLAYREADY_SYSTEM_ID};
+use crate::error::{PsshError, Result};
 
 
 struct Utf16Writer(Vec<u16>);
@@ -37,8 +42,8 @@ impl Write for Utf16Writer {
 
 pub fn to_utf16(xml: &str) -> Vec<u16> {
     let mut writer = Utf16Writer(Vec::new());
-    write!(writer, "{xml}")
-        .expect("writing XML as UTF-16");
+    // Utf16Writer never returns an error.
+    let _ = write!(writer, "{xml}");
     writer.0
 }
 
@@ -56,20 +61,43 @@ where S: serde::Serializer {
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(default)]
 pub struct PlayReadyKid {
-    #[serde(rename = "@value")]
-    pub value: Option<String>,
     #[serde(rename = "@ALGID")]
     pub algid: Option<String>,
     #[serde_as(as = "Option<Base64>")]
     #[serde(rename = "@CHECKSUM")]
     pub checksum: Option<Vec<u8>>,
+    #[serde(rename = "@VALUE", alias = "@value")]
+    pub value: Option<String>,
     #[serde_as(as = "Base64")]
     #[serde(rename = "$text")]
     pub content: Vec<u8>,
 }
 
-// Note that some fields overlap with the PlayReadyKid type, depending on whether we have a version
-// 4.0.0.0 header or a 4.2.0.0 header.
+impl PlayReadyKid {
+    /// The key ID, from the element content or the `value` attribute. PlayReady encodes KIDs as
+    /// GUIDs whose first three fields are little endian; they are converted to the big-endian
+    /// (UUID) byte order used in the PSSH box header.
+    pub fn key_id(&self) -> Option<DRMKeyId> {
+        let octets = if self.content.len() == 16 {
+            self.content.clone()
+        } else {
+            BASE64_STANDARD.decode(self.value.as_deref()?).ok()?
+        };
+        Some(DRMKeyId::from_guid_bytes(octets.try_into().ok()?))
+    }
+}
+
+/// The KIDS element of a version 4.2 or 4.3 header.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayReadyKids {
+    #[serde(rename = "KID")]
+    pub kid: Vec<PlayReadyKid>,
+}
+
+// The content of PROTECTINFO depends on the header version: KEYLEN and ALGID in a 4.0.0.0 header,
+// a single KID element in a 4.1.0.0 header, and a KIDS element containing KID elements in 4.2.0.0
+// and 4.3.0.0 headers.
 #[skip_serializing_none]
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(default)]
@@ -78,8 +106,22 @@ pub struct ProtectInfo {
     pub keylen: Option<u32>,
     #[serde(rename = "ALGID")]
     pub algid: Option<String>,
+    #[serde(rename = "KID")]
+    pub kid: Option<PlayReadyKid>,
     #[serde(rename = "KIDS")]
-    pub kids: Vec<PlayReadyKid>,
+    pub kids: Option<PlayReadyKids>,
+}
+
+impl ProtectInfo {
+    /// The KID elements in PROTECTINFO, whether directly (version 4.1) or in KIDS (versions 4.2
+    /// and 4.3).
+    pub fn kids(&self) -> impl Iterator<Item = &PlayReadyKid> {
+        self.kid.iter().chain(self.kids.iter().flat_map(|k| k.kid.iter()))
+    }
+
+    pub fn kids_mut(&mut self) -> impl Iterator<Item = &mut PlayReadyKid> {
+        self.kid.iter_mut().chain(self.kids.iter_mut().flat_map(|k| k.kid.iter_mut()))
+    }
 }
 
 
@@ -89,10 +131,10 @@ pub struct ProtectInfo {
 #[serde(rename = "WRMDATA")]
 #[serde(default)]
 pub struct WRMData {
-    #[serde(rename = "KID")]
-    pub kids: Vec<PlayReadyKid>,
     #[serde(rename = "PROTECTINFO")]
     pub protect_info: Option<ProtectInfo>,
+    #[serde(rename = "KID")]
+    pub kids: Vec<PlayReadyKid>,
     #[serde_as(as = "Option<Base64>")]
     #[serde(rename = "CHECKSUM")]
     pub checksum: Option<Vec<u8>>,
@@ -105,11 +147,18 @@ pub struct WRMData {
     /// base64-encoded guid
     #[serde(rename = "DS_ID")]
     pub ds_id: Option<String>,
-    // These are not parsed via quick-xml, because they often contain invalid XML.
-    #[serde(rename(serialize = "CUSTOMATTRIBUTES"))]
+    // These are not parsed via quick-xml, because they often contain invalid XML. The content is
+    // kept as raw XML. The element is removed before the XML is deserialized, so the name is only
+    // read from other serde formats.
+    #[serde(rename = "CUSTOMATTRIBUTES", alias = "custom_attributes")]
     pub custom_attributes: Option<String>,
     #[serde(rename = "DECRYPTORSETUP")]
     pub decryptor_setup: Option<String>,
+    /// Child elements of DATA which aren't defined above, as raw XML. They are emitted verbatim
+    /// at the end of the DATA element. When parsing XML they are collected by scanning the DATA
+    /// element, so this field is only read from other serde formats such as JSON.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unknown_elements: Vec<String>,
 }
 
 #[skip_serializing_none]
@@ -125,39 +174,98 @@ pub struct WRMHeader {
     pub data: WRMData,
 }
 
+// Serialized in place of the content of CUSTOMATTRIBUTES, which is then substituted as raw XML.
+// quick-xml doesn't escape characters from the Private Use Area.
+const CUSTOMATTRIBUTES_PLACEHOLDER: &str = "\u{E000}CUSTOMATTRIBUTES\u{E000}";
+
+impl WRMHeader {
+    /// The XML serialization of this header. The content of CUSTOMATTRIBUTES and the unknown child
+    /// elements of DATA are emitted verbatim rather than as escaped text.
+    pub fn to_xml(&self) -> Result<String> {
+        let mut header = self.clone();
+        let custom_attributes = header.data.custom_attributes.as_mut()
+            .map(|ca| std::mem::replace(ca, String::from(CUSTOMATTRIBUTES_PLACEHOLDER)));
+        let unknown_elements = std::mem::take(&mut header.data.unknown_elements);
+        let mut xml = String::new();
+        let mut ser = quick_xml::se::Serializer::new(&mut xml);
+        // Empty elements are written as <KID ...></KID>, as in the PlayReady Header Specification.
+        ser.expand_empty_elements(true);
+        header.serialize(ser)
+            .map_err(|e| PsshError::Encode { system: PLAYREADY_SYSTEM_ID, source: Box::new(e) })?;
+        if let Some(pos) = xml.rfind("</DATA>") {
+            xml.insert_str(pos, &unknown_elements.concat());
+        }
+        if let Some(ca) = custom_attributes {
+            xml = xml.replacen(CUSTOMATTRIBUTES_PLACEHOLDER, &ca, 1);
+        }
+        Ok(xml)
+    }
+}
+
 impl ToBytes for WRMHeader {
-    fn to_bytes(&self) -> Vec<u8> {
-        let xml = quick_xml::se::to_string(self)
-            .expect("parsing WRMHeader XML");
-        let mut out = Vec::<u8>::new();
+    fn write_to<W: io::Write>(&self, out: &mut W) -> Result<()> {
+        let xml = self.to_xml()?;
         for u in to_utf16(&xml) {
-            let _ = out.write_u16::<LittleEndian>(u);
+            out.write_u16::<LittleEndian>(u)?;
         }
-        out
+        Ok(())
     }
 }
 
-#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TryFromPrimitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, FromPrimitive, IntoPrimitive)]
 #[repr(u16)]
 pub enum PlayReadyRecordType {
-    #[default]
     RightsManagement = 1,
     Reserved = 2,
     EmbeddedLicenseStore = 3,
+    /// A record type which is not defined by the PlayReady Header specification.
+    #[num_enum(catch_all)]
+    Unknown(u16),
+}
+
+// Not derived, because num_enum uses the #[default] attribute as an alternative to catch_all.
+#[allow(clippy::derivable_impls)]
+impl Default for PlayReadyRecordType {
+    fn default() -> Self {
+        PlayReadyRecordType::RightsManagement
+    }
 }
 
 impl ToBytes for PlayReadyRecordType {
-    fn to_bytes(&self) -> Vec<u8> {
-        let mut buf = Vec::new();
-        let _ = buf.write_u16::<LittleEndian>(*self as u16);
-        buf
+    fn write_to<W: io::Write>(&self, out: &mut W) -> Result<()> {
+        Ok(out.write_u16::<LittleEndian>(u16::from(*self))?)
+    }
+}
+
+/// The value of a record in a PlayReady Header Object.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlayReadyRecordValue {
+    /// The WRMHEADER of a Rights Management record.
+    WRMHeader(WRMHeader),
+    /// The octets of an Embedded License Store, reserved or unknown record, kept verbatim.
+    Raw(Vec<u8>),
+}
+
+impl Default for PlayReadyRecordValue {
+    fn default() -> Self {
+        PlayReadyRecordValue::WRMHeader(WRMHeader::default())
+    }
+}
+
+impl ToBytes for PlayReadyRecordValue {
+    fn write_to<W: io::Write>(&self, out: &mut W) -> Result<()> {
+        match self {
+            PlayReadyRecordValue::WRMHeader(h) => h.write_to(out),
+            PlayReadyRecordValue::Raw(octets) => Ok(out.write_all(octets)?),
+        }
     }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PlayReadyRecord {
     pub record_type: PlayReadyRecordType,
-    pub record_value: WRMHeader,
+    pub record_value: PlayReadyRecordValue,
 }
 
 impl PlayReadyRecord {
@@ -168,38 +276,110 @@ impl PlayReadyRecord {
         rv.version = String::from("4.0.0.0");
         PlayReadyRecord {
             record_type: PlayReadyRecordType::RightsManagement,
-            record_value: rv,
+            record_value: PlayReadyRecordValue::WRMHeader(rv),
         }
     }
+
+    /// The WRMHEADER, if this is a Rights Management record.
+    pub fn wrm_header(&self) -> Option<&WRMHeader> {
+        match &self.record_value {
+            PlayReadyRecordValue::WRMHeader(h) => Some(h),
+            PlayReadyRecordValue::Raw(_) => None,
+        }
+    }
+
+    /// The WRMHEADER, if this is a Rights Management record, for modification.
+    pub fn wrm_header_mut(&mut self) -> Option<&mut WRMHeader> {
+        match &mut self.record_value {
+            PlayReadyRecordValue::WRMHeader(h) => Some(h),
+            PlayReadyRecordValue::Raw(_) => None,
+        }
+    }
+}
+
+// A length which must fit in a field of type T of the PlayReady Header Object.
+fn length_field<T: TryFrom<usize> + Into<u64>>(field: &'static str, length: usize, max: T) -> Result<T> {
+    T::try_from(length)
+        .map_err(|_| PsshError::LengthOverflow { field, length: length as u64, max: max.into() })
 }
 
 impl ToBytes for PlayReadyRecord {
-    fn to_bytes(&self) -> Vec<u8> {
-        let mut buf = Vec::new();
-        buf.append(&mut self.record_type.to_bytes());
-        let mut val_bytes = self.record_value.to_bytes();
-        let _ = buf.write_u16::<LittleEndian>(val_bytes.len().try_into().unwrap());
-        buf.append(&mut val_bytes);
-        buf
+    fn write_to<W: io::Write>(&self, out: &mut W) -> Result<()> {
+        let val_bytes = self.record_value.try_to_bytes()?;
+        let record_length = length_field("PlayReady record length", val_bytes.len(), u16::MAX)?;
+        self.record_type.write_to(out)?;
+        out.write_u16::<LittleEndian>(record_length)?;
+        out.write_all(&val_bytes)?;
+        Ok(())
     }
 }
 
-fn parse_playready_record(rdr: &mut Cursor<&[u8]>) -> Result<PlayReadyRecord> {
-    let record_type = rdr.read_u16::<LittleEndian>()
-        .context("reading record_type field")?;
-    if record_type != 1 {
-        return Err(anyhow!("can't parse PlayReady record of type {record_type}"));
+// The child elements of DATA which are parsed into a WRMData.
+const KNOWN_DATA_ELEMENTS: [&[u8]; 7] =
+    [b"KID", b"PROTECTINFO", b"CHECKSUM", b"LA_URL", b"LUI_URL", b"DS_ID", b"DECRYPTORSETUP"];
+
+// The byte ranges in xml of the child elements of WRMHEADER/DATA which aren't parsed into a WRMData.
+fn unknown_data_elements(xml: &str) -> anyhow::Result<Vec<Range<usize>>> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut spans = Vec::new();
+    let mut depth = 0;
+    let mut in_data = false;
+    loop {
+        let start = usize::try_from(reader.buffer_position())?;
+        match reader.read_event()? {
+            Event::Start(e) => {
+                depth += 1;
+                let name = e.local_name();
+                if depth == 2 && name.as_ref() == b"DATA" {
+                    in_data = true;
+                } else if in_data && depth == 3 && !KNOWN_DATA_ELEMENTS.contains(&name.as_ref()) {
+                    reader.read_to_end(e.name())?;
+                    depth -= 1;
+                    spans.push(start..usize::try_from(reader.buffer_position())?);
+                }
+            },
+            Event::Empty(e) if in_data && depth == 2 &&
+                !KNOWN_DATA_ELEMENTS.contains(&e.local_name().as_ref()) =>
+            {
+                spans.push(start..usize::try_from(reader.buffer_position())?);
+            },
+            Event::End(_) => {
+                if depth == 2 {
+                    in_data = false;
+                }
+                depth -= 1;
+            },
+            Event::Eof => break,
+            _ => (),
+        }
     }
+    Ok(spans)
+}
+
+fn parse_playready_record(rdr: &mut Cursor<&[u8]>) -> anyhow::Result<PlayReadyRecord> {
+    let record_type = PlayReadyRecordType::from(rdr.read_u16::<LittleEndian>()
+        .context("reading record_type field")?);
     let record_length = rdr.read_u16::<LittleEndian>()
         .context("reading record_length field")?;
     let mut wrmh_u8 = Vec::new();
     rdr.take(record_length.into()).read_to_end(&mut wrmh_u8)?;
+    if record_type != PlayReadyRecordType::RightsManagement {
+        if wrmh_u8.len() != usize::from(record_length) {
+            return Err(anyhow!("truncated PlayReady record of type {}", u16::from(record_type)));
+        }
+        return Ok(PlayReadyRecord { record_type, record_value: PlayReadyRecordValue::Raw(wrmh_u8) });
+    }
+    if wrmh_u8.len() % 2 != 0 {
+        return Err(anyhow!("odd length {} for UTF-16 record value", wrmh_u8.len()));
+    }
     let wrmh_u16 = wrmh_u8
-        .chunks(2)
-        .map(|e| u16::from_le_bytes(e.try_into().unwrap()))
+        .chunks_exact(2)
+        .map(|e| u16::from_le_bytes([e[0], e[1]]))
         .collect::<Vec<_>>();
-    let mut xml = String::from_utf16(&wrmh_u16)
+    let xml = String::from_utf16(&wrmh_u16)
         .context("decoding UTF-16")?;
+    // Some packagers include a byte order mark or NUL padding.
+    let mut xml = String::from(xml.strip_prefix('\u{FEFF}').unwrap_or(&xml).trim_end_matches('\0'));
     // Extract a possible <CUSTOMATTRIBUTES>...</CUSTOMATTRIBUTES> in the input, because it tends
     // not to contain valid XML (undeclared namespaces, in particular) and makes the XML parsing
     // fail. We insert it as a string in the parsed struct.
@@ -221,13 +401,19 @@ fn parse_playready_record(rdr: &mut Cursor<&[u8]>) -> Result<PlayReadyRecord> {
             }
         }
     }
+    let mut unknown_elements = Vec::new();
+    for span in unknown_data_elements(&xml).context("parsing PlayReady XML")?.into_iter().rev() {
+        unknown_elements.insert(0, xml[span.clone()].to_string());
+        xml.replace_range(span, "");
+    }
     let xd = &mut quick_xml::de::Deserializer::from_str(&xml);
     let mut wrm_header: WRMHeader = serde_path_to_error::deserialize(xd)
         .context("parsing PlayReady XML")?;
     wrm_header.data.custom_attributes = custom_attributes;
+    wrm_header.data.unknown_elements = unknown_elements;
     Ok(PlayReadyRecord {
-        record_type: PlayReadyRecordType::try_from(record_type)?,
-        record_value: wrm_header,
+        record_type,
+        record_value: PlayReadyRecordValue::WRMHeader(wrm_header),
     })
 }
 
@@ -243,16 +429,31 @@ impl PlayReadyPsshData {
         empty.record.push(empty_record);
         empty
     }
+
+    /// The key IDs in the KID elements of all the Rights Management records.
+    pub fn key_ids(&self) -> Vec<DRMKeyId> {
+        let mut kids = Vec::new();
+        for wrmh in self.record.iter().filter_map(PlayReadyRecord::wrm_header) {
+            let data = &wrmh.data;
+            kids.extend(data.kids.iter().filter_map(PlayReadyKid::key_id));
+            if let Some(pi) = &data.protect_info {
+                kids.extend(pi.kids().filter_map(PlayReadyKid::key_id));
+            }
+        }
+        kids
+    }
 }
 
 impl fmt::Debug for PlayReadyPsshData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut items = Vec::new();
         for r in &self.record {
-            if r.record_type == PlayReadyRecordType::RightsManagement {
-                let xml = quick_xml::se::to_string(&r.record_value)
-                    .map_err(|_| fmt::Error)?;
-                items.push(format!("RightsManagementRecord: {xml}"));
+            if let Some(wrmh) = r.wrm_header() {
+                // Formatting must not fail, since Display implementations which use this panic.
+                match wrmh.to_xml() {
+                    Ok(xml) => items.push(format!("RightsManagementRecord: {xml}")),
+                    Err(e) => items.push(format!("PlayReadyPsshData<unserializable: {e}>")),
+                }
             } else {
                 items.push(format!("{r:?}"));
             }
@@ -263,37 +464,458 @@ impl fmt::Debug for PlayReadyPsshData {
 
 
 impl ToBytes for PlayReadyPsshData {
-    #[allow(unused_must_use)]
-    fn to_bytes(&self) -> Vec<u8> {
-        let mut buf = Vec::<u8>::new();
+    fn write_to<W: io::Write>(&self, out: &mut W) -> Result<()> {
         let mut records_buf = Vec::<u8>::new();
         for r in &self.record {
-            trace!("Serializing playready, record of length {}", r.to_bytes().len());
-            records_buf.append(&mut r.to_bytes());
+            r.write_to(&mut records_buf)?;
         }
-        let total_length: u32 = 4 + 2 + records_buf.len() as u32;
-        buf.write_u32::<LittleEndian>(total_length).unwrap();
-        buf.write_u16::<LittleEndian>(self.record.len().try_into().unwrap()).unwrap();
-        buf.append(&mut records_buf);
-        buf
+        trace!("Serializing playready, records of length {}", records_buf.len());
+        let total_length = length_field("PlayReady Header Object length", 4 + 2 + records_buf.len(), u32::MAX)?;
+        let record_count = length_field("PlayReady record count", self.record.len(), u16::MAX)?;
+        out.write_u32::<LittleEndian>(total_length)?;
+        out.write_u16::<LittleEndian>(record_count)?;
+        out.write_all(&records_buf)?;
+        Ok(())
     }
 }
 
+/// Parse a PlayReady Header Object. The offset in any error is the position in `buf` at which
+/// decoding failed.
 pub fn parse_pssh_data(buf: &[u8]) -> Result<PlayReadyPsshData> {
     let mut rdr = Cursor::new(buf);
-    let blen = buf.len() as u32;
+    let mut offset = 0;
+    read_pssh_data(&mut rdr, &mut offset)
+        .map_err(|e| PsshError::payload(PLAYREADY_SYSTEM_ID, offset, e))
+}
+
+fn read_pssh_data(rdr: &mut Cursor<&[u8]>, offset: &mut u64) -> anyhow::Result<PlayReadyPsshData> {
+    let blen = rdr.get_ref().len() as u32;
     let length = rdr.read_u32::<LittleEndian>()
         .context("reading pssh data length")?;
     if length != blen {
         return Err(anyhow!("header length {length} different from buffer length {blen}"));
     }
+    *offset = rdr.position();
     let record_count = rdr.read_u16::<LittleEndian>()
         .context("reading pssh data record count")?;
     let mut records = Vec::new();
     for _ in 1..=record_count {
-        records.push(parse_playready_record(&mut rdr)?);
+        *offset = rdr.position();
+        records.push(parse_playready_record(rdr)?);
     }
     Ok(PlayReadyPsshData {
         record: records,
     })
 }
+
+
+/// A version of the PlayReady Header (WRMHEADER) format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PlayReadyHeaderVersion {
+    V4_0,
+    V4_1,
+    V4_2,
+    V4_3,
+}
+
+impl PlayReadyHeaderVersion {
+    /// The value of the `version` attribute of the WRMHEADER element.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlayReadyHeaderVersion::V4_0 => "4.0.0.0",
+            PlayReadyHeaderVersion::V4_1 => "4.1.0.0",
+            PlayReadyHeaderVersion::V4_2 => "4.2.0.0",
+            PlayReadyHeaderVersion::V4_3 => "4.3.0.0",
+        }
+    }
+}
+
+/// The content encryption algorithm specified by the ALGID of a PlayReady Header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PlayReadyAlgorithm {
+    /// AES in counter mode (the `cenc` scheme).
+    AesCtr,
+    /// AES in CBC mode (the `cbcs` scheme), allowed from header version 4.3.
+    AesCbc,
+    /// The legacy Cocktail cipher.
+    Cocktail,
+}
+
+impl PlayReadyAlgorithm {
+    /// The value of the ALGID element or attribute.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlayReadyAlgorithm::AesCtr => "AESCTR",
+            PlayReadyAlgorithm::AesCbc => "AESCBC",
+            PlayReadyAlgorithm::Cocktail => "COCKTAIL",
+        }
+    }
+
+    /// The algorithm specified by an ALGID value, if it is known.
+    pub fn from_algid(algid: &str) -> Option<PlayReadyAlgorithm> {
+        match algid.trim() {
+            a if a.eq_ignore_ascii_case("AESCTR") => Some(PlayReadyAlgorithm::AesCtr),
+            a if a.eq_ignore_ascii_case("AESCBC") => Some(PlayReadyAlgorithm::AesCbc),
+            a if a.eq_ignore_ascii_case("COCKTAIL") => Some(PlayReadyAlgorithm::Cocktail),
+            _ => None,
+        }
+    }
+
+    // The value of the KEYLEN element of a version 4.0 header.
+    fn key_length(&self) -> u32 {
+        match self {
+            PlayReadyAlgorithm::Cocktail => 7,
+            _ => 16,
+        }
+    }
+}
+
+/// A builder for PlayReady Rights Management headers, which places each field where the PlayReady
+/// Header specification requires for the target version and rejects combinations which that
+/// version does not allow.
+///
+/// ```
+/// use pssh_box::DRMKeyId;
+/// use pssh_box::playready::{PlayReadyHeaderBuilder, PlayReadyHeaderVersion, PlayReadyAlgorithm};
+///
+/// let kid = DRMKeyId::from_hex("00112233445566778899aabbccddeeff").unwrap();
+/// let pssh = PlayReadyHeaderBuilder::new(PlayReadyHeaderVersion::V4_3)
+///     .key_id(kid)
+///     .algorithm(PlayReadyAlgorithm::AesCbc)
+///     .la_url("https://example.com/rightsmanager.asmx")
+///     .build_pssh_box()
+///     .unwrap();
+/// assert_eq!(pssh.key_ids, vec![kid]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct PlayReadyHeaderBuilder {
+    version: PlayReadyHeaderVersion,
+    key_ids: Vec<DRMKeyId>,
+    content_keys: HashMap<DRMKeyId, Vec<u8>>,
+    key_seed: Option<Vec<u8>>,
+    algorithm: Option<PlayReadyAlgorithm>,
+    la_url: Option<String>,
+    lui_url: Option<String>,
+    ds_id: Option<String>,
+    custom_attributes: Option<String>,
+    decryptor_setup: Option<String>,
+}
+
+fn unsupported(reason: &'static str) -> PsshError {
+    PsshError::UnsupportedOperation { system: PLAYREADY_SYSTEM_ID, operation: "build PlayReady header", reason }
+}
+
+impl PlayReadyHeaderBuilder {
+    pub fn new(version: PlayReadyHeaderVersion) -> PlayReadyHeaderBuilder {
+        PlayReadyHeaderBuilder {
+            version,
+            key_ids: Vec::new(),
+            content_keys: HashMap::new(),
+            key_seed: None,
+            algorithm: None,
+            la_url: None,
+            lui_url: None,
+            ds_id: None,
+            custom_attributes: None,
+            decryptor_setup: None,
+        }
+    }
+
+    /// Add a key ID. Version 4.0 headers require exactly one KID, and version 4.1 headers allow at
+    /// most one.
+    pub fn key_id(mut self, kid: DRMKeyId) -> Self {
+        self.key_ids.push(kid);
+        self
+    }
+
+    /// Add a key ID (unless already added) and its content `key`, which is used to compute the KID
+    /// checksum. The key is 16 octets for AES and 7 octets for COCKTAIL.
+    pub fn content_key(mut self, kid: DRMKeyId, key: &[u8]) -> Self {
+        if !self.key_ids.contains(&kid) {
+            self.key_ids.push(kid);
+        }
+        self.content_keys.insert(kid, key.to_vec());
+        self
+    }
+
+    /// Compute the checksums of KIDs whose content key was not given by
+    /// [`content_key`](PlayReadyHeaderBuilder::content_key) from keys derived from `key_seed`.
+    pub fn key_seed(mut self, key_seed: &[u8]) -> Self {
+        self.key_seed = Some(key_seed.to_vec());
+        self
+    }
+
+    /// The encryption algorithm of the content. Defaults to AESCTR, except for version 4.3 headers
+    /// where the ALGID is optional and is omitted if not specified.
+    pub fn algorithm(mut self, algorithm: PlayReadyAlgorithm) -> Self {
+        self.algorithm = Some(algorithm);
+        self
+    }
+
+    /// The URL of the license acquisition web service (LA_URL).
+    pub fn la_url(mut self, url: &str) -> Self {
+        self.la_url = Some(String::from(url));
+        self
+    }
+
+    /// The URL of the non-silent license acquisition web page (LUI_URL).
+    pub fn lui_url(mut self, url: &str) -> Self {
+        self.lui_url = Some(String::from(url));
+        self
+    }
+
+    /// The service ID of the domain service (DS_ID), a base64-encoded GUID.
+    pub fn ds_id(mut self, ds_id: &str) -> Self {
+        self.ds_id = Some(String::from(ds_id));
+        self
+    }
+
+    /// Content of the CUSTOMATTRIBUTES element, as raw XML.
+    pub fn custom_attributes(mut self, attributes: &str) -> Self {
+        self.custom_attributes = Some(String::from(attributes));
+        self
+    }
+
+    /// Value of the DECRYPTORSETUP element (`ONDEMAND`), allowed from header version 4.1.
+    pub fn decryptor_setup(mut self, setup: &str) -> Self {
+        self.decryptor_setup = Some(String::from(setup));
+        self
+    }
+
+    /// Build the WRMHEADER, or fail with [`PsshError::UnsupportedOperation`] if the fields are not
+    /// allowed by the target version.
+    pub fn build(self) -> Result<WRMHeader> {
+        use PlayReadyHeaderVersion::*;
+        let algorithm = match (self.version, self.algorithm) {
+            (V4_3, a) => a,
+            (_, Some(PlayReadyAlgorithm::AesCbc)) =>
+                return Err(unsupported("AESCBC requires header version 4.3")),
+            (_, a) => Some(a.unwrap_or(PlayReadyAlgorithm::AesCtr)),
+        };
+        match self.version {
+            V4_0 if self.key_ids.len() != 1 =>
+                return Err(unsupported("a version 4.0 header requires exactly one KID")),
+            V4_1 if self.key_ids.len() > 1 =>
+                return Err(unsupported("a version 4.1 header allows at most one KID")),
+            _ => (),
+        }
+        if self.version == V4_0 && self.decryptor_setup.is_some() {
+            return Err(unsupported("DECRYPTORSETUP requires header version 4.1"));
+        }
+        let checksum_algorithm = algorithm.unwrap_or(PlayReadyAlgorithm::AesCtr);
+        let checksum = |kid: &DRMKeyId| -> Result<Option<Vec<u8>>> {
+            let key = match (self.content_keys.get(kid), &self.key_seed) {
+                (Some(key), _) => key.clone(),
+                (None, Some(seed)) => derive_content_key(seed, *kid)?.to_vec(),
+                (None, None) => return Ok(None),
+            };
+            compute_checksum(*kid, &key, checksum_algorithm).map(Some)
+        };
+        let mut data = WRMData {
+            la_url: self.la_url,
+            lui_url: self.lui_url,
+            ds_id: self.ds_id,
+            custom_attributes: self.custom_attributes,
+            decryptor_setup: self.decryptor_setup,
+            ..Default::default()
+        };
+        if self.version == V4_0 {
+            // In version 4.0 the KID is the content of a KID element in DATA, and the algorithm is
+            // specified in PROTECTINFO.
+            let algorithm = algorithm.unwrap_or(PlayReadyAlgorithm::AesCtr);
+            data.kids = self.key_ids.iter()
+                .map(|k| PlayReadyKid { content: k.to_guid_bytes().to_vec(), ..Default::default() })
+                .collect();
+            data.checksum = checksum(&self.key_ids[0])?;
+            data.protect_info = Some(ProtectInfo {
+                keylen: Some(algorithm.key_length()),
+                algid: Some(String::from(algorithm.as_str())),
+                ..Default::default()
+            });
+        } else if !self.key_ids.is_empty() {
+            // From version 4.1 each KID is in PROTECTINFO, with the algorithm as an attribute. A 4.1
+            // header has a single KID element in PROTECTINFO, and later versions a KIDS element.
+            let mut kids = self.key_ids.iter()
+                .map(|k| Ok(PlayReadyKid {
+                    value: Some(BASE64_STANDARD.encode(k.to_guid_bytes())),
+                    algid: algorithm.map(|a| String::from(a.as_str())),
+                    checksum: checksum(k)?,
+                    ..Default::default()
+                }))
+                .collect::<Result<Vec<_>>>()?;
+            let protect_info = if self.version == V4_1 {
+                ProtectInfo { kid: kids.pop(), ..Default::default() }
+            } else {
+                ProtectInfo { kids: Some(PlayReadyKids { kid: kids }), ..Default::default() }
+            };
+            data.protect_info = Some(protect_info);
+        }
+        Ok(WRMHeader {
+            xmlns: Some(String::from("http://schemas.microsoft.com/DRM/2007/03/PlayReadyHeader")),
+            version: String::from(self.version.as_str()),
+            data,
+        })
+    }
+
+    /// Build PlayReady PSSH data containing a single Rights Management record.
+    pub fn build_pssh_data(self) -> Result<PlayReadyPsshData> {
+        let record = PlayReadyRecord {
+            record_type: PlayReadyRecordType::RightsManagement,
+            record_value: PlayReadyRecordValue::WRMHeader(self.build()?),
+        };
+        Ok(PlayReadyPsshData { record: vec![record] })
+    }
+
+    /// Build a v1 PlayReady PSSH box whose header lists the key IDs.
+    pub fn build_pssh_box(self) -> Result<PsshBox> {
+        let key_ids = self.key_ids.clone();
+        let mut bx = PsshBox::new_playready();
+        bx.pssh_data = PsshData::PlayReady(self.build_pssh_data()?);
+        bx.key_ids = key_ids;
+        Ok(bx)
+    }
+}
+
+
+/// Compute the checksum of the key ID `kid` for the content `key`, as specified by the PlayReady
+/// Header specification. For AESCTR and AESCBC, the key is 16 octets and the checksum is the first 8
+/// octets of the KID (in GUID byte order) encrypted with AES-ECB. For COCKTAIL, the key is 7 octets
+/// and the checksum is the first 7 octets of five iterations of SHA-1 over the key.
+pub fn compute_checksum(kid: DRMKeyId, key: &[u8], algorithm: PlayReadyAlgorithm) -> Result<Vec<u8>> {
+    match algorithm {
+        PlayReadyAlgorithm::AesCtr | PlayReadyAlgorithm::AesCbc => {
+            use aes::cipher::{BlockEncrypt, KeyInit};
+            let cipher = aes::Aes128::new_from_slice(key)
+                .map_err(|_| PsshError::InvalidLength { offset: 0, expected: 16, found: key.len() })?;
+            let mut block = aes::Block::from(kid.to_guid_bytes());
+            cipher.encrypt_block(&mut block);
+            Ok(block[..8].to_vec())
+        },
+        PlayReadyAlgorithm::Cocktail => {
+            use sha1::{Digest, Sha1};
+            if key.len() != 7 {
+                return Err(PsshError::InvalidLength { offset: 0, expected: 7, found: key.len() });
+            }
+            let mut buf = [0u8; 21];
+            buf[..7].copy_from_slice(key);
+            for _ in 0..5 {
+                let digest = Sha1::digest(buf);
+                buf[..20].copy_from_slice(&digest);
+            }
+            Ok(buf[..7].to_vec())
+        },
+    }
+}
+
+/// A KID whose checksum in a PlayReady header doesn't match the checksum computed from its content
+/// key, reported by [`PlayReadyPsshData::verify_checksums`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumMismatch {
+    pub key_id: DRMKeyId,
+    /// The checksum computed from the content key.
+    pub expected: Vec<u8>,
+    /// The checksum in the PlayReady header.
+    pub found: Vec<u8>,
+}
+
+// The algorithm named by an ALGID, where an absent ALGID (allowed in version 4.3 headers) has the
+// same checksum as AESCTR.
+fn checksum_algorithm(algid: Option<&str>) -> Result<PlayReadyAlgorithm> {
+    match algid {
+        None => Ok(PlayReadyAlgorithm::AesCtr),
+        Some(a) => PlayReadyAlgorithm::from_algid(a)
+            .ok_or(unsupported_checksum("unknown ALGID")),
+    }
+}
+
+fn unsupported_checksum(reason: &'static str) -> PsshError {
+    PsshError::UnsupportedOperation { system: PLAYREADY_SYSTEM_ID, operation: "verify checksum", reason }
+}
+
+impl PlayReadyPsshData {
+    /// Check the checksums of the KIDs in the Rights Management records against the content `keys`,
+    /// returning the KIDs whose checksum is wrong. KIDs without a checksum, or whose content key is
+    /// not in `keys`, are not checked, and keys for KIDs which are not in the header are ignored, so
+    /// `keys` can be a full key store. Fails with [`PsshError::InvalidLength`] if a content key has
+    /// the wrong length for the algorithm of its KID, and with [`PsshError::UnsupportedOperation`]
+    /// if the algorithm is unknown.
+    pub fn verify_checksums(&self, keys: &HashMap<DRMKeyId, Vec<u8>>) -> Result<Vec<ChecksumMismatch>> {
+        let mut mismatches = Vec::new();
+        let mut check = |kid: Option<DRMKeyId>, found: Option<&Vec<u8>>, algid: Option<&str>| -> Result<()> {
+            let (Some(kid), Some(found)) = (kid, found) else { return Ok(()) };
+            let Some(key) = keys.get(&kid) else { return Ok(()) };
+            let expected = compute_checksum(kid, key, checksum_algorithm(algid)?)?;
+            if &expected != found {
+                mismatches.push(ChecksumMismatch { key_id: kid, expected, found: found.clone() });
+            }
+            Ok(())
+        };
+        for wrmh in self.record.iter().filter_map(PlayReadyRecord::wrm_header) {
+            let data = &wrmh.data;
+            let pi_algid = data.protect_info.as_ref().and_then(|pi| pi.algid.as_deref());
+            // In a version 4.0 header, the checksum of the KID is in the CHECKSUM element of DATA.
+            if let Some(kid) = data.kids.first() {
+                let found = kid.checksum.as_ref().or(data.checksum.as_ref());
+                check(kid.key_id(), found, kid.algid.as_deref().or(pi_algid))?;
+            }
+            for kid in data.kids.iter().skip(1) {
+                check(kid.key_id(), kid.checksum.as_ref(), kid.algid.as_deref().or(pi_algid))?;
+            }
+            if let Some(pi) = &data.protect_info {
+                for kid in pi.kids() {
+                    check(kid.key_id(), kid.checksum.as_ref(), kid.algid.as_deref().or(pi_algid))?;
+                }
+            }
+        }
+        Ok(mismatches)
+    }
+
+    /// Check the checksums of the KIDs as for [`PlayReadyPsshData::verify_checksums`], but fail
+    /// with [`PsshError::KeyIdNotFound`] if `keys` contains a KID which is not in the header.
+    pub fn verify_checksums_strict(&self, keys: &HashMap<DRMKeyId, Vec<u8>>) -> Result<Vec<ChecksumMismatch>> {
+        let present = self.key_ids();
+        let mut supplied: Vec<&DRMKeyId> = keys.keys().collect();
+        supplied.sort();
+        if let Some(missing) = supplied.into_iter().find(|kid| !present.contains(kid)) {
+            return Err(PsshError::KeyIdNotFound { system: PLAYREADY_SYSTEM_ID, key_id: *missing });
+        }
+        self.verify_checksums(keys)
+    }
+}
+
+
+/// The key seed of the public PlayReady test server, whose base64 encoding is
+/// `XVBovsmzhP9gRIZxWfFta3VVRPzVEWmJsazEJ46I`.
+pub const PLAYREADY_TEST_KEY_SEED: [u8; 30] = [
+    0x5d, 0x50, 0x68, 0xbe, 0xc9, 0xb3, 0x84, 0xff, 0x60, 0x44, 0x86, 0x71, 0x59, 0xf1, 0x6d,
+    0x6b, 0x75, 0x55, 0x44, 0xfc, 0xd5, 0x11, 0x69, 0x89, 0xb1, 0xac, 0xc4, 0x27, 0x8e, 0x88];
+
+/// Derive the content key for `kid` from a PlayReady key seed, using the SHA-256 construction of the
+/// PlayReady key generation algorithm. Only the first 30 octets of `key_seed` are used, and the KID
+/// is hashed in GUID byte order.
+pub fn derive_content_key(key_seed: &[u8], kid: DRMKeyId) -> Result<[u8; 16]> {
+    use sha2::{Digest, Sha256};
+    let seed = key_seed.get(..30)
+        .ok_or(PsshError::InvalidLength { offset: 0, expected: 30, found: key_seed.len() })?;
+    let guid = kid.to_guid_bytes();
+    let sha_a = Sha256::new().chain_update(seed).chain_update(guid).finalize();
+    let sha_b = Sha256::new().chain_update(seed).chain_update(guid).chain_update(seed).finalize();
+    let sha_c = Sha256::new().chain_update(seed).chain_update(guid).chain_update(seed).chain_update(guid).finalize();
+    let mut key = [0u8; 16];
+    for (i, k) in key.iter_mut().enumerate() {
+        *k = sha_a[i] ^ sha_a[i + 16] ^ sha_b[i] ^ sha_b[i + 16] ^ sha_c[i] ^ sha_c[i + 16];
+    }
+    Ok(key)
+}
+
+impl PlayReadyPsshData {
+    /// Check the checksums of the KIDs as for [`PlayReadyPsshData::verify_checksums`], with content
+    /// keys derived from `key_seed`.
+    pub fn verify_checksums_with_key_seed(&self, key_seed: &[u8]) -> Result<Vec<ChecksumMismatch>> {
+        let mut keys = HashMap::new();
+        for kid in self.key_ids() {
+            keys.insert(kid, derive_content_key(key_seed, kid)?.to_vec());
+        }
+        self.verify_checksums(&keys)
+    }
+}
@@ -0,0 +1,46 @@
+//! Serde support for representing a [`DRMSystemId`] as a UUID string rather than an array of octets.
+//!
+//! The derived `Serialize` implementation of `DRMSystemId` produces `{"id":[237,239,...]}`. Use this
+//! module on a field to serialize it as a string like `"edef8ba9-79d6-4ace-a3c8-27dcd51d21ed"`
+//! instead. Deserialization accepts any of the formats accepted by the `FromStr` implementation
+//! (hex, UUID, `urn:uuid:` URN or base64).
+//!
+//! ```
+//! use serde::{Serialize, Deserialize};
+//! use pssh_box::DRMSystemId;
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Config {
+//!     #[serde(with = "pssh_box::serde_uuid")]
+//!     system_id: DRMSystemId,
+//! }
+//! ```
+
+use std::fmt;
+use serde::{de, Deserializer, Serializer};
+use crate::DRMSystemId;
+
+
+/// Serialize `id` as a lowercase UUID string.
+pub fn serialize<S: Serializer>(id: &DRMSystemId, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&id.to_uuid_string())
+}
+
+/// Deserialize a string in any of the formats accepted by `DRMSystemId::from_str`.
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DRMSystemId, D::Error> {
+    deserializer.deserialize_str(UuidVisitor)
+}
+
+struct UuidVisitor;
+
+impl de::Visitor<'_> for UuidVisitor {
+    type Value = DRMSystemId;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a DRM system ID as a UUID, URN, hex or base64 string")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<DRMSystemId, E> {
+        v.parse().map_err(E::custom)
+    }
+}
@@ -0,0 +1,145 @@
+//! Registry of known DRM systems, with the identifiers used for them in PSSH boxes, DASH manifests,
+//! the Encrypted Media Extensions (EME) and HLS playlists.
+//
+// See the list at https://dashif.org/identifiers/content_protection/
+
+use hex_literal::hex;
+use crate::DRMSystemId;
+use crate::{COMMON_SYSTEM_ID, FAIRPLAYNFLX_SYSTEM_ID, IRDETO_SYSTEM_ID, MARLIN_SYSTEM_ID,
+            MOBI_SYSTEM_ID, NAGRA_SYSTEM_ID, PLAYREADY_SYSTEM_ID, WIDEVINE_SYSTEM_ID,
+            WISEPLAY_SYSTEM_ID};
+use crate::{ABV_SYSTEM_ID, ADOBE_PRIMETIME_SYSTEM_ID, ALTICAST_SYSTEM_ID, CLEARKEY_AES128_SYSTEM_ID,
+            CLEARKEY_DASHIF_SYSTEM_ID, CLEARKEY_SAMPLEAES_SYSTEM_ID, CORETRUST_SYSTEM_ID,
+            FAIRPLAY_SYSTEM_ID, GPAC_SYSTEM_ID, SECUREMEDIA_SYSTEM_ID, VISIONCRYPT_SYSTEM_ID};
+
+
+/// Information on a DRM system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrmSystem {
+    /// The canonical name, as used by the `Display` implementation of [`DRMSystemId`].
+    pub name: &'static str,
+    /// The system ID used in PSSH boxes.
+    pub system_id: DRMSystemId,
+    /// The UUID in the `urn:uuid:` scheme identifier of a DASH `ContentProtection` element. This is
+    /// the same as `system_id` for all DRM systems except Marlin.
+    pub urn_uuid: DRMSystemId,
+    /// The key system strings used to request access to this DRM system with the EME API.
+    pub eme_key_systems: &'static [&'static str],
+    /// The value of the `KEYFORMAT` attribute of an HLS `EXT-X-KEY` or `EXT-X-SESSION-KEY` tag.
+    pub hls_keyformat: Option<&'static str>,
+}
+
+impl DrmSystem {
+    /// The `schemeIdUri` of a DASH `ContentProtection` element for this DRM system, such as
+    /// `urn:uuid:edef8ba9-79d6-4ace-a3c8-27dcd51d21ed`.
+    pub fn scheme_id_uri(&self) -> String {
+        self.urn_uuid.to_urn()
+    }
+}
+
+const fn system(name: &'static str, system_id: DRMSystemId) -> DrmSystem {
+    DrmSystem { name, system_id, urn_uuid: system_id, eme_key_systems: &[], hls_keyformat: None }
+}
+
+/// All the DRM systems known to this crate.
+pub static DRM_SYSTEMS: &[DrmSystem] = &[
+    system("Common", COMMON_SYSTEM_ID),
+    // See https://github.com/gpac/testsuite/blob/b1c1f23079431221b582f3c7674706c6b6044cf2/media/encryption/tpl_roll.xml#L6
+    system("GPAC", GPAC_SYSTEM_ID),
+    DrmSystem {
+        eme_key_systems: &["com.widevine.alpha"],
+        hls_keyformat: Some("urn:uuid:edef8ba9-79d6-4ace-a3c8-27dcd51d21ed"),
+        ..system("Widevine", WIDEVINE_SYSTEM_ID)
+    },
+    DrmSystem {
+        eme_key_systems: &[
+            "com.microsoft.playready",
+            "com.microsoft.playready.recommendation",
+            "com.microsoft.playready.recommendation.3000",
+            "com.microsoft.playready.hardware",
+            "com.microsoft.playready.software",
+        ],
+        hls_keyformat: Some("com.microsoft.playready"),
+        ..system("PlayReady", PLAYREADY_SYSTEM_ID)
+    },
+    system("ABV", ABV_SYSTEM_ID),
+    DrmSystem {
+        eme_key_systems: &["com.adobe.primetime", "com.adobe.access"],
+        ..system("Adobe Primetime", ADOBE_PRIMETIME_SYSTEM_ID)
+    },
+    system("Alticast", ALTICAST_SYSTEM_ID),
+    DrmSystem {
+        eme_key_systems: &["com.apple.fps", "com.apple.fps.1_0", "com.apple.fps.2_0", "com.apple.fps.3_0"],
+        hls_keyformat: Some("com.apple.streamingkeydelivery"),
+        ..system("Apple FairPlay", FAIRPLAY_SYSTEM_ID)
+    },
+    // Unofficial FairPlay systemID used by Netflix for DASH streaming,
+    // see https://forums.developer.apple.com/thread/6185
+    system("Apple FairPlay-Netflix variant", FAIRPLAYNFLX_SYSTEM_ID),
+    DrmSystem {
+        hls_keyformat: Some("identity"),
+        ..system("ClearKey AES-128", CLEARKEY_AES128_SYSTEM_ID)
+    },
+    system("ClearKey SAMPLE-AES", CLEARKEY_SAMPLEAES_SYSTEM_ID),
+    DrmSystem {
+        eme_key_systems: &["org.w3.clearkey"],
+        ..system("ClearKey DASH-IF", CLEARKEY_DASHIF_SYSTEM_ID)
+    },
+    system("CoreTrust", CORETRUST_SYSTEM_ID),
+    system("Irdeto", IRDETO_SYSTEM_ID),
+    // The urn:uuid:<uuid> code used for Marlin is not the same as the Marlin SystemID (whereas for
+    // most other DRM systems, the urn:uuid code is the same as the system ID). However, there is
+    // some confusion in PSSH boxes used in practice, so from_system_id() also recognizes the
+    // urn:uuid code as being Marlin.
+    DrmSystem {
+        urn_uuid: DRMSystemId { id: hex!("5e629af538da4063897797ffbd9902d4") },
+        ..system("Marlin", MARLIN_SYSTEM_ID)
+    },
+    system("Nagra", NAGRA_SYSTEM_ID),
+    system("SecureMedia", SECUREMEDIA_SYSTEM_ID),
+    // WisePlay (from Huawei) and ChinaDRM are apparently different DRM systems that are identified
+    // by the same system id.
+    DrmSystem {
+        eme_key_systems: &["com.huawei.wiseplay"],
+        hls_keyformat: Some("urn:uuid:3d5e6d35-9b9a-41e8-b843-dd3c6e72c42c"),
+        ..system("WisePlay-ChinaDRM", WISEPLAY_SYSTEM_ID)
+    },
+    system("VisionCrypt", VISIONCRYPT_SYSTEM_ID),
+    system("MobiDRM", MOBI_SYSTEM_ID),
+];
+
+/// The DRM system identified by `system_id` in a PSSH box. The `urn:uuid:` code of a DRM system is
+/// also accepted, because it is sometimes used as a system ID when it differs (Marlin).
+pub fn from_system_id(system_id: DRMSystemId) -> Option<&'static DrmSystem> {
+    DRM_SYSTEMS.iter().find(|s| s.system_id == system_id)
+        .or_else(|| DRM_SYSTEMS.iter().find(|s| s.urn_uuid == system_id))
+}
+
+/// The DRM system with canonical `name`, ignoring ASCII case.
+pub fn from_name(name: &str) -> Option<&'static DrmSystem> {
+    DRM_SYSTEMS.iter().find(|s| s.name.eq_ignore_ascii_case(name))
+}
+
+/// The DRM system identified by the `schemeIdUri` of a DASH `ContentProtection` element, such as
+/// `urn:uuid:EDEF8BA9-79D6-4ACE-A3C8-27DCD51D21ED`. The prefix and hex digits are matched ignoring
+/// ASCII case.
+pub fn from_scheme_id_uri(uri: &str) -> Option<&'static DrmSystem> {
+    let prefix = uri.get(..9)?;
+    if !prefix.eq_ignore_ascii_case("urn:uuid:") {
+        return None;
+    }
+    let uuid = uri[9..].replace('-', "");
+    let id: [u8; 16] = hex::decode(uuid).ok()?.try_into().ok()?;
+    DRM_SYSTEMS.iter().find(|s| s.urn_uuid.id == id)
+}
+
+/// The DRM system with EME key system string `key_system`, such as `com.widevine.alpha`.
+pub fn from_eme_key_system(key_system: &str) -> Option<&'static DrmSystem> {
+    DRM_SYSTEMS.iter().find(|s| s.eme_key_systems.contains(&key_system))
+}
+
+/// The DRM system identified by the `KEYFORMAT` attribute of an HLS key tag, ignoring ASCII case.
+pub fn from_hls_keyformat(keyformat: &str) -> Option<&'static DrmSystem> {
+    DRM_SYSTEMS.iter()
+        .find(|s| s.hls_keyformat.is_some_and(|kf| kf.eq_ignore_ascii_case(keyformat)))
+}
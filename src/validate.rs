@@ -0,0 +1,234 @@
+//! Semantic validation of PSSH boxes, which detects content that parses correctly but is likely to
+//! cause playback failures on some devices.
+
+use std::fmt;
+use crate::{PsshBox, PsshBoxVec, PsshData, DRMKeyId, DRMSystemId, KeyIdLocation, Severity};
+use crate::widevine::widevine_pssh_data::{Algorithm, ProtectionScheme};
+use crate::playready::{PlayReadyPsshData, PlayReadyRecord};
+
+
+/// A problem detected by [`PsshBox::validate`] or [`PsshBoxVec::validate`].
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lint {
+    /// The key IDs in the header of a v1 box differ from those in its PSSH data.
+    KeyIdMismatch { header: Vec<DRMKeyId>, pssh_data: Vec<DRMKeyId> },
+    /// Entry `index` of the `key_id` field of Widevine PSSH data has `length` octets instead of 16.
+    WidevineKeyIdLength { index: usize, length: usize },
+    /// The Widevine PSSH data specifies an unencrypted algorithm.
+    WidevineUnencrypted,
+    /// A PlayReady header of `version` lacks the `element` required by that version.
+    PlayReadyMissingElement { version: String, element: &'static str },
+    /// The PlayReady license acquisition URL doesn't use https.
+    InsecureLicenseUrl { url: String },
+    /// The encryption algorithm `algid` in a PlayReady header contradicts the Widevine protection
+    /// `scheme` of another box.
+    ProtectionSchemeMismatch { algid: String, scheme: String },
+    /// The boxes at `indices` are all for the DRM system `system_id`.
+    DuplicateSystem { system_id: DRMSystemId, indices: Vec<usize> },
+    /// A version 0 box has non-zero `flags`.
+    FlagsOnV0 { flags: u32 },
+}
+
+impl Lint {
+    /// How serious the problem is: an `Error` is expected to prevent playback on some devices.
+    pub fn severity(&self) -> Severity {
+        match self {
+            Lint::KeyIdMismatch { .. } |
+            Lint::WidevineKeyIdLength { .. } |
+            Lint::PlayReadyMissingElement { .. } |
+            Lint::ProtectionSchemeMismatch { .. } => Severity::Error,
+            Lint::WidevineUnencrypted |
+            Lint::InsecureLicenseUrl { .. } |
+            Lint::DuplicateSystem { .. } |
+            Lint::FlagsOnV0 { .. } => Severity::Warning,
+        }
+    }
+}
+
+fn key_id_list(kids: &[DRMKeyId]) -> String {
+    kids.iter().map(DRMKeyId::to_uuid_string).collect::<Vec<_>>().join(", ")
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lint::KeyIdMismatch { header, pssh_data } =>
+                write!(f, "key IDs in box header [{}] differ from key IDs in PSSH data [{}]",
+                       key_id_list(header), key_id_list(pssh_data)),
+            Lint::WidevineKeyIdLength { index, length } =>
+                write!(f, "Widevine key_id {index} has length {length} instead of 16"),
+            Lint::WidevineUnencrypted => write!(f, "Widevine algorithm is UNENCRYPTED"),
+            Lint::PlayReadyMissingElement { version, element } =>
+                write!(f, "PlayReady header version {version} is missing required {element} element"),
+            Lint::InsecureLicenseUrl { url } =>
+                write!(f, "PlayReady LA_URL {url} does not use https"),
+            Lint::ProtectionSchemeMismatch { algid, scheme } =>
+                write!(f, "PlayReady ALGID {algid} contradicts Widevine protection scheme {scheme}"),
+            Lint::DuplicateSystem { system_id, indices } =>
+                write!(f, "boxes {indices:?} are all for {system_id}"),
+            Lint::FlagsOnV0 { flags } => write!(f, "version 0 box has flags {flags:#x}"),
+        }
+    }
+}
+
+/// A problem detected by validation, and the box it concerns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    /// The position of the box in a `PsshBoxVec`, or `None` for problems found by
+    /// [`PsshBox::validate`] and problems involving several boxes.
+    pub box_index: Option<usize>,
+    pub lint: Lint,
+}
+
+impl Finding {
+    fn new(box_index: Option<usize>, lint: Lint) -> Finding {
+        Finding { severity: lint.severity(), box_index, lint }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.box_index {
+            Some(i) => write!(f, "{}: box {i}: {}", self.severity, self.lint),
+            None => write!(f, "{}: {}", self.severity, self.lint),
+        }
+    }
+}
+
+
+impl PsshBox {
+    /// Check this box for content which is likely to cause playback failures.
+    pub fn validate(&self) -> Vec<Finding> {
+        self.lints().into_iter()
+            .map(|lint| Finding::new(None, lint))
+            .collect()
+    }
+
+    fn lints(&self) -> Vec<Lint> {
+        let mut lints = Vec::new();
+        if self.version == 0 && self.flags != 0 {
+            lints.push(Lint::FlagsOnV0 { flags: self.flags });
+        }
+        if self.version == 1 && !self.key_ids.is_empty() {
+            let mut pssh_data = Vec::new();
+            for (location, kid) in self.key_id_locations() {
+                if location != KeyIdLocation::Header && !pssh_data.contains(&kid) {
+                    pssh_data.push(kid);
+                }
+            }
+            let mismatch = !pssh_data.is_empty() && (
+                pssh_data.iter().any(|k| !self.key_ids.contains(k)) ||
+                    self.key_ids.iter().any(|k| !pssh_data.contains(k)));
+            if mismatch {
+                lints.push(Lint::KeyIdMismatch { header: self.key_ids.clone(), pssh_data });
+            }
+        }
+        match &self.pssh_data {
+            PsshData::Widevine(wv) => {
+                for (index, kid) in wv.key_id.iter().enumerate() {
+                    if kid.len() != 16 {
+                        lints.push(Lint::WidevineKeyIdLength { index, length: kid.len() });
+                    }
+                }
+                if wv.algorithm == Some(Algorithm::Unencrypted as i32) {
+                    lints.push(Lint::WidevineUnencrypted);
+                }
+            },
+            PsshData::PlayReady(pr) => {
+                for wrmh in pr.record.iter().filter_map(PlayReadyRecord::wrm_header) {
+                    if wrmh.version.starts_with("4.0.") {
+                        if wrmh.data.kids.is_empty() {
+                            lints.push(Lint::PlayReadyMissingElement { version: wrmh.version.clone(), element: "KID" });
+                        }
+                        if wrmh.data.protect_info.is_none() {
+                            lints.push(Lint::PlayReadyMissingElement { version: wrmh.version.clone(), element: "PROTECTINFO" });
+                        }
+                    }
+                    if let Some(url) = &wrmh.data.la_url {
+                        if url.trim().get(..7).is_some_and(|p| p.eq_ignore_ascii_case("http://")) {
+                            lints.push(Lint::InsecureLicenseUrl { url: url.clone() });
+                        }
+                    }
+                }
+            },
+            _ => (),
+        }
+        lints
+    }
+}
+
+// The PlayReady ALGID values specified in a PlayReady header.
+fn playready_algids(pr: &PlayReadyPsshData) -> Vec<String> {
+    let mut algids = Vec::new();
+    for wrmh in pr.record.iter().filter_map(PlayReadyRecord::wrm_header) {
+        let data = &wrmh.data;
+        let mut kids = data.kids.iter().collect::<Vec<_>>();
+        if let Some(pi) = &data.protect_info {
+            algids.extend(pi.algid.iter().cloned());
+            kids.extend(pi.kids());
+        }
+        algids.extend(kids.iter().filter_map(|k| k.algid.clone()));
+    }
+    algids
+}
+
+// Whether a PlayReady ALGID and a Widevine protection scheme use a different cipher mode.
+fn contradicts(algid: &str, scheme: ProtectionScheme) -> bool {
+    match scheme {
+        ProtectionScheme::Cenc | ProtectionScheme::Cens => algid.eq_ignore_ascii_case("AESCBC"),
+        ProtectionScheme::Cbc1 | ProtectionScheme::Cbcs => algid.eq_ignore_ascii_case("AESCTR"),
+        ProtectionScheme::Unspecified => false,
+    }
+}
+
+impl PsshBoxVec {
+    /// Check these boxes for content which is likely to cause playback failures, both in individual
+    /// boxes and between the boxes for different DRM systems.
+    pub fn validate(&self) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        for (i, bx) in self.iter().enumerate() {
+            findings.extend(bx.lints().into_iter().map(|lint| Finding::new(Some(i), lint)));
+        }
+        let mut seen: Vec<DRMSystemId> = Vec::new();
+        for bx in self.iter() {
+            if seen.contains(&bx.system_id) {
+                continue;
+            }
+            seen.push(bx.system_id);
+            let indices: Vec<usize> = self.iter().enumerate()
+                .filter(|(_, other)| other.system_id == bx.system_id)
+                .map(|(i, _)| i)
+                .collect();
+            if indices.len() > 1 {
+                findings.push(Finding::new(None, Lint::DuplicateSystem { system_id: bx.system_id, indices }));
+            }
+        }
+        let schemes: Vec<ProtectionScheme> = self.iter()
+            .filter_map(|bx| match &bx.pssh_data {
+                PsshData::Widevine(wv) => wv.protection_scheme
+                    .and_then(|ps| ProtectionScheme::try_from(ps).ok()),
+                _ => None,
+            })
+            .collect();
+        let mut reported = Vec::new();
+        for bx in self.iter() {
+            if let PsshData::PlayReady(pr) = &bx.pssh_data {
+                for algid in playready_algids(pr) {
+                    for scheme in &schemes {
+                        let lint = Lint::ProtectionSchemeMismatch {
+                            algid: algid.clone(),
+                            scheme: scheme.as_str_name().to_lowercase(),
+                        };
+                        if contradicts(&algid, *scheme) && !reported.contains(&lint) {
+                            reported.push(lint.clone());
+                            findings.push(Finding::new(None, lint));
+                        }
+                    }
+                }
+            }
+        }
+        findings
+    }
+}
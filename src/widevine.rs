@@ -7,7 +7,8 @@
 use std::fmt;
 use prost::Message;
 use serde_json::{json, Value};
-use crate::ToBytes;
+use crate::{ToBytes, DRMKeyId};
+use crate::error::Result;
 
 // This file is generated by Prost in our build script
 include!(concat!(env!("OUT_DIR"), "/widevine.rs"));
@@ -15,12 +16,19 @@ include!(concat!(env!("OUT_DIR"), "/widevine.rs"));
 // include!("widevine-generated.rs");
 
 impl ToBytes for WidevinePsshData {
-    fn to_bytes(&self) -> Vec<u8> {
-        self.encode_to_vec()
+    fn write_to<W: std::io::Write>(&self, out: &mut W) -> Result<()> {
+        Ok(out.write_all(&self.encode_to_vec())?)
     }
 }
 
 impl WidevinePsshData {
+    /// The key IDs in the `key_id` field which have the expected length of 16 octets.
+    pub fn key_ids(&self) -> Vec<DRMKeyId> {
+        self.key_id.iter()
+            .filter_map(|kid| DRMKeyId::try_from(kid.as_slice()).ok())
+            .collect()
+    }
+
     pub fn to_json(&self) -> Value {
         let mut out = json!({});
         if let Some(a) = self.algorithm {
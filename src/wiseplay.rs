@@ -7,8 +7,9 @@
 use std::fmt;
 use serde_json::Value;
 use serde::{Serialize, Deserialize};
-use anyhow::Result;
-use crate::ToBytes;
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use crate::{ToBytes, DRMKeyId, WISEPLAY_SYSTEM_ID};
+use crate::error::{PsshError, Result};
 
 
 #[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -22,13 +23,43 @@ impl fmt::Debug for WisePlayPsshData {
     }
 }
 
+impl WisePlayPsshData {
+    /// The key IDs in the `kids` array, which contains base64-encoded KIDs (either the 16 octets of
+    /// the KID or its 32 hex characters).
+    pub fn key_ids(&self) -> Vec<DRMKeyId> {
+        let Some(kids) = self.json.get("kids").and_then(Value::as_array) else {
+            return Vec::new();
+        };
+        kids.iter()
+            .filter_map(Value::as_str)
+            .filter_map(|k| BASE64_STANDARD.decode(k).ok())
+            .filter_map(|octets| match octets.len() {
+                16 => DRMKeyId::try_from(octets).ok(),
+                32 => std::str::from_utf8(&octets).ok()
+                    .and_then(|hex| DRMKeyId::try_from(hex).ok()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
 impl ToBytes for WisePlayPsshData {
-    fn to_bytes(&self) -> Vec<u8> {
-        self.json.to_string().into_bytes()
+    fn write_to<W: std::io::Write>(&self, out: &mut W) -> Result<()> {
+        Ok(out.write_all(self.json.to_string().as_bytes())?)
     }
 }
 
 pub fn parse_pssh_data(buf: &[u8]) -> Result<WisePlayPsshData> {
-    let json = serde_json::from_slice(buf)?;
+    let json = serde_json::from_slice(buf)
+        .map_err(|e| PsshError::payload(WISEPLAY_SYSTEM_ID, json_error_offset(buf, &e), e))?;
     Ok(WisePlayPsshData { json })
 }
+
+// serde_json reports errors as a line and column, which we convert back to an octet offset.
+fn json_error_offset(buf: &[u8], e: &serde_json::Error) -> u64 {
+    let line_start: usize = buf.split(|b| *b == b'\n')
+        .take(e.line().saturating_sub(1))
+        .map(|line| line.len() + 1)
+        .sum();
+    (line_start + e.column().saturating_sub(1)) as u64
+}
@@ -0,0 +1,135 @@
+//! Tests for PlayReady KID checksums
+
+use std::collections::HashMap;
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use test_log::test;
+use pssh_box::{from_base64, from_bytes, DRMKeyId, PsshData, PsshError, ToBytes};
+use pssh_box::playready::{compute_checksum, ChecksumMismatch, PlayReadyAlgorithm, PlayReadyHeaderBuilder,
+                          PlayReadyHeaderVersion, PlayReadyPsshData};
+
+
+fn playready_data(pssh_data: &PsshData) -> &PlayReadyPsshData {
+    match pssh_data {
+        PsshData::PlayReady(pr) => pr,
+        _ => panic!("expecting PlayReady PSSH data"),
+    }
+}
+
+#[test]
+fn test_compute_checksum() {
+    let kid = DRMKeyId::from_uuid_str("00112233-4455-6677-8899-aabbccddeeff").unwrap();
+    let key: Vec<u8> = (0..16).collect();
+    let checksum = compute_checksum(kid, &key, PlayReadyAlgorithm::AesCtr).unwrap();
+    assert_eq!(BASE64_STANDARD.encode(&checksum), "KpOvXRLUXfs=");
+    assert_eq!(compute_checksum(kid, &key, PlayReadyAlgorithm::AesCbc).unwrap(), checksum);
+    let cocktail = compute_checksum(kid, &key[..7], PlayReadyAlgorithm::Cocktail).unwrap();
+    assert_eq!(BASE64_STANDARD.encode(cocktail), "512fYbFjRg==");
+    assert!(matches!(compute_checksum(kid, &key[..7], PlayReadyAlgorithm::AesCtr),
+                     Err(PsshError::InvalidLength { expected: 16, found: 7, .. })));
+    assert!(compute_checksum(kid, &key, PlayReadyAlgorithm::Cocktail).is_err());
+}
+
+#[test]
+fn test_verify_checksums() {
+    let kid1 = DRMKeyId::from_uuid_str("00112233-4455-6677-8899-aabbccddeeff").unwrap();
+    let kid2 = DRMKeyId::from_hex("4444aaaa2222bbbb8888eeee7777cccc").unwrap();
+    let key1: Vec<u8> = (0..16).collect();
+    let key2: Vec<u8> = (16..32).collect();
+    let keys = HashMap::from([(kid1, key1.clone()), (kid2, key2.clone())]);
+
+    for version in [PlayReadyHeaderVersion::V4_0, PlayReadyHeaderVersion::V4_1] {
+        let pssh = PlayReadyHeaderBuilder::new(version)
+            .content_key(kid1, &key1)
+            .build_pssh_box()
+            .unwrap();
+        let reparsed = from_bytes(&pssh.to_bytes()).unwrap();
+        let pr = playready_data(&reparsed[0].pssh_data);
+        assert_eq!(pr.verify_checksums(&HashMap::from([(kid1, key1.clone())])).unwrap(), vec![]);
+        // Checksums are not verified for KIDs whose key is unknown.
+        assert_eq!(pr.verify_checksums(&HashMap::new()).unwrap(), vec![]);
+        // Keys for KIDs which are not in the header are ignored, except by the strict variant.
+        assert_eq!(pr.verify_checksums(&keys).unwrap(), vec![]);
+        assert_eq!(pr.verify_checksums_strict(&HashMap::from([(kid1, key1.clone())])).unwrap(), vec![]);
+        assert!(matches!(pr.verify_checksums_strict(&keys),
+                         Err(PsshError::KeyIdNotFound { key_id, .. }) if key_id == kid2));
+    }
+
+    let pssh = PlayReadyHeaderBuilder::new(PlayReadyHeaderVersion::V4_2)
+        .content_key(kid1, &key1)
+        .content_key(kid2, &key2)
+        .build_pssh_box()
+        .unwrap();
+    let pr = playready_data(&pssh.pssh_data);
+    assert_eq!(pr.verify_checksums(&keys).unwrap(), vec![]);
+    // The checksum of kid2 computed with the wrong content key.
+    let wrong = HashMap::from([(kid2, key1.clone())]);
+    assert_eq!(pr.verify_checksums(&wrong).unwrap(), vec![ChecksumMismatch {
+        key_id: kid2,
+        expected: compute_checksum(kid2, &key1, PlayReadyAlgorithm::AesCtr).unwrap(),
+        found: compute_checksum(kid2, &key2, PlayReadyAlgorithm::AesCtr).unwrap(),
+    }]);
+    let short = HashMap::from([(kid1, vec![0; 7])]);
+    assert!(matches!(pr.verify_checksums(&short), Err(PsshError::InvalidLength { .. })));
+}
+
+#[test]
+fn test_key_seed() {
+    use pssh_box::playready::{derive_content_key, PLAYREADY_TEST_KEY_SEED};
+
+    assert_eq!(BASE64_STANDARD.encode(PLAYREADY_TEST_KEY_SEED), "XVBovsmzhP9gRIZxWfFta3VVRPzVEWmJsazEJ46I");
+    // The content key of the PlayReady test server for this KID.
+    let kid = DRMKeyId::from_uuid_str("10000000-1000-1000-1000-100000000001").unwrap();
+    let key = derive_content_key(&PLAYREADY_TEST_KEY_SEED, kid).unwrap();
+    assert_eq!(hex::encode(key), "3a2a1b68dd2bd9b2eeb25e84c4776668");
+    let kid2 = DRMKeyId::from_uuid_str("00112233-4455-6677-8899-aabbccddeeff").unwrap();
+    assert_eq!(hex::encode(derive_content_key(&PLAYREADY_TEST_KEY_SEED, kid2).unwrap()),
+               "cc93a30d9a68a1699ca45b168c83743e");
+    // Octets after the first 30 are ignored.
+    let mut long_seed = PLAYREADY_TEST_KEY_SEED.to_vec();
+    long_seed.extend_from_slice(&[1, 2]);
+    assert_eq!(derive_content_key(&long_seed, kid).unwrap(), key);
+    assert!(matches!(derive_content_key(&PLAYREADY_TEST_KEY_SEED[..16], kid),
+                     Err(PsshError::InvalidLength { expected: 30, found: 16, .. })));
+
+    let pssh = PlayReadyHeaderBuilder::new(PlayReadyHeaderVersion::V4_2)
+        .key_id(kid)
+        .key_id(kid2)
+        .key_seed(&PLAYREADY_TEST_KEY_SEED)
+        .build_pssh_box()
+        .unwrap();
+    let pr = playready_data(&pssh.pssh_data);
+    assert_eq!(pr.verify_checksums_with_key_seed(&PLAYREADY_TEST_KEY_SEED).unwrap(), vec![]);
+    let keys = HashMap::from([(kid, key.to_vec())]);
+    assert_eq!(pr.verify_checksums(&keys).unwrap(), vec![]);
+    let other_seed = [7u8; 30];
+    assert_eq!(pr.verify_checksums_with_key_seed(&other_seed).unwrap().len(), 2);
+}
+
+
+// A version 4.2 header from the PlayReady test server, whose LA_URL contains the content keys.
+#[test]
+fn test_verify_checksums_reference() {
+    let boxes = from_base64("AAAFyHBzc2gBAAAAmgTweZhAQoarkuZb4IhflQAAAAPoLxhMOqpXtKzoYGteP+utCHvPxvelVxa4QGqm66M2ng1rQCONoV51r2h1xRTFm2MAAAV0dAUAAAEAAQBqBTwAVwBSAE0ASABFAEEARABFAFIAIAB4AG0AbABuAHMAPQAiAGgAdAB0AHAAOgAvAC8AcwBjAGgAZQBtAGEAcwAuAG0AaQBjAHIAbwBzAG8AZgB0AC4AYwBvAG0ALwBEAFIATQAvADIAMAAwADcALwAwADMALwBQAGwAYQB5AFIAZQBhAGQAeQBIAGUAYQBkAGUAcgAiACAAdgBlAHIAcwBpAG8AbgA9ACIANAAuADIALgAwAC4AMAAiAD4APABEAEEAVABBAD4APABQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEsASQBEAFMAPgA8AEsASQBEACAAQQBMAEcASQBEAD0AIgBBAEUAUwBDAFQAUgAiACAAQwBIAEUAQwBLAFMAVQBNAD0AIgArAE4AVgA5AC8AOABqAGIAZgByAHcAPQAiACAAVgBBAEwAVQBFAD0AIgBUAEIAZwB2ADYASwBvADYAdABGAGUAcwA2AEcAQgByAFgAagAvAHIAcgBRAD0APQAiAD4APAAvAEsASQBEAD4APABLAEkARAAgAEEATABHAEkARAA9ACIAQQBFAFMAQwBUAFIAIgAgAEMASABFAEMASwBTAFUATQA9ACIAWgAxADAAaQBPAFkAWQB6AEgAMwBrAD0AIgAgAFYAQQBMAFUARQA9ACIAeABzADkANwBDAEsAWAAzAEYAbABlADQAUQBHAHEAbQA2ADYATQAyAG4AZwA9AD0AIgA+ADwALwBLAEkARAA+ADwASwBJAEQAIABBAEwARwBJAEQAPQAiAEEARQBTAEMAVABSACIAIABDAEgARQBDAEsAUwBVAE0APQAiAE8ARQB1AE0AeQBEAGUAUQAxAHMAOAA9ACIAIABWAEEATABVAEUAPQAiAEkAMABCAHIARABhAEcATgBkAFYANgB2AGEASABYAEYARgBNAFcAYgBZAHcAPQA9ACIAPgA8AC8ASwBJAEQAPgA8AC8ASwBJAEQAUwA+ADwALwBQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEwAQQBfAFUAUgBMAD4AaAB0AHQAcABzADoALwAvAHQAZQBzAHQALgBwAGwAYQB5AHIAZQBhAGQAeQAuAG0AaQBjAHIAbwBzAG8AZgB0AC4AYwBvAG0ALwBzAGUAcgB2AGkAYwBlAC8AcgBpAGcAaAB0AHMAbQBhAG4AYQBnAGUAcgAuAGEAcwBtAHgAPwBjAGYAZwA9ACgAawBpAGQAOgBUAEIAZwB2ADYASwBvADYAdABGAGUAcwA2AEcAQgByAFgAagAvAHIAcgBRAD0APQAsAGMAbwBuAHQAZQBuAHQAawBlAHkAOgB3AHYAcgAyAGIAaQBoAFMAegBFAHgASwBkAFIAOABLAEsAcABRAGYAMgB3AD0APQApACwAKABrAGkAZAA6AHgAcwA5ADcAQwBLAFgAMwBGAGwAZQA0AFEARwBxAG0ANgA2AE0AMgBuAGcAPQA9ACwAYwBvAG4AdABlAG4AdABrAGUAeQA6AGcAbwBIAE8AagBiAGsASQBOAHAAZgBaAGQAdwAyAEgAMgA1AFkAbwBOAFEAPQA9ACkALAAoAGsAaQBkADoASQAwAEIAcgBEAGEARwBOAGQAVgA2AHYAYQBIAFgARgBGAE0AVwBiAFkAdwA9AD0ALABjAG8AbgB0AGUAbgB0AGsAZQB5ADoAVwBDADEAcgBjAFcARQBiADQARQB5AEkANABpAHEAcQBFAEUAUQBlAEwAQQA9AD0AKQA8AC8ATABBAF8AVQBSAEwAPgA8AC8ARABBAFQAQQA+ADwALwBXAFIATQBIAEUAQQBEAEUAUgA+AA==").unwrap();
+    let pr = playready_data(&boxes[0].pssh_data);
+    // The KID TBgv6Ko6tFes6GBrXj/rrQ== in GUID byte order.
+    let kid = DRMKeyId::from_hex("e82f184c3aaa57b4ace8606b5e3febad").unwrap();
+    let key = BASE64_STANDARD.decode("wvr2bihSzExKdR8KKpQf2w==").unwrap();
+    assert_eq!(BASE64_STANDARD.encode(compute_checksum(kid, &key, PlayReadyAlgorithm::AesCtr).unwrap()),
+               "+NV9/8jbfrw=");
+    let keys = HashMap::from([
+        (kid, key.clone()),
+        (DRMKeyId::from_hex("087bcfc6f7a55716b8406aa6eba3369e").unwrap(),
+         BASE64_STANDARD.decode("goHOjbkINpfZdw2H25YoNQ==").unwrap()),
+        (DRMKeyId::from_hex("0d6b40238da15e75af6875c514c59b63").unwrap(),
+         BASE64_STANDARD.decode("WC1rcWEb4EyI4iqqEEQeLA==").unwrap()),
+    ]);
+    assert_eq!(pr.verify_checksums(&keys).unwrap(), vec![]);
+    // A key store holding keys for other content as well.
+    let mut store = keys.clone();
+    store.insert(DRMKeyId::from_hex("00112233445566778899aabbccddeeff").unwrap(), vec![1; 16]);
+    store.insert(DRMKeyId::from_hex("4444aaaa2222bbbb8888eeee7777cccc").unwrap(), vec![2; 16]);
+    assert_eq!(pr.verify_checksums(&store).unwrap(), vec![]);
+    assert!(matches!(pr.verify_checksums_strict(&store), Err(PsshError::KeyIdNotFound { .. })));
+    let wrong = HashMap::from([(kid, vec![0; 16])]);
+    assert_eq!(pr.verify_checksums(&wrong).unwrap().len(), 1);
+}
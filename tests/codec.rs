@@ -0,0 +1,201 @@
+//! Tests for the pluggable PSSH data codecs
+
+use std::fmt;
+use std::sync::Arc;
+use hex_literal::hex;
+use test_log::test;
+use pssh_box::{from_base64, from_bytes, from_bytes_with_options, find_boxes_buffer, iter_box_refs};
+use pssh_box::{PsshBox, PsshData, PsshError, DRMKeyId, DRMSystemId, ParseOptions, ToBytes};
+use pssh_box::{CodecRegistry, CustomPsshData, DrmPayloadCodec, register_codec};
+use pssh_box::codec::{codec_for, unregister_codec, RawCodec};
+use pssh_box::{WIDEVINE_SYSTEM_ID, PLAYREADY_SYSTEM_ID, WISEPLAY_SYSTEM_ID};
+
+
+// A codec for a made-up DRM system whose PSSH data is a JSON object with a "kid" field containing
+// a hex-encoded key ID.
+struct JsonCodec;
+
+impl DrmPayloadCodec for JsonCodec {
+    fn decode(&self, system_id: DRMSystemId, pssh_data: &[u8]) -> pssh_box::Result<PsshData> {
+        let value = serde_json::from_slice(pssh_data)
+            .map_err(|e| PsshError::PayloadDecode { offset: 0, system: system_id, source: Box::new(e) })?;
+        Ok(PsshData::Custom(CustomPsshData { system_id, value }))
+    }
+
+    fn encode(&self, pssh_data: &PsshData) -> pssh_box::Result<Vec<u8>> {
+        match pssh_data {
+            PsshData::Custom(pd) => Ok(pd.value.to_string().into_bytes()),
+            _ => pssh_data.try_to_bytes(),
+        }
+    }
+
+    fn display(&self, pssh_data: &PsshData, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match pssh_data {
+            PsshData::Custom(pd) => write!(f, "JsonPSSHData<{}>", pd.value["kid"]),
+            _ => write!(f, "JsonPSSHData<>"),
+        }
+    }
+
+    fn key_ids(&self, pssh_data: &PsshData) -> Vec<DRMKeyId> {
+        match pssh_data {
+            PsshData::Custom(pd) => pd.value["kid"].as_str()
+                .and_then(|k| DRMKeyId::try_from(k).ok())
+                .into_iter()
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn json_box(system_id: DRMSystemId) -> Vec<u8> {
+    let mut bx = PsshBox::new_widevine();
+    bx.system_id = system_id;
+    bx.pssh_data = PsshData::Unknown(br#"{"kid":"00112233445566778899aabbccddeeff"}"#.to_vec());
+    bx.to_bytes()
+}
+
+
+#[test]
+fn test_codec_global() {
+    let system_id = DRMSystemId::try_from("0a0b0c0d0e0f00010203040506070801").unwrap();
+    let octets = json_box(system_id);
+    let boxes = from_bytes(&octets).unwrap();
+    assert!(matches!(boxes[0].pssh_data, PsshData::Unknown(_)));
+
+    assert!(register_codec(system_id, JsonCodec).is_none());
+    let boxes = from_bytes(&octets).unwrap();
+    let PsshData::Custom(ref pd) = boxes[0].pssh_data else {
+        panic!("expecting custom PSSH data, got {:?}", boxes[0].pssh_data);
+    };
+    assert_eq!(pd.system_id, system_id);
+    assert_eq!(pd.value["kid"], "00112233445566778899aabbccddeeff");
+    assert_eq!(boxes[0].pssh_data.to_string(), "JsonPSSHData<\"00112233445566778899aabbccddeeff\">");
+    assert!(boxes[0].to_string().contains("JsonPSSHData"));
+    let codec = codec_for(system_id).unwrap();
+    assert_eq!(codec.key_ids(&boxes[0].pssh_data),
+               vec![DRMKeyId::try_from("00112233445566778899aabbccddeeff").unwrap()]);
+    assert_eq!(boxes[0].to_bytes(), octets);
+
+    // The custom payload is re-encoded with the codec once the original octets are discarded.
+    let mut bx = boxes[0].clone();
+    bx.forget_original();
+    assert_eq!(bx.to_bytes(), octets);
+
+    // The scanning functions use the same registry.
+    let mut buf = vec![0u8; 20];
+    buf.extend_from_slice(&octets);
+    let found: Vec<PsshBox> = find_boxes_buffer(&buf).collect();
+    assert_eq!(found.len(), 1);
+    assert!(matches!(found[0].pssh_data, PsshData::Custom(_)));
+    let bx = iter_box_refs(&buf[20..]).next().unwrap().unwrap();
+    assert!(matches!(bx.to_pssh_box().unwrap().pssh_data, PsshData::Custom(_)));
+
+    // A box parsed with a registry which lacks the codec keeps its PSSH data as raw octets, and
+    // doesn't use the global codec to encode or display it.
+    let options = ParseOptions { registry: Some(Arc::new(CodecRegistry::new())), ..Default::default() };
+    let mut raw = from_bytes_with_options(&octets, &options).unwrap()[0].clone();
+    assert!(matches!(raw.pssh_data, PsshData::Unknown(_)));
+    raw.forget_original();
+    assert_eq!(raw.to_bytes(), octets);
+    assert!(!raw.to_string().contains("JsonPSSHData"));
+
+    assert!(unregister_codec(system_id).is_some());
+    let err = bx.to_pssh_box().unwrap().pssh_data;
+    assert!(matches!(err, PsshData::Unknown(_)));
+    let custom = PsshData::Custom(pd.clone());
+    assert!(matches!(custom.try_to_bytes(), Err(PsshError::Encode { .. })));
+    assert!(custom.to_string().starts_with("CustomPSSHData<"));
+}
+
+#[test]
+fn test_codec_registry_options() {
+    let system_id = DRMSystemId::try_from("0a0b0c0d0e0f00010203040506070802").unwrap();
+    let octets = json_box(system_id);
+    let mut registry = CodecRegistry::with_builtins();
+    registry.register(system_id, JsonCodec);
+    let options = ParseOptions { registry: Some(Arc::new(registry)), ..Default::default() };
+    let boxes = from_bytes_with_options(&octets, &options).unwrap();
+    assert!(matches!(boxes[0].pssh_data, PsshData::Custom(_)));
+    // The box keeps the codec which decoded it, to serialize, display and extract key IDs.
+    let kid = DRMKeyId::try_from("00112233445566778899aabbccddeeff").unwrap();
+    assert!(!boxes[0].is_modified());
+    assert_eq!(boxes[0].to_bytes(), octets);
+    assert!(boxes[0].to_string().contains("JsonPSSHData"));
+    assert_eq!(boxes[0].all_key_ids()[0].key_id, kid);
+    let mut bx = boxes[0].clone();
+    bx.forget_original();
+    assert_eq!(bx.to_bytes(), octets);
+    if let PsshData::Custom(ref mut pd) = bx.pssh_data {
+        pd.value["kid"] = serde_json::Value::from("ffeeddccbbaa99887766554433221100");
+    }
+    let reparsed = from_bytes_with_options(&bx.to_bytes(), &options).unwrap();
+    assert_eq!(reparsed[0], bx);
+    assert_eq!(reparsed[0].all_key_ids()[0].key_id, DRMKeyId::try_from("ffeeddccbbaa99887766554433221100").unwrap());
+    // The global registry is unaffected.
+    assert!(codec_for(system_id).is_none());
+    let boxes = from_bytes(&octets).unwrap();
+    assert!(matches!(boxes[0].pssh_data, PsshData::Unknown(_)));
+
+    // Errors from a custom codec are reported at the offset of the PSSH data in the input.
+    let mut bad = octets.clone();
+    let len = bad.len();
+    bad[len - 1] = b'!';
+    match from_bytes_with_options(&bad, &options) {
+        Err(PsshError::PayloadDecode { offset, system, .. }) => {
+            assert_eq!(system, system_id);
+            assert_eq!(offset, 36);
+        },
+        other => panic!("expecting PayloadDecode error, got {other:?}"),
+    }
+
+    // With an empty registry, even the built-in DRM systems are left undecoded.
+    let widevine = PsshBox::new_widevine().to_bytes();
+    let options = ParseOptions { registry: Some(Arc::new(CodecRegistry::new())), ..Default::default() };
+    let boxes = from_bytes_with_options(&widevine, &options).unwrap();
+    assert!(matches!(boxes[0].pssh_data, PsshData::Unknown(_)));
+    let boxes = from_bytes(&widevine).unwrap();
+    assert!(matches!(boxes[0].pssh_data, PsshData::Widevine(_)));
+
+    // A built-in codec can be replaced in a registry.
+    let mut registry = CodecRegistry::with_builtins();
+    assert!(registry.register(WIDEVINE_SYSTEM_ID, RawCodec).is_some());
+    let options = ParseOptions { registry: Some(Arc::new(registry)), ..Default::default() };
+    let boxes = from_bytes_with_options(&widevine, &options).unwrap();
+    assert!(matches!(boxes[0].pssh_data, PsshData::Unknown(_)));
+
+    // A codec replacing a built-in codec in a registry is also used to encode the PSSH data.
+    let mut registry = CodecRegistry::with_builtins();
+    registry.register(WIDEVINE_SYSTEM_ID, JsonCodec);
+    let options = ParseOptions { registry: Some(Arc::new(registry)), ..Default::default() };
+    let octets = json_box(WIDEVINE_SYSTEM_ID);
+    let mut bx = from_bytes_with_options(&octets, &options).unwrap()[0].clone();
+    assert!(matches!(bx.pssh_data, PsshData::Custom(_)));
+    bx.forget_original();
+    assert_eq!(bx.to_bytes(), octets);
+    // Without that codec, the custom PSSH data can't be encoded by the built-in Widevine codec.
+    let mut detached = PsshBox::new_widevine();
+    detached.pssh_data = bx.pssh_data.clone();
+    assert!(matches!(detached.try_to_bytes(), Err(PsshError::Encode { .. })));
+    assert!(detached.to_string().contains("CustomPSSHData<"));
+}
+
+#[test]
+fn test_builtin_codecs() {
+    let registry = CodecRegistry::with_builtins();
+    assert!(registry.system_ids().any(|id| id == PLAYREADY_SYSTEM_ID));
+    assert!(registry.get(DRMSystemId::try_from("0a0b0c0d0e0f00010203040506070803").unwrap()).is_none());
+
+    let boxes = from_base64("AAACJnBzc2gAAAAAmgTweZhAQoarkuZb4IhflQAAAgYGAgAAAQABAPwBPABXAFIATQBIAEUAQQBEAEUAUgAgAHgAbQBsAG4AcwA9ACIAaAB0AHQAcAA6AC8ALwBzAGMAaABlAG0AYQBzAC4AbQBpAGMAcgBvAHMAbwBmAHQALgBjAG8AbQAvAEQAUgBNAC8AMgAwADAANwAvADAAMwAvAFAAbABhAHkAUgBlAGEAZAB5AEgAZQBhAGQAZQByACIAIAB2AGUAcgBzAGkAbwBuAD0AIgA0AC4AMAAuADAALgAwACIAPgA8AEQAQQBUAEEAPgA8AFAAUgBPAFQARQBDAFQASQBOAEYATwA+ADwASwBFAFkATABFAE4APgAxADYAPAAvAEsARQBZAEwARQBOAD4APABBAEwARwBJAEQAPgBBAEUAUwBDAFQAUgA8AC8AQQBMAEcASQBEAD4APAAvAFAAUgBPAFQARQBDAFQASQBOAEYATwA+ADwASwBJAEQAPgBNAGwAUwBKAFYAMwBhAFkAUgBTAE4ASABWAG0AVgBIAEsAVABnAGoAUQBRAD0APQA8AC8ASwBJAEQAPgA8AEMASABFAEMASwBTAFUATQA+AFUARwBOAFYAQgBTAHUAZwAzADgAcwA9ADwALwBDAEgARQBDAEsAUwBVAE0APgA8AC8ARABBAFQAQQA+ADwALwBXAFIATQBIAEUAQQBEAEUAUgA+AA==")
+        .unwrap();
+    let codec = registry.get(PLAYREADY_SYSTEM_ID).unwrap();
+    // The KID is stored as a little-endian GUID in the PlayReady header.
+    assert_eq!(codec.key_ids(&boxes[0].pssh_data),
+               vec![DRMKeyId::try_from("57895432-9876-2345-4756-654729382341").unwrap()]);
+
+    let boxes = from_base64("AAAAn3Bzc2gAAAAAPV5tNZuaQei4Q908bnLELAAAAH97InZlcnNpb24iOiJWMS4wIiwia2lkcyI6WyJtWkNpWm9ENVBnbWxXcjgxcHcyQVpRPT0iXSwiY29udGVudElEIjoiZXlKaGMzTmxkRWxrSWpvaWRIWnRaV1JwWVMweU1ETXhPRFUyTnlKOSIsImVuc2NoZW1hIjoiY2VuYyJ9")
+        .unwrap();
+    let codec = registry.get(WISEPLAY_SYSTEM_ID).unwrap();
+    assert_eq!(codec.key_ids(&boxes[0].pssh_data),
+               vec![DRMKeyId::try_from(&hex!("9990a26680f93e09a55abf35a70d8065")[..]).unwrap()]);
+    assert_eq!(codec.encode(&boxes[0].pssh_data).unwrap(), boxes[0].pssh_data.to_bytes());
+}
@@ -0,0 +1,92 @@
+//! Tests for the collection API of PsshBoxVec
+
+use test_log::test;
+use pssh_box::{from_base64, PsshBox, PsshBoxVec, PsshData, DRMKeyId, ToBytes};
+use pssh_box::{WIDEVINE_SYSTEM_ID, PLAYREADY_SYSTEM_ID};
+
+
+fn widevine_with_kid(kid: DRMKeyId) -> PsshBox {
+    let mut bx = PsshBox::new_widevine();
+    bx.add_key_id(kid);
+    if let PsshData::Widevine(ref mut wv) = bx.pssh_data {
+        wv.key_id.push(kid.as_bytes().to_vec());
+    }
+    bx
+}
+
+#[test]
+fn test_collect_filter() {
+    let kid1 = DRMKeyId::from_hex("00112233445566778899aabbccddeeff").unwrap();
+    let kid2 = DRMKeyId::from_hex("ffeeddccbbaa99887766554433221100").unwrap();
+    let mut boxes: PsshBoxVec = vec![widevine_with_kid(kid1), PsshBox::new_playready()]
+        .into_iter()
+        .collect();
+    boxes.extend([widevine_with_kid(kid2)]);
+    assert_eq!(boxes.len(), 3);
+    // Collected and extended boxes keep their order.
+    assert_eq!(boxes[0], widevine_with_kid(kid1));
+    assert_eq!(boxes[1].system_id, PLAYREADY_SYSTEM_ID);
+    assert_eq!(boxes[2], widevine_with_kid(kid2));
+    let keys: Vec<DRMKeyId> = boxes.all_key_ids().into_iter().map(|t| t.key_id).collect();
+    assert_eq!(keys, vec![kid1, kid2]);
+    assert_eq!(boxes.for_system(WIDEVINE_SYSTEM_ID).count(), 2);
+    assert_eq!(boxes.for_system(PLAYREADY_SYSTEM_ID).count(), 1);
+    assert_eq!((&boxes).into_iter().count(), 3);
+
+    let groups = boxes.group_by_key_id();
+    assert_eq!(groups.keys().copied().collect::<Vec<_>>(), vec![kid1, kid2]);
+    assert_eq!(groups[&kid1], vec![&boxes[0]]);
+    assert_eq!(groups[&kid2], vec![&boxes[2]]);
+
+    boxes.retain(|bx| bx.system_id != WIDEVINE_SYSTEM_ID);
+    assert_eq!(boxes.len(), 1);
+    assert_eq!(boxes[0].system_id, PLAYREADY_SYSTEM_ID);
+    assert!(boxes.all_key_ids().is_empty());
+}
+
+#[test]
+fn test_dedup_sort() {
+    let pr1 = from_base64("AAACJnBzc2gAAAAAmgTweZhAQoarkuZb4IhflQAAAgYGAgAAAQABAPwBPABXAFIATQBIAEUAQQBEAEUAUgAgAHgAbQBsAG4AcwA9ACIAaAB0AHQAcAA6AC8ALwBzAGMAaABlAG0AYQBzAC4AbQBpAGMAcgBvAHMAbwBmAHQALgBjAG8AbQAvAEQAUgBNAC8AMgAwADAANwAvADAAMwAvAFAAbABhAHkAUgBlAGEAZAB5AEgAZQBhAGQAZQByACIAIAB2AGUAcgBzAGkAbwBuAD0AIgA0AC4AMAAuADAALgAwACIAPgA8AEQAQQBUAEEAPgA8AFAAUgBPAFQARQBDAFQASQBOAEYATwA+ADwASwBFAFkATABFAE4APgAxADYAPAAvAEsARQBZAEwARQBOAD4APABBAEwARwBJAEQAPgBBAEUAUwBDAFQAUgA8AC8AQQBMAEcASQBEAD4APAAvAFAAUgBPAFQARQBDAFQASQBOAEYATwA+ADwASwBJAEQAPgBNAGwAUwBKAFYAMwBhAFkAUgBTAE4ASABWAG0AVgBIAEsAVABnAGoAUQBRAD0APQA8AC8ASwBJAEQAPgA8AEMASABFAEMASwBTAFUATQA+AFUARwBOAFYAQgBTAHUAZwAzADgAcwA9ADwALwBDAEgARQBDAEsAUwBVAE0APgA8AC8ARABBAFQAQQA+ADwALwBXAFIATQBIAEUAQQBEAEUAUgA+AA==")
+        .unwrap()[0].clone();
+    // The same PlayReady header, serialized by our encoder rather than retaining the original octets.
+    let mut pr2 = pr1.clone();
+    pr2.forget_original();
+    let kid = DRMKeyId::from_hex("00112233445566778899aabbccddeeff").unwrap();
+    let wv = widevine_with_kid(kid);
+    let mut boxes: PsshBoxVec = [pr1.clone(), wv.clone(), pr2, wv.clone()].into_iter().collect();
+    boxes.dedup();
+    assert_eq!(boxes.len(), 2);
+    assert_eq!(boxes[0], pr1);
+    assert_eq!(boxes[1], wv);
+
+    // Boxes which differ only in the order of the key IDs in their header are duplicates.
+    let kid2 = DRMKeyId::from_hex("ffeeddccbbaa99887766554433221100").unwrap();
+    let mut wv1 = wv.clone();
+    wv1.add_key_id(kid2);
+    let mut wv2 = PsshBox::new_widevine();
+    wv2.add_key_id(kid2);
+    wv2.add_key_id(kid);
+    wv2.pssh_data = wv.pssh_data.clone();
+    assert_ne!(wv1, wv2);
+    let mut boxes: PsshBoxVec = [wv1.clone(), wv2].into_iter().collect();
+    boxes.dedup();
+    assert_eq!(boxes.len(), 1);
+    assert_eq!(boxes[0], wv1);
+
+    // A v0 and a v1 box with the same PSSH data are different boxes.
+    let mut wv0 = wv.clone();
+    wv0.version = 0;
+    let mut boxes: PsshBoxVec = [wv.clone(), wv0.clone(), wv.clone(), wv0.clone()].into_iter().collect();
+    boxes.dedup();
+    assert_eq!(boxes.len(), 2);
+    assert_eq!(boxes[0], wv);
+    assert_eq!(boxes[1], wv0);
+
+    // The canonical order doesn't depend on the order of generation.
+    let mut a: PsshBoxVec = [pr1.clone(), wv.clone(), PsshBox::new_widevine()].into_iter().collect();
+    let mut b: PsshBoxVec = [PsshBox::new_widevine(), wv.clone(), pr1.clone()].into_iter().collect();
+    a.sort_canonical();
+    b.sort_canonical();
+    assert_eq!(a.to_bytes(), b.to_bytes());
+    assert_eq!(a.to_base64(), b.to_base64());
+}
@@ -0,0 +1,83 @@
+//! Tests for comparing two sets of PSSH boxes
+
+use test_log::test;
+use pssh_box::{from_base64, PsshBox, PsshBoxVec, PsshData, DRMKeyId, Change};
+use pssh_box::widevine::widevine_pssh_data::ProtectionScheme;
+use pssh_box::{WIDEVINE_SYSTEM_ID, PLAYREADY_SYSTEM_ID, NAGRA_SYSTEM_ID};
+
+
+#[test]
+fn test_diff_unchanged() {
+    let boxes: PsshBoxVec = [PsshBox::new_widevine(), PsshBox::new_playready()].into_iter().collect();
+    let diff = boxes.diff(&boxes.clone());
+    assert!(diff.is_empty());
+    assert_eq!(diff.to_string(), "");
+}
+
+#[test]
+fn test_diff_changes() {
+    let kid1 = DRMKeyId::from_hex("840d5cc9fa4523a83164451c615b206a").unwrap();
+    let kid2 = DRMKeyId::from_hex("00112233445566778899aabbccddeeff").unwrap();
+    let mut wv_old = PsshBox::new_widevine();
+    wv_old.add_key_id(kid1);
+    if let PsshData::Widevine(ref mut wv) = wv_old.pssh_data {
+        wv.provider = Some(String::from("widevine_test"));
+    }
+    let mut wv_new = PsshBox::new_widevine();
+    wv_new.add_key_id(kid2);
+    wv_new.flags = 1;
+    if let PsshData::Widevine(ref mut wv) = wv_new.pssh_data {
+        wv.provider = Some(String::from("widevine_test"));
+        wv.protection_scheme = Some(ProtectionScheme::Cbcs as i32);
+        wv.content_id = Some(vec![0xca, 0xfe]);
+    }
+    let pr_old = PsshBox::new_playready();
+    let mut pr_new = PsshBox::new_playready();
+    pr_new.version = 0;
+    if let PsshData::PlayReady(ref mut pr) = pr_new.pssh_data {
+        pr.record[0].wrm_header_mut().unwrap().data.la_url = Some(String::from("https://example.com/rightsmanager.asmx"));
+    }
+    let mut nagra = PsshBox::new_widevine();
+    nagra.system_id = NAGRA_SYSTEM_ID;
+    nagra.pssh_data = PsshData::Unknown(Vec::new());
+
+    let old: PsshBoxVec = [wv_old, pr_old, nagra].into_iter().collect();
+    let new: PsshBoxVec = [pr_new, wv_new].into_iter().collect();
+    let diff = old.diff(&new);
+    assert_eq!(diff.changes, vec![
+        Change::FlagsChanged { system_id: WIDEVINE_SYSTEM_ID, old: 0, new: 1 },
+        Change::FieldChanged { system_id: WIDEVINE_SYSTEM_ID, field: "content_id", old: None, new: Some(String::from("cafe")) },
+        Change::FieldChanged { system_id: WIDEVINE_SYSTEM_ID, field: "protection_scheme", old: None, new: Some(String::from("cbcs")) },
+        Change::KeyIdAdded { system_id: WIDEVINE_SYSTEM_ID, key_id: kid2 },
+        Change::KeyIdRemoved { system_id: WIDEVINE_SYSTEM_ID, key_id: kid1 },
+        Change::VersionChanged { system_id: PLAYREADY_SYSTEM_ID, old: 1, new: 0 },
+        Change::FieldChanged { system_id: PLAYREADY_SYSTEM_ID, field: "LA_URL", old: None,
+                               new: Some(String::from("https://example.com/rightsmanager.asmx")) },
+        Change::SystemRemoved { system_id: NAGRA_SYSTEM_ID },
+    ]);
+    let text = diff.to_string();
+    assert!(text.contains("~ Widevine: flags 0x0 -> 0x1\n"));
+    assert!(text.contains("+ Widevine: key ID 00112233-4455-6677-8899-aabbccddeeff\n"));
+    assert!(text.contains("~ PlayReady: LA_URL (absent) -> https://example.com/rightsmanager.asmx\n"));
+    assert!(text.ends_with("- Nagra: box removed\n"));
+
+    let reverse = new.diff(&old);
+    assert!(reverse.changes.contains(&Change::SystemAdded { system_id: NAGRA_SYSTEM_ID }));
+}
+
+// Two v0 boxes with PlayReady 4.2 headers which differ only in a KID in PROTECTINFO/KIDS.
+#[test]
+fn test_diff_playready_kids() {
+    let old_kid = DRMKeyId::from_hex("e82f184c3aaa57b4ace8606b5e3febad").unwrap();
+    let new_kid = DRMKeyId::from_hex("00112233445566778899aabbccddeeff").unwrap();
+    let v1 = from_base64("AAAFyHBzc2gBAAAAmgTweZhAQoarkuZb4IhflQAAAAPoLxhMOqpXtKzoYGteP+utCHvPxvelVxa4QGqm66M2ng1rQCONoV51r2h1xRTFm2MAAAV0dAUAAAEAAQBqBTwAVwBSAE0ASABFAEEARABFAFIAIAB4AG0AbABuAHMAPQAiAGgAdAB0AHAAOgAvAC8AcwBjAGgAZQBtAGEAcwAuAG0AaQBjAHIAbwBzAG8AZgB0AC4AYwBvAG0ALwBEAFIATQAvADIAMAAwADcALwAwADMALwBQAGwAYQB5AFIAZQBhAGQAeQBIAGUAYQBkAGUAcgAiACAAdgBlAHIAcwBpAG8AbgA9ACIANAAuADIALgAwAC4AMAAiAD4APABEAEEAVABBAD4APABQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEsASQBEAFMAPgA8AEsASQBEACAAQQBMAEcASQBEAD0AIgBBAEUAUwBDAFQAUgAiACAAQwBIAEUAQwBLAFMAVQBNAD0AIgArAE4AVgA5AC8AOABqAGIAZgByAHcAPQAiACAAVgBBAEwAVQBFAD0AIgBUAEIAZwB2ADYASwBvADYAdABGAGUAcwA2AEcAQgByAFgAagAvAHIAcgBRAD0APQAiAD4APAAvAEsASQBEAD4APABLAEkARAAgAEEATABHAEkARAA9ACIAQQBFAFMAQwBUAFIAIgAgAEMASABFAEMASwBTAFUATQA9ACIAWgAxADAAaQBPAFkAWQB6AEgAMwBrAD0AIgAgAFYAQQBMAFUARQA9ACIAeABzADkANwBDAEsAWAAzAEYAbABlADQAUQBHAHEAbQA2ADYATQAyAG4AZwA9AD0AIgA+ADwALwBLAEkARAA+ADwASwBJAEQAIABBAEwARwBJAEQAPQAiAEEARQBTAEMAVABSACIAIABDAEgARQBDAEsAUwBVAE0APQAiAE8ARQB1AE0AeQBEAGUAUQAxAHMAOAA9ACIAIABWAEEATABVAEUAPQAiAEkAMABCAHIARABhAEcATgBkAFYANgB2AGEASABYAEYARgBNAFcAYgBZAHcAPQA9ACIAPgA8AC8ASwBJAEQAPgA8AC8ASwBJAEQAUwA+ADwALwBQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEwAQQBfAFUAUgBMAD4AaAB0AHQAcABzADoALwAvAHQAZQBzAHQALgBwAGwAYQB5AHIAZQBhAGQAeQAuAG0AaQBjAHIAbwBzAG8AZgB0AC4AYwBvAG0ALwBzAGUAcgB2AGkAYwBlAC8AcgBpAGcAaAB0AHMAbQBhAG4AYQBnAGUAcgAuAGEAcwBtAHgAPwBjAGYAZwA9ACgAawBpAGQAOgBUAEIAZwB2ADYASwBvADYAdABGAGUAcwA2AEcAQgByAFgAagAvAHIAcgBRAD0APQAsAGMAbwBuAHQAZQBuAHQAawBlAHkAOgB3AHYAcgAyAGIAaQBoAFMAegBFAHgASwBkAFIAOABLAEsAcABRAGYAMgB3AD0APQApACwAKABrAGkAZAA6AHgAcwA5ADcAQwBLAFgAMwBGAGwAZQA0AFEARwBxAG0ANgA2AE0AMgBuAGcAPQA9ACwAYwBvAG4AdABlAG4AdABrAGUAeQA6AGcAbwBIAE8AagBiAGsASQBOAHAAZgBaAGQAdwAyAEgAMgA1AFkAbwBOAFEAPQA9ACkALAAoAGsAaQBkADoASQAwAEIAcgBEAGEARwBOAGQAVgA2AHYAYQBIAFgARgBGAE0AVwBiAFkAdwA9AD0ALABjAG8AbgB0AGUAbgB0AGsAZQB5ADoAVwBDADEAcgBjAFcARQBiADQARQB5AEkANABpAHEAcQBFAEUAUQBlAEwAQQA9AD0AKQA8AC8ATABBAF8AVQBSAEwAPgA8AC8ARABBAFQAQQA+ADwALwBXAFIATQBIAEUAQQBEAEUAUgA+AA==").unwrap();
+    let old_box = v1[0].to_v0().unwrap();
+    let mut new_box = old_box.clone();
+    new_box.replace_key_id(old_kid, new_kid).unwrap();
+    let old: PsshBoxVec = [old_box].into_iter().collect();
+    let new: PsshBoxVec = [new_box].into_iter().collect();
+    assert_eq!(old.diff(&new).changes, vec![
+        Change::KeyIdAdded { system_id: PLAYREADY_SYSTEM_ID, key_id: new_kid },
+        Change::KeyIdRemoved { system_id: PLAYREADY_SYSTEM_ID, key_id: old_kid },
+    ]);
+}
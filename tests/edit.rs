@@ -0,0 +1,129 @@
+//! Tests for modifying the header and PSSH data of boxes consistently
+
+use test_log::test;
+use pssh_box::{from_base64, from_bytes, PsshBox, PsshBoxVec, PsshData, PsshError, DRMKeyId, KeyIdReplacement, ToBytes};
+use pssh_box::{NAGRA_SYSTEM_ID, PLAYREADY_SYSTEM_ID, WIDEVINE_SYSTEM_ID};
+
+
+// A v1 PlayReady 4.0 box with a KID in its header and in the WRMHEADER, and a KID checksum.
+const PLAYREADY: &str = "AAADrHBzc2gBAAAAmgTweZhAQoarkuZb4IhflQAAAAGEDVzJ+kUjqDFkRRxhWyBqAAADeHgDAAABAAEAbgM8AFcAUgBNAEgARQBBAEQARQBSACAAeABtAGwAbgBzAD0AIgBoAHQAdABwADoALwAvAHMAYwBoAGUAbQBhAHMALgBtAGkAYwByAG8AcwBvAGYAdAAuAGMAbwBtAC8ARABSAE0ALwAyADAAMAA3AC8AMAAzAC8AUABsAGEAeQBSAGUAYQBkAHkASABlAGEAZABlAHIAIgAgAHYAZQByAHMAaQBvAG4APQAiADQALgAwAC4AMAAuADAAIgA+ADwARABBAFQAQQA+ADwAUABSAE8AVABFAEMAVABJAE4ARgBPAD4APABLAEUAWQBMAEUATgA+ADEANgA8AC8ASwBFAFkATABFAE4APgA8AEEATABHAEkARAA+AEEARQBTAEMAVABSADwALwBBAEwARwBJAEQAPgA8AC8AUABSAE8AVABFAEMAVABJAE4ARgBPAD4APABLAEkARAA+AHkAVgB3AE4AaABFAFgANgBxAEMATQB4AFoARQBVAGMAWQBWAHMAZwBhAGcAPQA9ADwALwBLAEkARAA+ADwATABBAF8AVQBSAEwAPgBoAHQAdABwAHMAOgAvAC8AcABsAGEAeQByAGUAYQBkAHkALQBsAGkAYwBlAG4AcwBlAC4AdgB1AGQAcgBtAC4AdABlAGMAaAAvAHIAaQBnAGgAdABzAG0AYQBuAGEAZwBlAHIALgBhAHMAbQB4ADwALwBMAEEAXwBVAFIATAA+ADwATABVAEkAXwBVAFIATAA+AGgAdAB0AHAAcwA6AC8ALwBwAGwAYQB5AHIAZQBhAGQAeQAtAGwAaQBjAGUAbgBzAGUALgB2AHUAZAByAG0ALgB0AGUAYwBoAC8AcgBpAGcAaAB0AHMAbQBhAG4AYQBnAGUAcgAuAGEAcwBtAHgAPAAvAEwAVQBJAF8AVQBSAEwAPgA8AEQAUwBfAEkARAA+AGcAdwBJAEMASQA4AHkAZgBJAFUARwBmADQAUgAvADUAcQBPAFcAdQBxAGcAPQA9ADwALwBEAFMAXwBJAEQAPgA8AEMASABFAEMASwBTAFUATQA+ADcAegBEAHMAWQBmAEQAVgBIAFUAWQA9ADwALwBDAEgARQBDAEsAUwBVAE0APgA8AC8ARABBAFQAQQA+ADwALwBXAFIATQBIAEUAQQBEAEUAUgA+AA==";
+
+// A v1 PlayReady 4.2 box with three KIDs in PROTECTINFO/KIDS, each with a checksum.
+const PLAYREADY_4_2: &str = "AAAFyHBzc2gBAAAAmgTweZhAQoarkuZb4IhflQAAAAPoLxhMOqpXtKzoYGteP+utCHvPxvelVxa4QGqm66M2ng1rQCONoV51r2h1xRTFm2MAAAV0dAUAAAEAAQBqBTwAVwBSAE0ASABFAEEARABFAFIAIAB4AG0AbABuAHMAPQAiAGgAdAB0AHAAOgAvAC8AcwBjAGgAZQBtAGEAcwAuAG0AaQBjAHIAbwBzAG8AZgB0AC4AYwBvAG0ALwBEAFIATQAvADIAMAAwADcALwAwADMALwBQAGwAYQB5AFIAZQBhAGQAeQBIAGUAYQBkAGUAcgAiACAAdgBlAHIAcwBpAG8AbgA9ACIANAAuADIALgAwAC4AMAAiAD4APABEAEEAVABBAD4APABQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEsASQBEAFMAPgA8AEsASQBEACAAQQBMAEcASQBEAD0AIgBBAEUAUwBDAFQAUgAiACAAQwBIAEUAQwBLAFMAVQBNAD0AIgArAE4AVgA5AC8AOABqAGIAZgByAHcAPQAiACAAVgBBAEwAVQBFAD0AIgBUAEIAZwB2ADYASwBvADYAdABGAGUAcwA2AEcAQgByAFgAagAvAHIAcgBRAD0APQAiAD4APAAvAEsASQBEAD4APABLAEkARAAgAEEATABHAEkARAA9ACIAQQBFAFMAQwBUAFIAIgAgAEMASABFAEMASwBTAFUATQA9ACIAWgAxADAAaQBPAFkAWQB6AEgAMwBrAD0AIgAgAFYAQQBMAFUARQA9ACIAeABzADkANwBDAEsAWAAzAEYAbABlADQAUQBHAHEAbQA2ADYATQAyAG4AZwA9AD0AIgA+ADwALwBLAEkARAA+ADwASwBJAEQAIABBAEwARwBJAEQAPQAiAEEARQBTAEMAVABSACIAIABDAEgARQBDAEsAUwBVAE0APQAiAE8ARQB1AE0AeQBEAGUAUQAxAHMAOAA9ACIAIABWAEEATABVAEUAPQAiAEkAMABCAHIARABhAEcATgBkAFYANgB2AGEASABYAEYARgBNAFcAYgBZAHcAPQA9ACIAPgA8AC8ASwBJAEQAPgA8AC8ASwBJAEQAUwA+ADwALwBQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEwAQQBfAFUAUgBMAD4AaAB0AHQAcABzADoALwAvAHQAZQBzAHQALgBwAGwAYQB5AHIAZQBhAGQAeQAuAG0AaQBjAHIAbwBzAG8AZgB0AC4AYwBvAG0ALwBzAGUAcgB2AGkAYwBlAC8AcgBpAGcAaAB0AHMAbQBhAG4AYQBnAGUAcgAuAGEAcwBtAHgAPwBjAGYAZwA9ACgAawBpAGQAOgBUAEIAZwB2ADYASwBvADYAdABGAGUAcwA2AEcAQgByAFgAagAvAHIAcgBRAD0APQAsAGMAbwBuAHQAZQBuAHQAawBlAHkAOgB3AHYAcgAyAGIAaQBoAFMAegBFAHgASwBkAFIAOABLAEsAcABRAGYAMgB3AD0APQApACwAKABrAGkAZAA6AHgAcwA5ADcAQwBLAFgAMwBGAGwAZQA0AFEARwBxAG0ANgA2AE0AMgBuAGcAPQA9ACwAYwBvAG4AdABlAG4AdABrAGUAeQA6AGcAbwBIAE8AagBiAGsASQBOAHAAZgBaAGQAdwAyAEgAMgA1AFkAbwBOAFEAPQA9ACkALAAoAGsAaQBkADoASQAwAEIAcgBEAGEARwBOAGQAVgA2AHYAYQBIAFgARgBGAE0AVwBiAFkAdwA9AD0ALABjAG8AbgB0AGUAbgB0AGsAZQB5ADoAVwBDADEAcgBjAFcARQBiADQARQB5AEkANABpAHEAcQBFAEUAUQBlAEwAQQA9AD0AKQA8AC8ATABBAF8AVQBSAEwAPgA8AC8ARABBAFQAQQA+ADwALwBXAFIATQBIAEUAQQBEAEUAUgA+AA==";
+
+fn playready_data(bx: &PsshBox) -> &pssh_box::playready::WRMData {
+    match &bx.pssh_data {
+        PsshData::PlayReady(pr) => &pr.record[0].wrm_header().unwrap().data,
+        _ => panic!("expecting PlayReady PSSH data"),
+    }
+}
+
+#[test]
+fn test_set_license_url() {
+    let mut boxes = from_base64(PLAYREADY).unwrap();
+    boxes.add(PsshBox::new_widevine());
+    boxes.set_license_url(PLAYREADY_SYSTEM_ID, "https://prod.example.com/rightsmanager.asmx").unwrap();
+    assert_eq!(playready_data(&boxes[0]).la_url.as_deref(), Some("https://prod.example.com/rightsmanager.asmx"));
+    let reparsed = from_bytes(&boxes[0].to_bytes()).unwrap();
+    assert_eq!(reparsed[0], boxes[0]);
+
+    // Widevine PSSH data has no license URL, and a failed operation leaves the boxes unchanged.
+    let before = boxes.clone();
+    let err = boxes.set_license_url(WIDEVINE_SYSTEM_ID, "https://prod.example.com/").unwrap_err();
+    assert!(matches!(err, PsshError::UnsupportedOperation { system: WIDEVINE_SYSTEM_ID, .. }));
+    assert_eq!(boxes, before);
+    // There is no box for this DRM system.
+    let err = boxes.set_license_url(NAGRA_SYSTEM_ID, "https://prod.example.com/").unwrap_err();
+    assert!(matches!(err, PsshError::UnsupportedOperation { system: NAGRA_SYSTEM_ID, .. }));
+}
+
+#[test]
+fn test_replace_key_id() {
+    let old = DRMKeyId::from_hex("840d5cc9fa4523a83164451c615b206a").unwrap();
+    let new = DRMKeyId::from_hex("00112233445566778899aabbccddeeff").unwrap();
+    let mut boxes = from_base64(PLAYREADY).unwrap();
+    assert!(playready_data(&boxes[0]).checksum.is_some());
+    let mut widevine = PsshBox::new_widevine();
+    widevine.insert_key_id(old).unwrap();
+    boxes.add(widevine);
+
+    let replacement = boxes.replace_key_id(old, new).unwrap();
+    assert_eq!(replacement.replaced, 4);
+    assert_eq!(replacement.removed_checksums, vec![new]);
+    assert_eq!(boxes[0].key_ids, vec![new]);
+    assert_eq!(boxes[0].all_key_ids().len(), 1);
+    assert_eq!(boxes[0].all_key_ids()[0].key_id, new);
+    // The checksum of the old KID is no longer valid.
+    assert!(playready_data(&boxes[0]).checksum.is_none());
+    assert_eq!(boxes[1].key_ids, vec![new]);
+    assert_eq!(boxes[1].all_key_ids()[0].sources.len(), 2);
+    assert!(boxes.validate().iter().all(|f| !f.to_string().contains("key IDs in box header")));
+    assert_eq!(boxes.replace_key_id(old, new).unwrap(), KeyIdReplacement::default());
+
+    // In a 4.2 header the KIDs are in PROTECTINFO/KIDS, with their checksum as an attribute.
+    let old = DRMKeyId::from_hex("e82f184c3aaa57b4ace8606b5e3febad").unwrap();
+    let mut bx = from_base64(PLAYREADY_4_2).unwrap()[0].clone();
+    let replacement = bx.replace_key_id(old, new).unwrap();
+    assert_eq!(replacement.replaced, 2);
+    assert_eq!(replacement.removed_checksums, vec![new]);
+    assert_eq!(bx.key_ids[0], new);
+    let pi = playready_data(&bx).protect_info.as_ref().unwrap();
+    let kid = pi.kids().next().unwrap();
+    assert_eq!(kid.key_id(), Some(new));
+    assert!(kid.checksum.is_none());
+    assert!(pi.kids().skip(1).all(|k| k.checksum.is_some()));
+    assert!(bx.validate().is_empty());
+}
+
+#[test]
+fn test_insert_key_id() {
+    let kid1 = DRMKeyId::from_hex("840d5cc9fa4523a83164451c615b206a").unwrap();
+    let kid2 = DRMKeyId::from_hex("00112233445566778899aabbccddeeff").unwrap();
+    let mut boxes: PsshBoxVec = [PsshBox::new_widevine(), PsshBox::new_playready()].into_iter().collect();
+    boxes.insert_key_id(kid1).unwrap();
+    // Adding a key ID which is already present does nothing.
+    boxes.insert_key_id(kid1).unwrap();
+    for bx in &boxes {
+        assert_eq!(bx.key_ids, vec![kid1]);
+        assert_eq!(bx.all_key_ids()[0].sources.len(), 2);
+    }
+
+    // A PlayReady 4.0 header holds a single KID.
+    let before = boxes.clone();
+    let err = boxes.insert_key_id(kid2).unwrap_err();
+    assert!(matches!(err, PsshError::UnsupportedOperation { system: PLAYREADY_SYSTEM_ID, .. }));
+    assert_eq!(boxes, before);
+    let mut widevine = boxes[0].clone();
+    widevine.insert_key_id(kid2).unwrap();
+    assert_eq!(widevine.key_ids, vec![kid1, kid2]);
+
+    // A PlayReady 4.2 header can hold several KIDs.
+    let mut pr = PsshBox::new_playready();
+    if let PsshData::PlayReady(ref mut pd) = pr.pssh_data {
+        pd.record[0].wrm_header_mut().unwrap().version = String::from("4.2.0.0");
+    }
+    pr.insert_key_id(kid1).unwrap();
+    pr.insert_key_id(kid2).unwrap();
+    assert_eq!(pr.all_key_ids().iter().map(|t| t.key_id).collect::<Vec<_>>(), vec![kid1, kid2]);
+    let pi = playready_data(&pr).protect_info.as_ref().unwrap();
+    assert!(pi.kid.is_none());
+    assert_eq!(pi.kids.as_ref().unwrap().kid.len(), 2);
+
+    // A PlayReady 4.1 header holds a single KID, directly in PROTECTINFO.
+    let mut pr = PsshBox::new_playready();
+    if let PsshData::PlayReady(ref mut pd) = pr.pssh_data {
+        pd.record[0].wrm_header_mut().unwrap().version = String::from("4.1.0.0");
+    }
+    pr.insert_key_id(kid1).unwrap();
+    assert_eq!(playready_data(&pr).protect_info.as_ref().unwrap().kid.as_ref().unwrap().key_id(), Some(kid1));
+    assert!(pr.insert_key_id(kid2).is_err());
+
+    // A v0 box for a DRM system whose PSSH data can't hold key IDs.
+    let mut unknown = PsshBox::new_widevine();
+    unknown.version = 0;
+    unknown.pssh_data = PsshData::Unknown(Vec::new());
+    assert!(unknown.insert_key_id(kid1).is_err());
+    unknown.version = 1;
+    unknown.insert_key_id(kid1).unwrap();
+    assert_eq!(unknown.key_ids, vec![kid1]);
+}
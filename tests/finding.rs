@@ -41,7 +41,7 @@ fn test_find_iter() {
     println!("PLAYREADY-v1(2)> {pssh:?}");
     assert_eq!(pssh.flags, 0);
     if let PsshData::PlayReady(ref pd) = pssh.pssh_data {
-        let wrmh = &pd.record[0].record_value;
+        let wrmh = pd.record[0].wrm_header().unwrap();
         assert_eq!(wrmh.data.checksum, Some(BASE64_STANDARD.decode("5kJ+76Cqats=").unwrap()));
         assert!(wrmh.data.la_url.as_ref().is_some_and(|s| s.contains("youtube.com")));
     }
@@ -76,18 +76,17 @@ fn test_find_boxes_buffer() {
 
     // https://mpeggroup.github.io/FileFormatConformance/files/published/isobmff/18_pssh_v2.mp4
     //
-    // This wierd test file contains two PSSH boxes that we don't currently detect. One is a
-    // PlayReady box which seems to have an invalid header length (MP4Box.js is able to parse it,
-    // but it's perhaps implementing less error checking than this library). The second uses a
-    // "GPAC" SystemID which we don't currently recognize and which doesn't seem to be officially
-    // recognized.
+    // This wierd test file contains two PSSH boxes. One is a PlayReady box which seems to have an
+    // invalid header length (MP4Box.js is able to parse it, but it's perhaps implementing less
+    // error checking than this library), which we don't detect. The second uses a "GPAC" SystemID
+    // which doesn't seem to be officially recognized, and whose PSSH data is kept undecoded.
     let init = reqwest::blocking::get("https://github.com/dukesook/FileFormatConformance/raw/refs/heads/main/data/file_features/published/isobmff/18_pssh_v2.mp4")
         .unwrap()
         .bytes()
         .unwrap();
     let boxes: Vec<PsshBox> = find_boxes_buffer(&init)
         .collect();
-    assert!(boxes.is_empty());
+    assert!(boxes.iter().all(|bx| matches!(bx.pssh_data, PsshData::Unknown(_))));
 
     let buf = BASE64_STANDARD.decode("AAAAQHBzc2gAAAAA7e+LqXnWSs6jyCfc1R0h7QAAACAiGFlPVVRVQkU6NTM5ZjEyZjRhM2IzMTczYkjj3JWbBgAAAvRwc3NoAAAAAJoE8HmYQEKGq5LmW+CIX5UAAALU1AIAAAEAAQDKAjwAVwBSAE0ASABFAEEARABFAFIAIAB4AG0AbABuAHMAPQAiAGgAdAB0AHAAOgAvAC8AcwBjAGgAZQBtAGEAcwAuAG0AaQBjAHIAbwBzAG8AZgB0AC4AYwBvAG0ALwBEAFIATQAvADIAMAAwADcALwAwADMALwBQAGwAYQB5AFIAZQBhAGQAeQBIAGUAYQBkAGUAcgAiACAAdgBlAHIAcwBpAG8AbgA9ACIANAAuADAALgAwAC4AMAAiAD4APABEAEEAVABBAD4APABQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEsARQBZAEwARQBOAD4AMQA2ADwALwBLAEUAWQBMAEUATgA+ADwAQQBMAEcASQBEAD4AQQBFAFMAQwBUAFIAPAAvAEEATABHAEkARAA+ADwALwBQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEsASQBEAD4AdwB3AFQASwA0AFMAbwBkAEYAVgArAFgAMQAwAHYAYQBjAFMAQgBFAEcAUQA9AD0APAAvAEsASQBEAD4APABDAEgARQBDAEsAUwBVAE0APgA1AGsASgArADcANgBDAHEAYQB0AHMAPQA8AC8AQwBIAEUAQwBLAFMAVQBNAD4APABMAEEAXwBVAFIATAA+AGgAdAB0AHAAcwA6AC8ALwB3AHcAdwAuAHkAbwB1AHQAdQBiAGUALgBjAG8AbQAvAGEAcABpAC8AZAByAG0ALwBwAGwAYQB5AHIAZQBhAGQAeQA/AHMAbwB1AHIAYwBlAD0AWQBPAFUAVABVAEIARQAmAGEAbQBwADsAdgBpAGQAZQBvAF8AaQBkAD0ANQAzADkAZgAxADIAZgA0AGEAMwBiADMAMQA3ADMAYgA8AC8ATABBAF8AVQBSAEwAPgA8AC8ARABBAFQAQQA+ADwALwBXAFIATQBIAEUAQQBEAEUAUgA+AA==")
         .unwrap();
@@ -106,7 +105,7 @@ fn test_find_boxes_buffer() {
     println!("PLAYREADY-v1(2)> {pssh:?}");
     assert_eq!(pssh.flags, 0);
     if let PsshData::PlayReady(ref pd) = pssh.pssh_data {
-        let wrmh = &pd.record[0].record_value;
+        let wrmh = pd.record[0].wrm_header().unwrap();
         assert_eq!(wrmh.data.checksum, Some(BASE64_STANDARD.decode("5kJ+76Cqats=").unwrap()));
         assert!(wrmh.data.la_url.as_ref().is_some_and(|s| s.contains("youtube.com")));
     }
@@ -199,7 +198,7 @@ fn test_find_boxes_stream() {
     println!("PLAYREADY-v1(2)> {pssh:?}");
     assert_eq!(pssh.flags, 0);
     if let PsshData::PlayReady(ref pd) = pssh.pssh_data {
-        let wrmh = &pd.record[0].record_value;
+        let wrmh = pd.record[0].wrm_header().unwrap();
         assert_eq!(wrmh.data.checksum, Some(BASE64_STANDARD.decode("5kJ+76Cqats=").unwrap()));
         assert!(wrmh.data.la_url.as_ref().is_some_and(|s| s.contains("youtube.com")));
     }
@@ -339,10 +338,10 @@ fn test_find_boxes_streaming() {
     corrupted_buf.extend_from_slice(b"pssh");
     corrupted_buf.extend_from_slice(&[0u8; 100]);
     let reader2 = Cursor::new(&corrupted_buf);
-    let boxes2: Vec<PsshBox> = find_boxes_stream(reader2)
-        .map(|bx| bx.unwrap())
-        .collect();
-    assert_eq!(boxes2.len(), 0);
+    let results2: Vec<_> = find_boxes_stream(reader2).collect();
+    // The declared size exceeds the buffering limit, so the box is rejected from its header.
+    assert_eq!(results2.len(), 1);
+    assert!(results2[0].as_ref().is_err_and(|e| e.to_string().contains("buffered octets")));
 }
 
 
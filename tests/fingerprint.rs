@@ -0,0 +1,77 @@
+//! Tests for semantic comparison and fingerprinting of PSSH boxes
+
+use test_log::test;
+use pssh_box::{from_bytes, PsshBox, PsshBoxVec, PsshData, DRMKeyId, ToBytes, PLAYREADY_SYSTEM_ID};
+use pssh_box::playready::to_utf16;
+
+
+// A v0 PlayReady PSSH box containing a single Rights Management record with the WRMHEADER `xml`.
+fn playready_box(xml: &str) -> PsshBox {
+    let record: Vec<u8> = to_utf16(xml).iter().flat_map(|u| u.to_le_bytes()).collect();
+    let mut data = Vec::new();
+    data.extend_from_slice(&(10 + record.len() as u32).to_le_bytes());
+    data.extend_from_slice(&1u16.to_le_bytes());
+    data.extend_from_slice(&1u16.to_le_bytes());
+    data.extend_from_slice(&(record.len() as u16).to_le_bytes());
+    data.extend_from_slice(&record);
+    let mut octets = Vec::new();
+    octets.extend_from_slice(&(32 + data.len() as u32).to_be_bytes());
+    octets.extend_from_slice(b"pssh");
+    octets.extend_from_slice(&[0, 0, 0, 0]);
+    octets.extend_from_slice(&PLAYREADY_SYSTEM_ID.to_bytes());
+    octets.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    octets.extend_from_slice(&data);
+    from_bytes(&octets).unwrap()[0].clone()
+}
+
+#[test]
+fn test_semantically_eq_playready() {
+    let compact = playready_box(r#"<WRMHEADER xmlns="http://schemas.microsoft.com/DRM/2007/03/PlayReadyHeader" version="4.0.0.0"><DATA><KID>MlSJV3aYRSNHVmVHKTgjQQ==</KID></DATA></WRMHEADER>"#);
+    let spaced = playready_box(r#"<WRMHEADER version="4.0.0.0"  xmlns="http://schemas.microsoft.com/DRM/2007/03/PlayReadyHeader">
+  <DATA>
+    <KID>MlSJV3aYRSNHVmVHKTgjQQ==</KID>
+  </DATA>
+</WRMHEADER>"#);
+    assert_ne!(compact.to_bytes(), spaced.to_bytes());
+    assert!(compact.semantically_eq(&spaced));
+    assert_eq!(compact.fingerprint().unwrap(), spaced.fingerprint().unwrap());
+
+    let other = playready_box(r#"<WRMHEADER xmlns="http://schemas.microsoft.com/DRM/2007/03/PlayReadyHeader" version="4.0.0.0"><DATA><KID>AAAAAAAAAAAAAAAAAAAAAA==</KID></DATA></WRMHEADER>"#);
+    assert!(!compact.semantically_eq(&other));
+    assert_ne!(compact.fingerprint().unwrap(), other.fingerprint().unwrap());
+
+    // The key IDs in the header of a v1 box are those in the PSSH data.
+    let v1 = compact.to_v1();
+    assert_ne!(v1, compact);
+    assert!(v1.semantically_eq(&compact));
+    assert_eq!(v1.fingerprint().unwrap(), compact.fingerprint().unwrap());
+}
+
+#[test]
+fn test_fingerprint_widevine() {
+    let kid1 = DRMKeyId::from_hex("840d5cc9fa4523a83164451c615b206a").unwrap();
+    let kid2 = DRMKeyId::from_hex("00112233445566778899aabbccddeeff").unwrap();
+    let mut a = PsshBox::new_widevine();
+    a.add_key_id(kid1);
+    a.add_key_id(kid2);
+    let mut b = PsshBox::new_widevine();
+    b.add_key_id(kid2);
+    b.add_key_id(kid1);
+    assert!(a.semantically_eq(&b));
+    assert_eq!(a.fingerprint().unwrap(), b.fingerprint().unwrap());
+
+    // The header key IDs of a v1 box are significant when they aren't in the PSSH data.
+    let mut c = b.clone();
+    c.key_ids.pop();
+    assert!(!a.semantically_eq(&c));
+    if let PsshData::Widevine(ref mut wv) = c.pssh_data {
+        wv.provider = Some(String::from("widevine_test"));
+    }
+    assert_ne!(a.fingerprint().unwrap(), c.fingerprint().unwrap());
+
+    let set1: PsshBoxVec = [a.clone(), c.clone()].into_iter().collect();
+    let set2: PsshBoxVec = [c.clone(), b.clone(), a.clone()].into_iter().collect();
+    let set3: PsshBoxVec = [a.clone()].into_iter().collect();
+    assert_eq!(set1.fingerprint().unwrap(), set2.fingerprint().unwrap());
+    assert_ne!(set1.fingerprint().unwrap(), set3.fingerprint().unwrap());
+}
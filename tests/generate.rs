@@ -93,7 +93,7 @@ fn test_roundtrip_playready_bytes() {
     let kid2 = DRMKeyId::try_from("AAAAaaaa222200008888dddd3333eeee").unwrap();
     pssh.add_key_id(kid2);
     if let PsshData::PlayReady(ref mut pd) = pssh.pssh_data {
-        let wrmh = &mut pd.record[0].record_value;
+        let wrmh = pd.record[0].wrm_header_mut().unwrap();
         wrmh.data.checksum = Some(BASE64_STANDARD.decode("7zDsYfDVHUY=").unwrap());
         wrmh.data.lui_url = Some(String::from("http://www.example.com/"));
     }
@@ -106,7 +106,7 @@ fn test_roundtrip_playready_bytes() {
     assert_eq!(parsed.key_ids[1], kid2);
     println!("PlayReady> {pssh:?}");
     if let PsshData::PlayReady(ref pd) = parsed.pssh_data {
-        let wrmh = &pd.record[0].record_value;
+        let wrmh = pd.record[0].wrm_header().unwrap();
         assert_eq!(wrmh.data.checksum, Some(BASE64_STANDARD.decode("7zDsYfDVHUY=").unwrap()));
     }
 }
@@ -121,7 +121,7 @@ fn test_roundtrip_playready_base64() {
     let kid2 = DRMKeyId::try_from("AAAAaaaa222200008888dddd3333eeee").unwrap();
     pssh.add_key_id(kid2);
     if let PsshData::PlayReady(ref mut pd) = pssh.pssh_data {
-        let wrmh = &mut pd.record[0].record_value;
+        let wrmh = pd.record[0].wrm_header_mut().unwrap();
         wrmh.data.checksum = Some(BASE64_STANDARD.decode("7zDsYfDVHUY=").unwrap());
         wrmh.data.lui_url = Some(String::from("http://www.example.com/"));
     }
@@ -134,8 +134,116 @@ fn test_roundtrip_playready_base64() {
     assert_eq!(parsed.key_ids[1], kid2);
     println!("PlayReady> {pssh:?}");
     if let PsshData::PlayReady(ref pd) = parsed.pssh_data {
-        let wrmh = &pd.record[0].record_value;
+        let wrmh = pd.record[0].wrm_header().unwrap();
         assert_eq!(wrmh.data.checksum, Some(BASE64_STANDARD.decode("7zDsYfDVHUY=").unwrap()));
         assert_eq!(wrmh.data.lui_url, Some(String::from("http://www.example.com/")));
     }
 }
+
+#[test]
+fn test_playready_builder() {
+    use pssh_box::{PsshError, Lint};
+    use pssh_box::playready::{PlayReadyHeaderBuilder, PlayReadyHeaderVersion, PlayReadyAlgorithm};
+
+    let kid1 = DRMKeyId::try_from("4444aaaa2222bbbb8888eeee7777cccc").unwrap();
+    let kid2 = DRMKeyId::try_from("00112233445566778899aabbccddeeff").unwrap();
+    let pssh = PlayReadyHeaderBuilder::new(PlayReadyHeaderVersion::V4_0)
+        .key_id(kid1)
+        .la_url("https://example.com/rightsmanager.asmx")
+        .lui_url("https://example.com/lui")
+        .build_pssh_box()
+        .unwrap();
+    assert_eq!(pssh.system_id, PLAYREADY_SYSTEM_ID);
+    assert_eq!(pssh.key_ids, vec![kid1]);
+    assert!(pssh.validate().is_empty());
+    if let PsshData::PlayReady(ref pd) = pssh.pssh_data {
+        let wrmh = pd.record[0].wrm_header().unwrap();
+        assert_eq!(wrmh.version, "4.0.0.0");
+        let pi = wrmh.data.protect_info.as_ref().unwrap();
+        assert_eq!(pi.keylen, Some(16));
+        assert_eq!(pi.algid.as_deref(), Some("AESCTR"));
+        assert_eq!(wrmh.data.kids[0].key_id(), Some(kid1));
+    }
+    let parsed = from_bytes(&pssh.to_bytes()).unwrap();
+    assert_eq!(parsed[0], pssh);
+
+    let pssh = PlayReadyHeaderBuilder::new(PlayReadyHeaderVersion::V4_2)
+        .key_id(kid1)
+        .key_id(kid2)
+        .algorithm(PlayReadyAlgorithm::Cocktail)
+        .decryptor_setup("ONDEMAND")
+        .build_pssh_box()
+        .unwrap();
+    assert_eq!(pssh.all_key_ids().iter().map(|t| t.key_id).collect::<Vec<_>>(), vec![kid1, kid2]);
+    if let PsshData::PlayReady(ref pd) = pssh.pssh_data {
+        let data = &pd.record[0].wrm_header().unwrap().data;
+        assert!(data.kids.is_empty());
+        let pi = data.protect_info.as_ref().unwrap();
+        assert!(pi.kids().all(|k| k.algid.as_deref() == Some("COCKTAIL")));
+        assert_eq!(data.decryptor_setup.as_deref(), Some("ONDEMAND"));
+    }
+    let parsed = from_bytes(&pssh.to_bytes()).unwrap();
+    assert_eq!(parsed[0], pssh);
+
+    // In version 4.3 the ALGID is optional, and AESCBC is allowed.
+    let wrmh = PlayReadyHeaderBuilder::new(PlayReadyHeaderVersion::V4_3).key_id(kid1).build().unwrap();
+    assert_eq!(wrmh.data.protect_info.unwrap().kids().next().unwrap().algid, None);
+    let pssh = PlayReadyHeaderBuilder::new(PlayReadyHeaderVersion::V4_3)
+        .key_id(kid1)
+        .algorithm(PlayReadyAlgorithm::AesCbc)
+        .build_pssh_box()
+        .unwrap();
+    assert!(!pssh.validate().iter().any(|f| matches!(f.lint, Lint::PlayReadyMissingElement { .. })));
+
+    for builder in [
+        PlayReadyHeaderBuilder::new(PlayReadyHeaderVersion::V4_2).key_id(kid1).algorithm(PlayReadyAlgorithm::AesCbc),
+        PlayReadyHeaderBuilder::new(PlayReadyHeaderVersion::V4_0),
+        PlayReadyHeaderBuilder::new(PlayReadyHeaderVersion::V4_0).key_id(kid1).key_id(kid2),
+        PlayReadyHeaderBuilder::new(PlayReadyHeaderVersion::V4_1).key_id(kid1).key_id(kid2),
+        PlayReadyHeaderBuilder::new(PlayReadyHeaderVersion::V4_0).key_id(kid1).decryptor_setup("ONDEMAND"),
+    ] {
+        assert!(matches!(builder.build(), Err(PsshError::UnsupportedOperation { .. })));
+    }
+}
+
+
+// The XML of generated headers, compared with a 4.2 header from the PlayReady test server and with
+// the layout of a 4.1 header in the PlayReady Header Specification.
+#[test]
+fn test_playready_builder_xml() {
+    use pssh_box::playready::{PlayReadyHeaderBuilder, PlayReadyHeaderVersion, PlayReadyAlgorithm};
+
+    let la_url = "https://test.playready.microsoft.com/service/rightsmanager.asmx?cfg=(kid:TBgv6Ko6tFes6GBrXj/rrQ==,contentkey:wvr2bihSzExKdR8KKpQf2w==),(kid:xs97CKX3Fle4QGqm66M2ng==,contentkey:goHOjbkINpfZdw2H25YoNQ==),(kid:I0BrDaGNdV6vaHXFFMWbYw==,contentkey:WC1rcWEb4EyI4iqqEEQeLA==)";
+    let keys = [("e82f184c3aaa57b4ace8606b5e3febad", "wvr2bihSzExKdR8KKpQf2w=="),
+                ("087bcfc6f7a55716b8406aa6eba3369e", "goHOjbkINpfZdw2H25YoNQ=="),
+                ("0d6b40238da15e75af6875c514c59b63", "WC1rcWEb4EyI4iqqEEQeLA==")];
+    let mut builder = PlayReadyHeaderBuilder::new(PlayReadyHeaderVersion::V4_2)
+        .algorithm(PlayReadyAlgorithm::AesCtr)
+        .la_url(la_url);
+    for (kid, key) in keys {
+        let kid = DRMKeyId::try_from(kid).unwrap();
+        builder = builder.key_id(kid).content_key(kid, &BASE64_STANDARD.decode(key).unwrap());
+    }
+    let xml = builder.build().unwrap().to_xml().unwrap();
+    assert_eq!(xml, format!("<WRMHEADER xmlns=\"http://schemas.microsoft.com/DRM/2007/03/PlayReadyHeader\" version=\"4.2.0.0\"><DATA><PROTECTINFO><KIDS><KID ALGID=\"AESCTR\" CHECKSUM=\"+NV9/8jbfrw=\" VALUE=\"TBgv6Ko6tFes6GBrXj/rrQ==\"></KID><KID ALGID=\"AESCTR\" CHECKSUM=\"Z10iOYYzH3k=\" VALUE=\"xs97CKX3Fle4QGqm66M2ng==\"></KID><KID ALGID=\"AESCTR\" CHECKSUM=\"OEuMyDeQ1s8=\" VALUE=\"I0BrDaGNdV6vaHXFFMWbYw==\"></KID></KIDS></PROTECTINFO><LA_URL>{la_url}</LA_URL></DATA></WRMHEADER>"));
+
+    let kid = DRMKeyId::try_from("e82f184c3aaa57b4ace8606b5e3febad").unwrap();
+    let xml = PlayReadyHeaderBuilder::new(PlayReadyHeaderVersion::V4_1)
+        .key_id(kid)
+        .content_key(kid, &BASE64_STANDARD.decode("wvr2bihSzExKdR8KKpQf2w==").unwrap())
+        .la_url("https://example.com/rightsmanager.asmx")
+        .build()
+        .unwrap()
+        .to_xml()
+        .unwrap();
+    assert_eq!(xml, "<WRMHEADER xmlns=\"http://schemas.microsoft.com/DRM/2007/03/PlayReadyHeader\" version=\"4.1.0.0\"><DATA><PROTECTINFO><KID ALGID=\"AESCTR\" CHECKSUM=\"+NV9/8jbfrw=\" VALUE=\"TBgv6Ko6tFes6GBrXj/rrQ==\"></KID></PROTECTINFO><LA_URL>https://example.com/rightsmanager.asmx</LA_URL></DATA></WRMHEADER>");
+
+    let xml = PlayReadyHeaderBuilder::new(PlayReadyHeaderVersion::V4_0)
+        .key_id(kid)
+        .content_key(kid, &BASE64_STANDARD.decode("wvr2bihSzExKdR8KKpQf2w==").unwrap())
+        .build()
+        .unwrap()
+        .to_xml()
+        .unwrap();
+    assert_eq!(xml, "<WRMHEADER xmlns=\"http://schemas.microsoft.com/DRM/2007/03/PlayReadyHeader\" version=\"4.0.0.0\"><DATA><PROTECTINFO><KEYLEN>16</KEYLEN><ALGID>AESCTR</ALGID></PROTECTINFO><KID>TBgv6Ko6tFes6GBrXj/rrQ==</KID><CHECKSUM>+NV9/8jbfrw=</CHECKSUM></DATA></WRMHEADER>");
+}
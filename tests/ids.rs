@@ -0,0 +1,120 @@
+//! Tests for parsing and formatting DRM system IDs and key IDs
+
+use serde::{Serialize, Deserialize};
+use test_log::test;
+use pssh_box::{DRMKeyId, DRMSystemId, PsshError, WIDEVINE_SYSTEM_ID, MARLIN_SYSTEM_ID};
+
+
+#[test]
+fn test_system_id_from_str() {
+    for s in ["edef8ba979d64acea3c827dcd51d21ed",
+              "EDEF8BA979D64ACEA3C827DCD51D21ED",
+              "edef8ba9-79d6-4ace-a3c8-27dcd51d21ed",
+              "urn:uuid:edef8ba9-79d6-4ace-a3c8-27dcd51d21ed",
+              "urn:uuid:EDEF8BA9-79D6-4ACE-A3C8-27DCD51D21ED",
+              "URN:UUID:EDEF8BA9-79D6-4ACE-A3C8-27DCD51D21ED",
+              "urn:uuid:edef8ba979d64acea3c827dcd51d21ed",
+              "7e+LqXnWSs6jyCfc1R0h7Q==",
+              "7e+LqXnWSs6jyCfc1R0h7Q",
+              "7e-LqXnWSs6jyCfc1R0h7Q"] {
+        assert_eq!(s.parse::<DRMSystemId>().unwrap(), WIDEVINE_SYSTEM_ID, "parsing {s}");
+        assert_eq!(DRMSystemId::try_from(s).unwrap(), WIDEVINE_SYSTEM_ID, "parsing {s}");
+    }
+    assert_eq!(WIDEVINE_SYSTEM_ID.to_urn(), "urn:uuid:edef8ba9-79d6-4ace-a3c8-27dcd51d21ed");
+    assert_eq!(WIDEVINE_SYSTEM_ID.to_urn().parse::<DRMSystemId>().unwrap(), WIDEVINE_SYSTEM_ID);
+    assert_eq!(MARLIN_SYSTEM_ID.to_urn().parse::<DRMSystemId>().unwrap(), MARLIN_SYSTEM_ID);
+
+    assert!(matches!("".parse::<DRMSystemId>(),
+                     Err(PsshError::InvalidLength { expected: 32, found: 0, .. })));
+    assert!(matches!("edef8ba979d64acea3c827dcd51d21".parse::<DRMSystemId>(),
+                     Err(PsshError::InvalidLength { .. })));
+    // Dashes must be in the positions used by UUIDs.
+    assert!(matches!("edef8ba979-d6-4ace-a3c8-27dcd51d21ed".parse::<DRMSystemId>(),
+                     Err(PsshError::InvalidLength { .. })));
+    // Error offsets are positions in the input string.
+    assert!(matches!("edef8ba9-79d6-4ace-a3c8-27dcd51d21eX".parse::<DRMSystemId>(),
+                     Err(PsshError::InvalidEncoding { offset: 35, .. })));
+    assert!(matches!("urn:uuid:edef8ba9-79d6-4aXe-a3c8-27dcd51d21ed".parse::<DRMSystemId>(),
+                     Err(PsshError::InvalidEncoding { offset: 25, .. })));
+    assert!(matches!("7e+LqXnWSs6jyCfc1R0h7Q!!".parse::<DRMSystemId>(),
+                     Err(PsshError::InvalidEncoding { .. })));
+    // Base64 is not accepted after the urn:uuid: prefix.
+    assert!("urn:uuid:7e+LqXnWSs6jyCfc1R0h7Q==".parse::<DRMSystemId>().is_err());
+    assert!("édef8ba979d64acea3c827dcd51d21e".parse::<DRMSystemId>().is_err());
+}
+
+#[test]
+fn test_system_id_serde() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Config {
+        #[serde(with = "pssh_box::serde_uuid")]
+        system_id: DRMSystemId,
+    }
+
+    let config = Config { system_id: WIDEVINE_SYSTEM_ID };
+    let json = serde_json::to_string(&config).unwrap();
+    assert_eq!(json, r#"{"system_id":"edef8ba9-79d6-4ace-a3c8-27dcd51d21ed"}"#);
+    assert_eq!(serde_json::from_str::<Config>(&json).unwrap(), config);
+    let urn = r#"{"system_id":"urn:uuid:EDEF8BA9-79D6-4ACE-A3C8-27DCD51D21ED"}"#;
+    assert_eq!(serde_json::from_str::<Config>(urn).unwrap(), config);
+    assert!(serde_json::from_str::<Config>(r#"{"system_id":"edef"}"#).is_err());
+    assert!(serde_json::from_str::<Config>(r#"{"system_id":[237,239]}"#).is_err());
+
+    // The default representation is unchanged.
+    let json = serde_json::to_string(&WIDEVINE_SYSTEM_ID).unwrap();
+    assert!(json.starts_with(r#"{"id":[237,239,"#));
+}
+
+#[test]
+fn test_key_id_formats() {
+    let kid = DRMKeyId::from_uuid_str("57895432-9876-2345-4756-654729382341").unwrap();
+    assert_eq!(kid.to_hex(), "57895432987623454756654729382341");
+    assert_eq!(kid.to_uuid_string(), "57895432-9876-2345-4756-654729382341");
+    assert_eq!(kid.to_guid_string(), "{57895432-9876-2345-4756-654729382341}");
+    assert_eq!(kid.to_base64url(), "V4lUMph2I0VHVmVHKTgjQQ");
+    assert_eq!(kid.to_playready_base64(), "MlSJV3aYRSNHVmVHKTgjQQ==");
+    assert_eq!(kid.to_guid_bytes()[..4], [0x32, 0x54, 0x89, 0x57]);
+    assert_eq!(kid.as_bytes()[..4], [0x57, 0x89, 0x54, 0x32]);
+
+    assert_eq!(DRMKeyId::from_hex("57895432987623454756654729382341").unwrap(), kid);
+    assert_eq!(DRMKeyId::from_guid_str("{57895432-9876-2345-4756-654729382341}").unwrap(), kid);
+    assert_eq!(DRMKeyId::from_base64url("V4lUMph2I0VHVmVHKTgjQQ").unwrap(), kid);
+    assert_eq!(DRMKeyId::from_base64url("V4lUMph2I0VHVmVHKTgjQQ==").unwrap(), kid);
+    assert_eq!(DRMKeyId::from_playready_base64("MlSJV3aYRSNHVmVHKTgjQQ==").unwrap(), kid);
+    assert_eq!(DRMKeyId::from_guid_bytes(kid.to_guid_bytes()), kid);
+    assert_eq!(DRMKeyId::from(<[u8; 16]>::from(kid)), kid);
+    for s in ["57895432987623454756654729382341",
+              "57895432-9876-2345-4756-654729382341",
+              "{57895432-9876-2345-4756-654729382341}",
+              "urn:uuid:57895432-9876-2345-4756-654729382341"] {
+        assert_eq!(s.parse::<DRMKeyId>().unwrap(), kid, "parsing {s}");
+        assert_eq!(DRMKeyId::try_from(s).unwrap(), kid, "parsing {s}");
+    }
+    // Base64 is ambiguous as to byte order, so FromStr doesn't accept it.
+    assert!("V4lUMph2I0VHVmVHKTgjQQ".parse::<DRMKeyId>().is_err());
+
+    assert!(matches!(DRMKeyId::from_hex("5789"), Err(PsshError::InvalidLength { .. })));
+    assert!(matches!(DRMKeyId::from_uuid_str("57895432987623454756654729382341"),
+                     Err(PsshError::InvalidLength { .. })));
+    assert!(matches!(DRMKeyId::from_guid_str("57895432-9876-2345-4756-654729382341"),
+                     Err(PsshError::InvalidEncoding { offset: 0, .. })));
+    assert!(matches!(DRMKeyId::from_guid_str("{57895432-9876-2345-4756-65472938234X}"),
+                     Err(PsshError::InvalidEncoding { offset: 36, .. })));
+    assert!(matches!(DRMKeyId::from_base64url("V4lUMph2I0VHVmVH"), Err(PsshError::InvalidLength { .. })));
+    assert!(matches!(DRMKeyId::from_playready_base64("MlSJV3aY!"), Err(PsshError::InvalidEncoding { .. })));
+}
+
+#[cfg(feature = "uuid")]
+#[test]
+fn test_uuid_feature() {
+    let uuid = uuid::Uuid::parse_str("57895432-9876-2345-4756-654729382341").unwrap();
+    let kid = DRMKeyId::from(uuid);
+    assert_eq!(kid.to_uuid_string(), uuid.to_string());
+    assert_eq!(uuid::Uuid::from(kid), uuid);
+    // The GUID byte order used by PlayReady matches the little-endian fields of uuid's to_bytes_le.
+    assert_eq!(kid.to_guid_bytes(), uuid.to_bytes_le());
+
+    let system_id = DRMSystemId::from(uuid::Uuid::parse_str("edef8ba9-79d6-4ace-a3c8-27dcd51d21ed").unwrap());
+    assert_eq!(system_id, WIDEVINE_SYSTEM_ID);
+    assert_eq!(uuid::Uuid::from(WIDEVINE_SYSTEM_ID).urn().to_string(), WIDEVINE_SYSTEM_ID.to_urn());
+}
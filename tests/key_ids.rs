@@ -0,0 +1,117 @@
+//! Tests for the extraction of key IDs from PSSH boxes
+
+use test_log::test;
+use pssh_box::{from_base64, from_bytes, PsshBox, PsshBoxVec, PsshData, PsshError, DRMKeyId, KeyIdLocation, KeyIdSource, ToBytes};
+use pssh_box::{WIDEVINE_SYSTEM_ID, PLAYREADY_SYSTEM_ID, NAGRA_SYSTEM_ID};
+
+
+fn locations(sources: &[KeyIdSource]) -> Vec<KeyIdLocation> {
+    sources.iter().map(|s| s.location).collect()
+}
+
+#[test]
+fn test_key_ids_widevine() {
+    let kid1 = DRMKeyId::from_hex("840d5cc9fa4523a83164451c615b206a").unwrap();
+    let kid2 = DRMKeyId::from_hex("00112233445566778899aabbccddeeff").unwrap();
+    let mut bx = PsshBox::new_widevine();
+    bx.add_key_id(kid1);
+    if let PsshData::Widevine(ref mut wv) = bx.pssh_data {
+        wv.key_id.push(kid2.as_bytes().to_vec());
+        wv.key_id.push(kid1.as_bytes().to_vec());
+        // Malformed key IDs are ignored.
+        wv.key_id.push(vec![1, 2, 3]);
+    }
+    let kids = bx.all_key_ids();
+    assert_eq!(kids.len(), 2);
+    assert_eq!(kids[0].key_id, kid1);
+    assert_eq!(locations(&kids[0].sources), vec![KeyIdLocation::Header, KeyIdLocation::WidevineKeyId]);
+    assert_eq!(kids[1].key_id, kid2);
+    assert_eq!(kids[1].sources, vec![KeyIdSource { system_id: WIDEVINE_SYSTEM_ID, location: KeyIdLocation::WidevineKeyId }]);
+
+    // The header of a v0 box doesn't hold key IDs.
+    bx.version = 0;
+    let kids = bx.all_key_ids();
+    assert_eq!(locations(&kids[0].sources), vec![KeyIdLocation::WidevineKeyId]);
+    assert_eq!(kids[0].key_id, kid2);
+
+    assert!(PsshBox::new_widevine().all_key_ids().is_empty());
+    assert!(PsshBox::new_playready().all_key_ids().is_empty());
+}
+
+#[test]
+fn test_key_ids_multi_drm() {
+    let playready = from_base64("AAACJnBzc2gAAAAAmgTweZhAQoarkuZb4IhflQAAAgYGAgAAAQABAPwBPABXAFIATQBIAEUAQQBEAEUAUgAgAHgAbQBsAG4AcwA9ACIAaAB0AHQAcAA6AC8ALwBzAGMAaABlAG0AYQBzAC4AbQBpAGMAcgBvAHMAbwBmAHQALgBjAG8AbQAvAEQAUgBNAC8AMgAwADAANwAvADAAMwAvAFAAbABhAHkAUgBlAGEAZAB5AEgAZQBhAGQAZQByACIAIAB2AGUAcgBzAGkAbwBuAD0AIgA0AC4AMAAuADAALgAwACIAPgA8AEQAQQBUAEEAPgA8AFAAUgBPAFQARQBDAFQASQBOAEYATwA+ADwASwBFAFkATABFAE4APgAxADYAPAAvAEsARQBZAEwARQBOAD4APABBAEwARwBJAEQAPgBBAEUAUwBDAFQAUgA8AC8AQQBMAEcASQBEAD4APAAvAFAAUgBPAFQARQBDAFQASQBOAEYATwA+ADwASwBJAEQAPgBNAGwAUwBKAFYAMwBhAFkAUgBTAE4ASABWAG0AVgBIAEsAVABnAGoAUQBRAD0APQA8AC8ASwBJAEQAPgA8AEMASABFAEMASwBTAFUATQA+AFUARwBOAFYAQgBTAHUAZwAzADgAcwA9ADwALwBDAEgARQBDAEsAUwBVAE0APgA8AC8ARABBAFQAQQA+ADwALwBXAFIATQBIAEUAQQBEAEUAUgA+AA==")
+        .unwrap();
+    let kid = DRMKeyId::from_playready_base64("MlSJV3aYRSNHVmVHKTgjQQ==").unwrap();
+    let kids = playready[0].all_key_ids();
+    assert_eq!(kids.len(), 1);
+    assert_eq!(kids[0].key_id, kid);
+    assert_eq!(kids[0].sources, vec![KeyIdSource { system_id: PLAYREADY_SYSTEM_ID, location: KeyIdLocation::PlayReadyKid }]);
+
+    let nagra = from_base64("AAAAinBzc2gAAAAArbQcJC2/Sm2Vi0RXwNJ7lQAAAGpleUpqYjI1MFpXNTBTV1FpT2lKSGIyNWxJR2x1SUhSb1pTQjNhVzVrSWl3aWEyVjVTV1FpT2lJNU1XRXhaVFEwTnkwMk9EUmlMVFJoWTJVdFlqWmpaUzAwTURFeE5qQm1NRGRtTURFaWZR")
+        .unwrap();
+    let nagra_kid = DRMKeyId::from_uuid_str("91a1e447-684b-4ace-b6ce-401160f07f01").unwrap();
+    assert_eq!(nagra[0].all_key_ids()[0].key_id, nagra_kid);
+
+    let wiseplay = from_base64("AAAAn3Bzc2gAAAAAPV5tNZuaQei4Q908bnLELAAAAH97InZlcnNpb24iOiJWMS4wIiwia2lkcyI6WyJtWkNpWm9ENVBnbWxXcjgxcHcyQVpRPT0iXSwiY29udGVudElEIjoiZXlKaGMzTmxkRWxrSWpvaWRIWnRaV1JwWVMweU1ETXhPRFUyTnlKOSIsImVuc2NoZW1hIjoiY2VuYyJ9")
+        .unwrap();
+    let wiseplay_kid = DRMKeyId::from_hex("9990a26680f93e09a55abf35a70d8065").unwrap();
+    assert_eq!(locations(&wiseplay[0].all_key_ids()[0].sources), vec![KeyIdLocation::WisePlayKids]);
+
+    // The same key ID used by two DRM systems is reported once, with both sources.
+    let mut widevine = PsshBox::new_widevine();
+    widevine.add_key_id(kid);
+    let mut boxes: PsshBoxVec = PsshBoxVec::new();
+    boxes.add(widevine);
+    boxes.add(playready[0].clone());
+    boxes.add(nagra[0].clone());
+    boxes.add(wiseplay[0].clone());
+    let kids = boxes.all_key_ids();
+    assert_eq!(kids.iter().map(|t| t.key_id).collect::<Vec<_>>(), vec![kid, nagra_kid, wiseplay_kid]);
+    assert_eq!(kids[0].sources, vec![
+        KeyIdSource { system_id: WIDEVINE_SYSTEM_ID, location: KeyIdLocation::Header },
+        KeyIdSource { system_id: PLAYREADY_SYSTEM_ID, location: KeyIdLocation::PlayReadyKid },
+    ]);
+    assert_eq!(kids[1].sources[0].system_id, NAGRA_SYSTEM_ID);
+}
+
+#[test]
+fn test_convert_version() {
+    let kid1 = DRMKeyId::from_hex("840d5cc9fa4523a83164451c615b206a").unwrap();
+    let kid2 = DRMKeyId::from_hex("00112233445566778899aabbccddeeff").unwrap();
+    let mut bx = PsshBox::new_widevine();
+    bx.version = 0;
+    if let PsshData::Widevine(ref mut wv) = bx.pssh_data {
+        wv.key_id.push(kid1.as_bytes().to_vec());
+        wv.key_id.push(kid2.as_bytes().to_vec());
+    }
+    let v1 = bx.to_v1();
+    assert_eq!(v1.version, 1);
+    assert_eq!(v1.key_ids, vec![kid1, kid2]);
+    assert_eq!(v1.pssh_data, bx.pssh_data);
+    let parsed = from_bytes(&v1.clone().to_bytes()).unwrap();
+    assert_eq!(parsed[0].key_ids, vec![kid1, kid2]);
+    // Converting is idempotent.
+    assert_eq!(v1.to_v1(), v1);
+
+    let v0 = v1.to_v0().unwrap();
+    assert_eq!(v0, bx);
+    assert_eq!(v0.to_v0().unwrap(), bx);
+
+    // A KID which appears only in the header would be lost.
+    let mut header_only = v1.clone();
+    header_only.add_key_id(DRMKeyId::from_hex("ffeeddccbbaa99887766554433221100").unwrap());
+    let err = header_only.to_v0().unwrap_err();
+    assert!(matches!(err, PsshError::KeyIdsNotInPayload { ref key_ids, .. }
+                     if key_ids == &[DRMKeyId::from_hex("ffeeddccbbaa99887766554433221100").unwrap()]));
+    assert!(err.to_string().contains("ffeeddcc-bbaa-9988-7766-554433221100"));
+    assert!(PsshBox::new_playready().to_v0().is_ok());
+
+    // A v1 box with a PlayReady 4.2 header, whose KIDs are in PROTECTINFO/KIDS.
+    let v1 = from_base64("AAAFyHBzc2gBAAAAmgTweZhAQoarkuZb4IhflQAAAAPoLxhMOqpXtKzoYGteP+utCHvPxvelVxa4QGqm66M2ng1rQCONoV51r2h1xRTFm2MAAAV0dAUAAAEAAQBqBTwAVwBSAE0ASABFAEEARABFAFIAIAB4AG0AbABuAHMAPQAiAGgAdAB0AHAAOgAvAC8AcwBjAGgAZQBtAGEAcwAuAG0AaQBjAHIAbwBzAG8AZgB0AC4AYwBvAG0ALwBEAFIATQAvADIAMAAwADcALwAwADMALwBQAGwAYQB5AFIAZQBhAGQAeQBIAGUAYQBkAGUAcgAiACAAdgBlAHIAcwBpAG8AbgA9ACIANAAuADIALgAwAC4AMAAiAD4APABEAEEAVABBAD4APABQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEsASQBEAFMAPgA8AEsASQBEACAAQQBMAEcASQBEAD0AIgBBAEUAUwBDAFQAUgAiACAAQwBIAEUAQwBLAFMAVQBNAD0AIgArAE4AVgA5AC8AOABqAGIAZgByAHcAPQAiACAAVgBBAEwAVQBFAD0AIgBUAEIAZwB2ADYASwBvADYAdABGAGUAcwA2AEcAQgByAFgAagAvAHIAcgBRAD0APQAiAD4APAAvAEsASQBEAD4APABLAEkARAAgAEEATABHAEkARAA9ACIAQQBFAFMAQwBUAFIAIgAgAEMASABFAEMASwBTAFUATQA9ACIAWgAxADAAaQBPAFkAWQB6AEgAMwBrAD0AIgAgAFYAQQBMAFUARQA9ACIAeABzADkANwBDAEsAWAAzAEYAbABlADQAUQBHAHEAbQA2ADYATQAyAG4AZwA9AD0AIgA+ADwALwBLAEkARAA+ADwASwBJAEQAIABBAEwARwBJAEQAPQAiAEEARQBTAEMAVABSACIAIABDAEgARQBDAEsAUwBVAE0APQAiAE8ARQB1AE0AeQBEAGUAUQAxAHMAOAA9ACIAIABWAEEATABVAEUAPQAiAEkAMABCAHIARABhAEcATgBkAFYANgB2AGEASABYAEYARgBNAFcAYgBZAHcAPQA9ACIAPgA8AC8ASwBJAEQAPgA8AC8ASwBJAEQAUwA+ADwALwBQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEwAQQBfAFUAUgBMAD4AaAB0AHQAcABzADoALwAvAHQAZQBzAHQALgBwAGwAYQB5AHIAZQBhAGQAeQAuAG0AaQBjAHIAbwBzAG8AZgB0AC4AYwBvAG0ALwBzAGUAcgB2AGkAYwBlAC8AcgBpAGcAaAB0AHMAbQBhAG4AYQBnAGUAcgAuAGEAcwBtAHgAPwBjAGYAZwA9ACgAawBpAGQAOgBUAEIAZwB2ADYASwBvADYAdABGAGUAcwA2AEcAQgByAFgAagAvAHIAcgBRAD0APQAsAGMAbwBuAHQAZQBuAHQAawBlAHkAOgB3AHYAcgAyAGIAaQBoAFMAegBFAHgASwBkAFIAOABLAEsAcABRAGYAMgB3AD0APQApACwAKABrAGkAZAA6AHgAcwA5ADcAQwBLAFgAMwBGAGwAZQA0AFEARwBxAG0ANgA2AE0AMgBuAGcAPQA9ACwAYwBvAG4AdABlAG4AdABrAGUAeQA6AGcAbwBIAE8AagBiAGsASQBOAHAAZgBaAGQAdwAyAEgAMgA1AFkAbwBOAFEAPQA9ACkALAAoAGsAaQBkADoASQAwAEIAcgBEAGEARwBOAGQAVgA2AHYAYQBIAFgARgBGAE0AVwBiAFkAdwA9AD0ALABjAG8AbgB0AGUAbgB0AGsAZQB5ADoAVwBDADEAcgBjAFcARQBiADQARQB5AEkANABpAHEAcQBFAEUAUQBlAEwAQQA9AD0AKQA8AC8ATABBAF8AVQBSAEwAPgA8AC8ARABBAFQAQQA+ADwALwBXAFIATQBIAEUAQQBEAEUAUgA+AA==").unwrap()[0].clone();
+    assert_eq!(v1.key_ids.len(), 3);
+    let v0 = v1.to_v0().unwrap();
+    assert_eq!(v0.version, 0);
+    assert!(v0.key_ids.is_empty());
+    assert_eq!(v0.to_v1().key_ids, v1.key_ids);
+}
@@ -2,8 +2,8 @@
 
 use base64::prelude::{Engine as _, BASE64_STANDARD};
 use test_log::test;
-use pssh_box::{from_base64, from_hex, pprint};
-use pssh_box::{PsshData, DRMKeyId};
+use pssh_box::{from_base64, from_bytes, from_bytes_with_options, from_hex, parse_lenient, iter_box_refs, pprint};
+use pssh_box::{PsshBox, PsshData, PsshError, Severity, DRMKeyId, DRMSystemId, KeyIdLocation, ParseOptions, ToBytes};
 use pssh_box::{
     WIDEVINE_SYSTEM_ID,
     PLAYREADY_SYSTEM_ID,
@@ -225,7 +225,7 @@ fn test_parsing_playready_v0() {
     // println!("PLAYREADY> {pssh:?}");
     assert_eq!(pssh.flags, 0);
     if let PsshData::PlayReady(ref pd) = pssh.pssh_data {
-        let wrmh = &pd.record[0].record_value;
+        let wrmh = pd.record[0].wrm_header().unwrap();
         assert_eq!(wrmh.data.checksum, Some(BASE64_STANDARD.decode("/8I4XaPt2J8=").unwrap()));
     }
     assert!(boxes.contains(&boxes[0]));
@@ -237,7 +237,7 @@ fn test_parsing_playready_v0() {
     // println!("PLAYREADY> {pssh:?}");
     assert_eq!(pssh.system_id, PLAYREADY_SYSTEM_ID);
     if let PsshData::PlayReady(ref pd) = pssh.pssh_data {
-        let wrmh = &pd.record[0].record_value;
+        let wrmh = pd.record[0].wrm_header().unwrap();
         assert_eq!(wrmh.data.checksum, Some(BASE64_STANDARD.decode("reK/zM2j8pw=").unwrap()));
     }
     assert!(boxes.contains(&boxes[0]));
@@ -250,7 +250,7 @@ fn test_parsing_playready_v0() {
     pprint(pssh);
     assert_eq!(pssh.system_id, PLAYREADY_SYSTEM_ID);
     if let PsshData::PlayReady(ref pd) = pssh.pssh_data {
-        let wrmh = &pd.record[0].record_value;
+        let wrmh = pd.record[0].wrm_header().unwrap();
         assert_eq!(wrmh.data.checksum, Some(BASE64_STANDARD.decode("UGNVBSug38s=").unwrap()));
         assert_eq!(wrmh.data.kids[0].content, BASE64_STANDARD.decode("MlSJV3aYRSNHVmVHKTgjQQ==").unwrap());
     }
@@ -263,7 +263,7 @@ fn test_parsing_playready_v0() {
     // println!("PLAYREADY> {pssh:?}");
     assert_eq!(pssh.system_id, PLAYREADY_SYSTEM_ID);
     if let PsshData::PlayReady(ref pd) = pssh.pssh_data {
-        let wrmh = &pd.record[0].record_value;
+        let wrmh = pd.record[0].wrm_header().unwrap();
         assert_eq!(wrmh.data.checksum, Some(BASE64_STANDARD.decode("+FoqZH6Ky4U=").unwrap()));
         assert_eq!(wrmh.data.kids[0].content, BASE64_STANDARD.decode("rpqUeFVEresmG7b8c0N1Sg==").unwrap());
     }
@@ -276,7 +276,7 @@ fn test_parsing_playready_v0() {
     // println!("PLAYREADY> {pssh:?}");
     assert_eq!(pssh.system_id, PLAYREADY_SYSTEM_ID);
     if let PsshData::PlayReady(ref pd) = pssh.pssh_data {
-        let wrmh = &pd.record[0].record_value;
+        let wrmh = pd.record[0].wrm_header().unwrap();
         assert_eq!(wrmh.data.checksum, Some(BASE64_STANDARD.decode("do8QdfcRC4Q=").unwrap()));
         assert_eq!(wrmh.data.kids[0].content, BASE64_STANDARD.decode("9f0iCfpqoEGIYV0byEysYA==").unwrap());
     }
@@ -288,7 +288,7 @@ fn test_parsing_playready_v0() {
     let pssh = &boxes[0];
     assert_eq!(pssh.system_id, PLAYREADY_SYSTEM_ID);
     if let PsshData::PlayReady(ref pd) = pssh.pssh_data {
-        let wrmh = &pd.record[0].record_value;
+        let wrmh = pd.record[0].wrm_header().unwrap();
         assert_eq!(wrmh.data.checksum, Some(BASE64_STANDARD.decode("7MvnnnUtai8=").unwrap()));
         assert!(wrmh.data.la_url.as_ref().is_some_and(|u| u.contains("anycast.nagra.com")));
         assert!(wrmh.data.custom_attributes.as_ref().is_some_and(|ca| ca.contains("nv:ContentId")));
@@ -301,7 +301,7 @@ fn test_parsing_playready_v0() {
     let pssh = &boxes[0];
     assert_eq!(pssh.system_id, PLAYREADY_SYSTEM_ID);
     if let PsshData::PlayReady(ref pd) = pssh.pssh_data {
-        let wrmh = &pd.record[0].record_value;
+        let wrmh = pd.record[0].wrm_header().unwrap();
         assert_eq!(wrmh.data.checksum, Some(BASE64_STANDARD.decode("YggPsalSqJw=").unwrap()));
         assert!(wrmh.data.la_url.as_ref().is_some_and(|u| u.contains("drm.canal-plus.com")));
         assert!(wrmh.data.lui_url.as_ref().is_some_and(|u| u.contains("drm.canal-plus.com")));
@@ -316,7 +316,7 @@ fn test_parsing_playready_v0() {
     println!("PLAYREADY###> {pssh:?}");
     assert_eq!(pssh.system_id, PLAYREADY_SYSTEM_ID);
     if let PsshData::PlayReady(ref pd) = pssh.pssh_data {
-        let wrmh = &pd.record[0].record_value;
+        let wrmh = pd.record[0].wrm_header().unwrap();
         assert_eq!(wrmh.data.checksum, Some(BASE64_STANDARD.decode("hTVhX9H7gK0=").unwrap()));
         assert!(wrmh.data.la_url.as_ref().is_some_and(|u| u.contains("pr-keyos.licensekeyserver.com")));
         assert!(wrmh.data.custom_attributes.as_ref().is_some_and(|ca| ca.contains("<CID>PzW3zYLq1Embif4IcIKnNA==</CID>")));
@@ -331,7 +331,7 @@ fn test_parsing_playready_v0() {
     assert_eq!(pssh.system_id, PLAYREADY_SYSTEM_ID);
     assert!(boxes.contains(&boxes[0]));
     if let PsshData::PlayReady(ref pd) = pssh.pssh_data {
-        let wrmh = &pd.record[0].record_value;
+        let wrmh = pd.record[0].wrm_header().unwrap();
         assert!(wrmh.data.la_url.as_ref().is_some_and(|u| u.contains("dev.expressplay.com")));
     }
 
@@ -349,17 +349,35 @@ fn test_parsing_playready_v1() {
     assert_eq!(pssh.key_ids[0], DRMKeyId::try_from("840d5cc9fa4523a83164451c615b206a").unwrap());
     println!("PLAYREADY-v1> {pssh:?}");
     if let PsshData::PlayReady(ref pd) = pssh.pssh_data {
-        let wrmh = &pd.record[0].record_value;
+        let wrmh = pd.record[0].wrm_header().unwrap();
         assert_eq!(wrmh.data.checksum, Some(BASE64_STANDARD.decode("7zDsYfDVHUY=").unwrap()));
         assert!(wrmh.data.lui_url.as_ref().is_some_and(|s| s.contains("playready-license.vudrm.tech")));
     }
     assert!(boxes.contains(&boxes[0]));
 
-    // FIXME this one has version 4.2.0.0 and a kid inside <KIDS><KID> ...</KID></KIDS>
+    // A version 4.2.0.0 header, with the KIDs inside <PROTECTINFO><KIDS><KID VALUE=...></KID></KIDS>.
     let boxes = from_base64("AAAFyHBzc2gBAAAAmgTweZhAQoarkuZb4IhflQAAAAPoLxhMOqpXtKzoYGteP+utCHvPxvelVxa4QGqm66M2ng1rQCONoV51r2h1xRTFm2MAAAV0dAUAAAEAAQBqBTwAVwBSAE0ASABFAEEARABFAFIAIAB4AG0AbABuAHMAPQAiAGgAdAB0AHAAOgAvAC8AcwBjAGgAZQBtAGEAcwAuAG0AaQBjAHIAbwBzAG8AZgB0AC4AYwBvAG0ALwBEAFIATQAvADIAMAAwADcALwAwADMALwBQAGwAYQB5AFIAZQBhAGQAeQBIAGUAYQBkAGUAcgAiACAAdgBlAHIAcwBpAG8AbgA9ACIANAAuADIALgAwAC4AMAAiAD4APABEAEEAVABBAD4APABQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEsASQBEAFMAPgA8AEsASQBEACAAQQBMAEcASQBEAD0AIgBBAEUAUwBDAFQAUgAiACAAQwBIAEUAQwBLAFMAVQBNAD0AIgArAE4AVgA5AC8AOABqAGIAZgByAHcAPQAiACAAVgBBAEwAVQBFAD0AIgBUAEIAZwB2ADYASwBvADYAdABGAGUAcwA2AEcAQgByAFgAagAvAHIAcgBRAD0APQAiAD4APAAvAEsASQBEAD4APABLAEkARAAgAEEATABHAEkARAA9ACIAQQBFAFMAQwBUAFIAIgAgAEMASABFAEMASwBTAFUATQA9ACIAWgAxADAAaQBPAFkAWQB6AEgAMwBrAD0AIgAgAFYAQQBMAFUARQA9ACIAeABzADkANwBDAEsAWAAzAEYAbABlADQAUQBHAHEAbQA2ADYATQAyAG4AZwA9AD0AIgA+ADwALwBLAEkARAA+ADwASwBJAEQAIABBAEwARwBJAEQAPQAiAEEARQBTAEMAVABSACIAIABDAEgARQBDAEsAUwBVAE0APQAiAE8ARQB1AE0AeQBEAGUAUQAxAHMAOAA9ACIAIABWAEEATABVAEUAPQAiAEkAMABCAHIARABhAEcATgBkAFYANgB2AGEASABYAEYARgBNAFcAYgBZAHcAPQA9ACIAPgA8AC8ASwBJAEQAPgA8AC8ASwBJAEQAUwA+ADwALwBQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEwAQQBfAFUAUgBMAD4AaAB0AHQAcABzADoALwAvAHQAZQBzAHQALgBwAGwAYQB5AHIAZQBhAGQAeQAuAG0AaQBjAHIAbwBzAG8AZgB0AC4AYwBvAG0ALwBzAGUAcgB2AGkAYwBlAC8AcgBpAGcAaAB0AHMAbQBhAG4AYQBnAGUAcgAuAGEAcwBtAHgAPwBjAGYAZwA9ACgAawBpAGQAOgBUAEIAZwB2ADYASwBvADYAdABGAGUAcwA2AEcAQgByAFgAagAvAHIAcgBRAD0APQAsAGMAbwBuAHQAZQBuAHQAawBlAHkAOgB3AHYAcgAyAGIAaQBoAFMAegBFAHgASwBkAFIAOABLAEsAcABRAGYAMgB3AD0APQApACwAKABrAGkAZAA6AHgAcwA5ADcAQwBLAFgAMwBGAGwAZQA0AFEARwBxAG0ANgA2AE0AMgBuAGcAPQA9ACwAYwBvAG4AdABlAG4AdABrAGUAeQA6AGcAbwBIAE8AagBiAGsASQBOAHAAZgBaAGQAdwAyAEgAMgA1AFkAbwBOAFEAPQA9ACkALAAoAGsAaQBkADoASQAwAEIAcgBEAGEARwBOAGQAVgA2AHYAYQBIAFgARgBGAE0AVwBiAFkAdwA9AD0ALABjAG8AbgB0AGUAbgB0AGsAZQB5ADoAVwBDADEAcgBjAFcARQBiADQARQB5AEkANABpAHEAcQBFAEUAUQBlAEwAQQA9AD0AKQA8AC8ATABBAF8AVQBSAEwAPgA8AC8ARABBAFQAQQA+ADwALwBXAFIATQBIAEUAQQBEAEUAUgA+AA==").unwrap();
     assert_eq!(boxes.len(), 1);
     let pssh = &boxes[0];
     assert_eq!(pssh.system_id, PLAYREADY_SYSTEM_ID);
+    let kids = [DRMKeyId::try_from("e82f184c3aaa57b4ace8606b5e3febad").unwrap(),
+                DRMKeyId::try_from("087bcfc6f7a55716b8406aa6eba3369e").unwrap(),
+                DRMKeyId::try_from("0d6b40238da15e75af6875c514c59b63").unwrap()];
+    assert_eq!(pssh.key_ids, kids);
+    let tagged = pssh.all_key_ids();
+    assert_eq!(tagged.iter().map(|t| t.key_id).collect::<Vec<_>>(), kids);
+    assert!(tagged.iter().all(|t| t.sources.iter().any(|s| s.location == KeyIdLocation::PlayReadyProtectInfo)));
+    if let PsshData::PlayReady(ref pd) = pssh.pssh_data {
+        let wrmh = pd.record[0].wrm_header().unwrap();
+        assert_eq!(wrmh.version, "4.2.0.0");
+        assert!(wrmh.data.kids.is_empty());
+        let pi = wrmh.data.protect_info.as_ref().unwrap();
+        let kid = pi.kids().next().unwrap();
+        assert_eq!(kid.value.as_deref(), Some("TBgv6Ko6tFes6GBrXj/rrQ=="));
+        assert_eq!(kid.algid.as_deref(), Some("AESCTR"));
+        assert_eq!(kid.checksum, Some(BASE64_STANDARD.decode("+NV9/8jbfrw=").unwrap()));
+        assert_eq!(pd.key_ids(), kids);
+    }
     assert!(boxes.contains(&boxes[0]));
 }
 
@@ -633,7 +651,7 @@ fn test_parsing_multisystem() {
     let pr_pssh = &boxes[1];
     assert_eq!(pr_pssh.system_id, PLAYREADY_SYSTEM_ID);
     if let PsshData::PlayReady(ref pd) = pr_pssh.pssh_data {
-        let wrmh = &pd.record[0].record_value;
+        let wrmh = pd.record[0].wrm_header().unwrap();
         assert_eq!(wrmh.data.checksum, Some(BASE64_STANDARD.decode("5kJ+76Cqats=").unwrap()));
         assert!(wrmh.data.la_url.as_ref().is_some_and(|s| s.contains("youtube.com")));
     }
@@ -653,7 +671,7 @@ fn test_parsing_multisystem() {
     println!("PLAYREADY-v1(2)> {pssh:?}");
     assert_eq!(pssh.flags, 0);
     if let PsshData::PlayReady(ref pd) = pssh.pssh_data {
-        let wrmh = &pd.record[0].record_value;
+        let wrmh = pd.record[0].wrm_header().unwrap();
         assert_eq!(wrmh.data.checksum, Some(BASE64_STANDARD.decode("5kJ+76Cqats=").unwrap()));
         assert!(wrmh.data.la_url.as_ref().is_some_and(|s| s.contains("youtube.com")));
     }
@@ -662,7 +680,10 @@ fn test_parsing_multisystem() {
 
 #[test]
 fn test_parsing_concatenated_erroneous() {
-    assert!(from_base64("AAAAQHBzc2gAAAAA7e+LqXnWSs6jyCfc1R0h7QAAACAiGFlPVVRVQkU6ZDVlMjNlZDMzMWZjNjFiN0jj3JWbBgAAAvRwc3NoAAAAAJoE8HmYQEKGq5LmW+CIX5UAAALU1AIAAAEAAQDKAjwAVwBSAE0ASABFAEEARABFAFIAIAB4AG0AbABuAHMAPQAiAGgAdAB0AHAAOgAvAC8AcwBjAGgAZQBtAGEAcwAuAG0AaQBjAHIAbwBzAG8AZgB0AC4AYwBvAG0ALwBEAFIATQAvADIAMAAwADcALwAwADMALwBQAGwAYQB5AFIAZQBhAGQAeQBIAGUAYQBkAGUAcgAiACAAdgBlAHIAcwBpAG8AbgA9ACIANAAuADAALgAwAC4AMAAiAD4APABEAEEAVABBAD4APABQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEsARQBZAEwARQBOAD4AMQA2ADwALwBLAEUAWQBMAEUATgA+ADwAQQBMAEcASQBEAD4AQQBFAFMAQwBUAFIAPAAvAEEATABHAEkARAA+ADwALwBQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEsASQBEAD4AaQBRAGMAeAA3AHEAMQBoAEIARgBTAG4AUQBjAHQAOAB2AEwAKwBPAFYAQQA9AD0APAAvAEsASQBEAD4APABDAEgARQBDAEsAUwBVAE0APgBoAHUAZgBGAFMAdQBSAFoAQgBqAHMAPQA8AC8AQwBIAEUAQwBLAFMAVQBNAD4APABMAEEAXwBVAFIATAA+AGgAdAB0AHAAcwA6AC8ALwB3AHcAdwAuAHkAbwB1AHQAdQBiAGUALgBjAG8AbQAvAGEAcABpAC8AZAByAG0ALwBwAGwAYQB5AHIAZQBhAGQAeQA/AHMAbwB1AHIAYwBlAD0AWQBPAFUAVABVAEIARQAmAGEAbQBwADsAdgBpAGQAZQBvAF8AaQBkAD0AZAA1AGUAMgAzAGUAZAAzADMAMQBmAGMANgAxAGIANwA8AC8ATABBA").is_err());
+    // The PlayReady box is truncated; the Widevine box which precedes it is returned.
+    let boxes = from_base64("AAAAQHBzc2gAAAAA7e+LqXnWSs6jyCfc1R0h7QAAACAiGFlPVVRVQkU6ZDVlMjNlZDMzMWZjNjFiN0jj3JWbBgAAAvRwc3NoAAAAAJoE8HmYQEKGq5LmW+CIX5UAAALU1AIAAAEAAQDKAjwAVwBSAE0ASABFAEEARABFAFIAIAB4AG0AbABuAHMAPQAiAGgAdAB0AHAAOgAvAC8AcwBjAGgAZQBtAGEAcwAuAG0AaQBjAHIAbwBzAG8AZgB0AC4AYwBvAG0ALwBEAFIATQAvADIAMAAwADcALwAwADMALwBQAGwAYQB5AFIAZQBhAGQAeQBIAGUAYQBkAGUAcgAiACAAdgBlAHIAcwBpAG8AbgA9ACIANAAuADAALgAwAC4AMAAiAD4APABEAEEAVABBAD4APABQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEsARQBZAEwARQBOAD4AMQA2ADwALwBLAEUAWQBMAEUATgA+ADwAQQBMAEcASQBEAD4AQQBFAFMAQwBUAFIAPAAvAEEATABHAEkARAA+ADwALwBQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEsASQBEAD4AaQBRAGMAeAA3AHEAMQBoAEIARgBTAG4AUQBjAHQAOAB2AEwAKwBPAFYAQQA9AD0APAAvAEsASQBEAD4APABDAEgARQBDAEsAUwBVAE0APgBoAHUAZgBGAFMAdQBSAFoAQgBqAHMAPQA8AC8AQwBIAEUAQwBLAFMAVQBNAD4APABMAEEAXwBVAFIATAA+AGgAdAB0AHAAcwA6AC8ALwB3AHcAdwAuAHkAbwB1AHQAdQBiAGUALgBjAG8AbQAvAGEAcABpAC8AZAByAG0ALwBwAGwAYQB5AHIAZQBhAGQAeQA/AHMAbwB1AHIAYwBlAD0AWQBPAFUAVABVAEIARQAmAGEAbQBwADsAdgBpAGQAZQBvAF8AaQBkAD0AZAA1AGUAMgAzAGUAZAAzADMAMQBmAGMANgAxAGIANwA8AC8ATABBA").unwrap();
+    assert_eq!(boxes.len(), 1);
+    assert_eq!(boxes[0].system_id, WIDEVINE_SYSTEM_ID);
 }
 
 
@@ -672,6 +693,195 @@ fn test_parsing_erroneous() {
 }
 
 
+#[test]
+fn test_parsing_error_kinds() {
+    let full = hex::decode("000000207073736800000000edef8ba979d64acea3c827dcd51d21ed00000000").unwrap();
+    // Input that stops in the middle of the system_id
+    let err = from_bytes(&full[..20]).unwrap_err();
+    assert!(matches!(err, PsshError::Truncated { offset: 12, needed: 16 }));
+    assert_eq!(err.offset(), Some(12));
+
+    let mut bad_type = full.clone();
+    bad_type[4..8].copy_from_slice(b"moov");
+    let err = from_bytes(&bad_type).unwrap_err();
+    assert!(matches!(err, PsshError::BadBoxType { offset: 4, found } if found == *b"moov"));
+
+    let mut bad_version = full.clone();
+    bad_version[8] = 2;
+    let err = from_bytes(&bad_version).unwrap_err();
+    assert!(matches!(err, PsshError::UnsupportedVersion { offset: 8, version: 2 }));
+
+    // PSSH data declared as 10 octets long, but absent
+    let mut short_data = full.clone();
+    short_data[31] = 10;
+    let err = from_bytes(&short_data).unwrap_err();
+    assert!(matches!(err, PsshError::Truncated { offset: 32, needed: 10 }));
+
+    // Nagra PSSH data which is not valid base64
+    let err = from_hex("000000247073736800000000adb41c242dbf4a6d958b4457c0d27b9500000004ffffffff").unwrap_err();
+    assert!(matches!(err, PsshError::PayloadDecode { offset: 32, system, .. } if system == pssh_box::NAGRA_SYSTEM_ID));
+
+    let err = from_hex("0000002g").unwrap_err();
+    assert!(matches!(err, PsshError::InvalidEncoding { offset: 7, .. }));
+
+    let err = DRMSystemId::try_from(&[0u8; 8][..]).unwrap_err();
+    assert!(matches!(err, PsshError::InvalidLength { expected: 16, found: 8, .. }));
+    let err = DRMKeyId::try_from("72c3ed2c7a5f4aad902fcbef1efe89zz").unwrap_err();
+    assert!(matches!(err, PsshError::InvalidEncoding { offset: 30, .. }));
+}
+
+
+#[test]
+fn test_parse_lenient() {
+    let wv = BASE64_STANDARD.decode("AAAAR3Bzc2gAAAAA7e+LqXnWSs6jyCfc1R0h7QAAACcIARIBMBoNd2lkZXZpbmVfdGVzdCIKMjAxNV90ZWFycyoFQVVESU8=")
+        .unwrap();
+    // A Nagra PSSH box whose PSSH data is not valid base64
+    let bad_nagra = hex::decode("000000247073736800000000adb41c242dbf4a6d958b4457c0d27b9500000004ffffffff").unwrap();
+    let mut init_data = wv.clone();
+    init_data.extend_from_slice(&bad_nagra);
+    init_data.extend_from_slice(&wv);
+    let (boxes, diagnostics) = parse_lenient(&init_data);
+    assert_eq!(boxes.len(), 2);
+    assert!(boxes.iter().all(|bx| bx.system_id == WIDEVINE_SYSTEM_ID));
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity, Severity::Error);
+    assert!(matches!(diagnostics[0].error, PsshError::PayloadDecode { system, .. } if system == NAGRA_SYSTEM_ID));
+    assert_eq!(diagnostics[0].offset(), Some(71 + 32));
+
+    // Junk between the boxes, a box with an unsupported version and trailing junk
+    let mut init_data = wv.clone();
+    init_data.extend_from_slice(b"junk");
+    init_data.extend_from_slice(&wv);
+    let mut bad_version = wv.clone();
+    bad_version[8] = 2;
+    init_data.extend_from_slice(&bad_version);
+    init_data.extend_from_slice(&wv);
+    init_data.extend_from_slice(b"trailing garbage");
+    let (boxes, diagnostics) = parse_lenient(&init_data);
+    assert_eq!(boxes.len(), 3);
+    let kinds: Vec<_> = diagnostics.iter().map(|d| (d.severity, d.offset())).collect();
+    assert_eq!(kinds, vec![
+        (Severity::Error, Some(75)),
+        (Severity::Warning, Some(71)),
+        (Severity::Error, Some(146 + 8)),
+        (Severity::Error, Some(288 + 4)),
+        (Severity::Warning, Some(288)),
+    ]);
+    assert!(matches!(diagnostics[1].error, PsshError::UnexpectedData { length: 4, .. }));
+    assert!(matches!(diagnostics[4].error, PsshError::UnexpectedData { length: 16, .. }));
+
+    // Truncated final box
+    let (boxes, diagnostics) = parse_lenient(&wv[..50]);
+    assert!(boxes.is_empty());
+    assert!(matches!(diagnostics[0].error, PsshError::Truncated { offset: 32, needed: 39 }));
+    assert!(diagnostics.iter().all(|d| d.to_string().contains("offset")));
+
+    let (boxes, diagnostics) = parse_lenient(&[]);
+    assert!(boxes.is_empty());
+    assert!(diagnostics.is_empty());
+
+    // The boxes that were parsed before a truncated box in base64 input are returned
+    let mut b64 = String::from("AAAAR3Bzc2gAAAAA7e+LqXnWSs6jyCfc1R0h7QAAACcIARIBMBoNd2lkZXZpbmVfdGVzdCIKMjAxNV90ZWFycyoFQVVESU8=");
+    b64.push_str("AAAAR3Bzc2gAAAAA7e+LqXnWSs6jyCfc1R0h7QAAACcIARIBMB");
+    let boxes = from_base64(&b64).unwrap();
+    assert_eq!(boxes.len(), 1);
+}
+
+
+#[test]
+fn test_parsing_box_header() {
+    let strict = ParseOptions { strict: true, ..Default::default() };
+    // A CommonEnc v0 box with all 24 bits of flags set
+    let full = hex::decode("0000002870737368007a5b3c1077ef1ecd73ce8a1e65d6b1d4fc6e5c00000008deadbeefcafebabe").unwrap();
+    let boxes = from_bytes_with_options(&full, &strict).unwrap();
+    assert_eq!(boxes[0].flags, 0x7a5b3c);
+    assert_eq!(boxes[0].to_bytes(), full);
+
+    // The same box with a 64-bit largesize
+    let mut large = hex::decode("0000000170737368").unwrap();
+    large.extend_from_slice(&48u64.to_be_bytes());
+    large.extend_from_slice(&full[8..]);
+    let boxes = from_bytes_with_options(&large, &strict).unwrap();
+    assert_eq!(boxes.len(), 1);
+    assert_eq!(boxes[0].flags, 0x7a5b3c);
+    assert_eq!(boxes[0].to_bytes(), full);
+
+    // A box size of 0 means that the box extends to the end of the input
+    let mut to_end = full.clone();
+    to_end[3] = 0;
+    let boxes = from_bytes_with_options(&to_end, &strict).unwrap();
+    assert_eq!(boxes.len(), 1);
+    to_end.extend_from_slice(b"trailing");
+    assert!(from_bytes_with_options(&to_end, &strict).is_err());
+    assert_eq!(from_bytes(&to_end).unwrap().len(), 1);
+
+    // Unused octets inside a box are skipped, rather than being read as the start of the next box
+    let mut padded = full.clone();
+    padded[3] = 0x2c;
+    padded.extend_from_slice(b"\x01\x02\x03\x04");
+    padded.extend_from_slice(&full);
+    let boxes = from_bytes(&padded).unwrap();
+    assert_eq!(boxes.len(), 2);
+    let err = from_bytes_with_options(&padded, &strict).unwrap_err();
+    assert!(matches!(err, PsshError::BoxSizeMismatch { offset: 0, declared: 44, consumed: 40 }));
+
+    // Box sizes that are too small or extend beyond the input
+    let mut small = full.clone();
+    small[3] = 0x20;
+    assert!(from_bytes(&small).is_ok());
+    let err = from_bytes_with_options(&small, &strict).unwrap_err();
+    assert!(matches!(err, PsshError::BoxSizeMismatch { offset: 0, declared: 32, consumed: 40 }));
+    small[3] = 4;
+    let err = from_bytes_with_options(&small, &strict).unwrap_err();
+    assert!(matches!(err, PsshError::BadBoxSize { offset: 0, size: 4 }));
+    let mut large = full.clone();
+    large[3] = 0x40;
+    let err = from_bytes_with_options(&large, &strict).unwrap_err();
+    assert!(matches!(err, PsshError::Truncated { offset: 0, needed: 64 }));
+
+    // Flags wider than 24 bits are not allowed to overwrite the version
+    let mut bx = boxes[0].clone();
+    bx.flags = 0xFF00_0001;
+    let out = bx.to_bytes();
+    assert_eq!(&out[8..12], &[0, 0, 0, 1]);
+}
+
+
+#[test]
+fn test_box_refs() {
+    let wv = BASE64_STANDARD.decode("AAAAjHBzc2gBAAAA7e+LqXnWSs6jyCfc1R0h7QAAAAGEDVzJ+kUjqDFkRRxhWyBqAAAAWCJQbVZVVnEzUE4tTWZKamxyU2MtRG1DTDViMWktMzQ1MDQ5NjZfMzQ1MDQ5NjdfMzQ1MDQ5NjhfMzQ1MDQ5NzFfMzQ1MDQ5NzJfMzQ1MDQ5NzNI49yVmwY=")
+        .unwrap();
+    let bad_nagra = hex::decode("000000247073736800000000adb41c242dbf4a6d958b4457c0d27b9500000004ffffffff").unwrap();
+    let mut init_data = wv.clone();
+    init_data.extend_from_slice(&bad_nagra);
+    let refs: Vec<_> = iter_box_refs(&init_data).collect::<Result<_, _>>().unwrap();
+    assert_eq!(refs.len(), 2);
+    let wvref = refs[0];
+    assert_eq!(wvref.version, 1);
+    assert_eq!(wvref.system_id, WIDEVINE_SYSTEM_ID);
+    assert_eq!(wvref.key_ids, &[DRMKeyId::try_from("840d5cc9fa4523a83164451c615b206a").unwrap()]);
+    assert_eq!(wvref.as_bytes(), &wv[..]);
+    assert_eq!(wvref.pssh_data.len(), 0x58);
+    // The payload points into the input rather than being copied.
+    assert!(std::ptr::eq(wvref.pssh_data.as_ptr(), init_data[52..].as_ptr()));
+    let owned = from_bytes(&wv).unwrap();
+    assert_eq!(wvref.to_pssh_box().unwrap(), owned[0]);
+    assert_eq!(PsshBox::try_from(wvref).unwrap(), owned[0]);
+
+    // The header of the Nagra box can be examined, but its PSSH data can't be decoded.
+    let nref = refs[1];
+    assert_eq!(nref.system_id, NAGRA_SYSTEM_ID);
+    assert_eq!(nref.pssh_data, &[0xff; 4]);
+    let err = nref.decode_pssh_data().unwrap_err();
+    assert!(matches!(err, PsshError::PayloadDecode { offset, .. } if offset == 0x8c + 32));
+
+    // Iteration stops after an error.
+    let mut iter = iter_box_refs(&init_data[..50]);
+    assert!(matches!(iter.next(), Some(Err(PsshError::Truncated { offset: 48, needed: 4 }))));
+    assert!(iter.next().is_none());
+}
+
+
 #[test]
 fn test_partialeq() {
     let boxes = from_base64("AAAAR3Bzc2gAAAAA7e+LqXnWSs6jyCfc1R0h7QAAACcIARIBMBoNd2lkZXZpbmVfdGVzdCIKMjAxNV90ZWFycyoFQVVESU8=")
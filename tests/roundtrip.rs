@@ -4,7 +4,8 @@
 
 use test_log::test;
 use pretty_assertions::assert_eq;
-use pssh_box::{from_base64, ToBytes, from_bytes, PsshData};
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use pssh_box::{from_base64, from_hex, ToBytes, from_bytes, PsshBox, PsshData, PsshBoxVec, PsshError};
 use pssh_box::{WIDEVINE_SYSTEM_ID, PLAYREADY_SYSTEM_ID};
 
 
@@ -53,11 +54,199 @@ fn test_roundtrip_playready() {
     assert_eq!(reflected.system_id, PLAYREADY_SYSTEM_ID);
     if let PsshData::PlayReady(ref pd1) = pssh.pssh_data {
         if let PsshData::PlayReady(ref pd2) = reflected.pssh_data {
-            let wrmh1 = &pd1.record[0].record_value;
-            let wrmh2 = &pd2.record[0].record_value;
+            let wrmh1 = pd1.record[0].wrm_header().unwrap();
+            let wrmh2 = pd2.record[0].wrm_header().unwrap();
             assert_eq!(wrmh1.data.checksum, wrmh2.data.checksum);
             assert_eq!(wrmh1.data.kids[0].content, wrmh2.data.kids[0].content);
         }
     }
 }
 
+
+// A PlayReady Object containing a Rights Management record, an empty Embedded License Store and a
+// record of an unknown type.
+#[test]
+fn test_roundtrip_playready_els() {
+    use pssh_box::playready::{parse_pssh_data, PlayReadyRecordType, PlayReadyRecordValue};
+
+    let xml = r#"<WRMHEADER xmlns="http://schemas.microsoft.com/DRM/2007/03/PlayReadyHeader" version="4.0.0.0"><DATA><PROTECTINFO><KEYLEN>16</KEYLEN><ALGID>AESCTR</ALGID></PROTECTINFO><KID>MlSJV3aYRSNHVmVHKTgjQQ==</KID></DATA></WRMHEADER>"#;
+    let rm: Vec<u8> = xml.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    let els = vec![0u8; 10];
+    let unknown = vec![1u8, 2, 3];
+    let mut records = Vec::new();
+    for (rtype, value) in [(1u16, &rm), (3, &els), (7, &unknown)] {
+        records.extend_from_slice(&rtype.to_le_bytes());
+        records.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        records.extend_from_slice(value);
+    }
+    let mut octets = Vec::new();
+    octets.extend_from_slice(&(6 + records.len() as u32).to_le_bytes());
+    octets.extend_from_slice(&3u16.to_le_bytes());
+    octets.extend_from_slice(&records);
+    let pr = parse_pssh_data(&octets).unwrap();
+    assert_eq!(pr.record.len(), 3);
+    assert!(pr.record[0].wrm_header().is_some());
+    assert_eq!(pr.record[1].record_type, PlayReadyRecordType::EmbeddedLicenseStore);
+    assert_eq!(pr.record[1].record_value, PlayReadyRecordValue::Raw(els));
+    assert_eq!(pr.record[2].record_type, PlayReadyRecordType::Unknown(7));
+    assert_eq!(pr.record[2].record_value, PlayReadyRecordValue::Raw(unknown));
+    assert_eq!(pr.key_ids().len(), 1);
+    let reflected = parse_pssh_data(&pr.to_bytes()).unwrap();
+    assert_eq!(reflected, pr);
+    assert!(pr.to_bytes().ends_with(&[3, 0, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 7, 0, 3, 0, 1, 2, 3]));
+
+    // A truncated record is rejected.
+    let len = octets.len();
+    assert!(parse_pssh_data(&octets[..len - 1]).is_err());
+}
+
+fn playready_object(rm: &[u8]) -> Vec<u8> {
+    let mut octets = Vec::new();
+    octets.extend_from_slice(&(10 + rm.len() as u32).to_le_bytes());
+    octets.extend_from_slice(&1u16.to_le_bytes());
+    octets.extend_from_slice(&1u16.to_le_bytes());
+    octets.extend_from_slice(&(rm.len() as u16).to_le_bytes());
+    octets.extend_from_slice(rm);
+    octets
+}
+
+// CUSTOMATTRIBUTES and unknown DATA elements are emitted as XML, and a byte order mark and NUL
+// padding are ignored.
+#[test]
+fn test_roundtrip_playready_raw_xml() {
+    use pssh_box::playready::{parse_pssh_data, PlayReadyPsshData};
+
+    let xml = "\u{FEFF}<WRMHEADER xmlns=\"http://schemas.microsoft.com/DRM/2007/03/PlayReadyHeader\" version=\"4.0.0.0\"><DATA><PROTECTINFO><KEYLEN>16</KEYLEN><ALGID>AESCTR</ALGID></PROTECTINFO><KID>MlSJV3aYRSNHVmVHKTgjQQ==</KID><LA_URL>https://example.com/rightsmanager.asmx</LA_URL><CUSTOMATTRIBUTES><nv:CID xmlns:nv=\"urn:example\">PzW3zYLq1Embif4IcIKnNA==</nv:CID><DRMTYPE>smooth</DRMTYPE></CUSTOMATTRIBUTES><IIS_DRM_VERSION>8.1.2304.31</IIS_DRM_VERSION><VENDOR name=\"x&amp;y\"/></DATA></WRMHEADER>\0\0";
+    let rm: Vec<u8> = xml.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    let pr = parse_pssh_data(&playready_object(&rm)).unwrap();
+    let wrmh = pr.record[0].wrm_header().unwrap();
+    assert_eq!(wrmh.data.la_url.as_deref(), Some("https://example.com/rightsmanager.asmx"));
+    assert_eq!(wrmh.data.custom_attributes.as_deref(),
+               Some("<nv:CID xmlns:nv=\"urn:example\">PzW3zYLq1Embif4IcIKnNA==</nv:CID><DRMTYPE>smooth</DRMTYPE>"));
+    assert_eq!(wrmh.data.unknown_elements,
+               vec!["<IIS_DRM_VERSION>8.1.2304.31</IIS_DRM_VERSION>", "<VENDOR name=\"x&amp;y\"/>"]);
+    let out = wrmh.to_xml().unwrap();
+    assert!(out.contains("<CUSTOMATTRIBUTES><nv:CID xmlns:nv=\"urn:example\">PzW3zYLq1Embif4IcIKnNA==</nv:CID><DRMTYPE>smooth</DRMTYPE></CUSTOMATTRIBUTES>"));
+    assert!(out.contains("<IIS_DRM_VERSION>8.1.2304.31</IIS_DRM_VERSION><VENDOR name=\"x&amp;y\"/></DATA>"));
+    let reflected = parse_pssh_data(&pr.to_bytes()).unwrap();
+    assert_eq!(reflected, pr);
+    // The unknown elements are kept when serializing to and from JSON.
+    let json = serde_json::to_string(&pr).unwrap();
+    let reflected: PlayReadyPsshData = serde_json::from_str(&json).unwrap();
+    assert_eq!(reflected, pr);
+    assert_eq!(reflected.to_bytes(), pr.to_bytes());
+
+    // A Rights Management record of odd length can't be UTF-16.
+    let mut odd = rm.clone();
+    odd.push(0);
+    assert!(parse_pssh_data(&playready_object(&odd)).is_err());
+}
+
+
+#[test]
+fn test_roundtrip_unknown_system() {
+    // A v1 ClearKey (DASH-IF system ID) box with one KID, followed by a Widevine box.
+    let clearkey = "000000347073736801000000e2719d58a985b3c9781ab030af78d30e000000011077efecc0b24d02ace33c1e52e2fb4b00000000";
+    let widevine = "000000387073736800000000edef8ba979d64acea3c827dcd51d21ed000000181210001637069fb5d1ac3c4747e01322e4c248e3dc959b06";
+    let boxes = from_hex(&format!("{clearkey}{widevine}")).unwrap();
+    assert_eq!(boxes.len(), 2);
+    let pssh = &boxes[0];
+    assert_eq!(pssh.version, 1);
+    assert_eq!(pssh.key_ids.len(), 1);
+    assert_eq!(pssh.pssh_data, PsshData::Unknown(Vec::new()));
+    assert!(pssh.to_string().contains("ClearKey DASH-IF"));
+    assert_eq!(hex::encode(pssh.to_bytes()), clearkey);
+    assert_eq!(boxes[1].system_id, WIDEVINE_SYSTEM_ID);
+
+    let mut reordered = PsshBoxVec::new();
+    reordered.add(boxes[1].clone());
+    reordered.add(boxes[0].clone());
+    assert_eq!(reordered.to_hex(), format!("{widevine}{clearkey}"));
+}
+
+
+#[test]
+fn test_roundtrip_byte_exact() {
+    let pr = "AAADrHBzc2gBAAAAmgTweZhAQoarkuZb4IhflQAAAAGEDVzJ+kUjqDFkRRxhWyBqAAADeHgDAAABAAEAbgM8AFcAUgBNAEgARQBBAEQARQBSACAAeABtAGwAbgBzAD0AIgBoAHQAdABwADoALwAvAHMAYwBoAGUAbQBhAHMALgBtAGkAYwByAG8AcwBvAGYAdAAuAGMAbwBtAC8ARABSAE0ALwAyADAAMAA3AC8AMAAzAC8AUABsAGEAeQBSAGUAYQBkAHkASABlAGEAZABlAHIAIgAgAHYAZQByAHMAaQBvAG4APQAiADQALgAwAC4AMAAuADAAIgA+ADwARABBAFQAQQA+ADwAUABSAE8AVABFAEMAVABJAE4ARgBPAD4APABLAEUAWQBMAEUATgA+ADEANgA8AC8ASwBFAFkATABFAE4APgA8AEEATABHAEkARAA+AEEARQBTAEMAVABSADwALwBBAEwARwBJAEQAPgA8AC8AUABSAE8AVABFAEMAVABJAE4ARgBPAD4APABLAEkARAA+AHkAVgB3AE4AaABFAFgANgBxAEMATQB4AFoARQBVAGMAWQBWAHMAZwBhAGcAPQA9ADwALwBLAEkARAA+ADwATABBAF8AVQBSAEwAPgBoAHQAdABwAHMAOgAvAC8AcABsAGEAeQByAGUAYQBkAHkALQBsAGkAYwBlAG4AcwBlAC4AdgB1AGQAcgBtAC4AdABlAGMAaAAvAHIAaQBnAGgAdABzAG0AYQBuAGEAZwBlAHIALgBhAHMAbQB4ADwALwBMAEEAXwBVAFIATAA+ADwATABVAEkAXwBVAFIATAA+AGgAdAB0AHAAcwA6AC8ALwBwAGwAYQB5AHIAZQBhAGQAeQAtAGwAaQBjAGUAbgBzAGUALgB2AHUAZAByAG0ALgB0AGUAYwBoAC8AcgBpAGcAaAB0AHMAbQBhAG4AYQBnAGUAcgAuAGEAcwBtAHgAPAAvAEwAVQBJAF8AVQBSAEwAPgA8AEQAUwBfAEkARAA+AGcAdwBJAEMASQA4AHkAZgBJAFUARwBmADQAUgAvADUAcQBPAFcAdQBxAGcAPQA9ADwALwBEAFMAXwBJAEQAPgA8AEMASABFAEMASwBTAFUATQA+ADcAegBEAHMAWQBmAEQAVgBIAFUAWQA9ADwALwBDAEgARQBDAEsAUwBVAE0APgA8AC8ARABBAFQAQQA+ADwALwBXAFIATQBIAEUAQQBEAEUAUgA+AA==";
+    let octets = BASE64_STANDARD.decode(pr).unwrap();
+    let boxes = from_bytes(&octets).unwrap();
+    let pssh = &boxes[0];
+    assert!(!pssh.is_modified());
+    assert_eq!(pssh.original_pssh_data(), Some(&octets[52..]));
+    assert_eq!(pssh.to_bytes(), octets);
+    assert_eq!(boxes.clone().to_base64(), pr);
+
+    // Once the PSSH data is modified, it is serialized by the DRM-specific encoder.
+    let mut modified = pssh.clone();
+    if let PsshData::PlayReady(ref mut pd) = modified.pssh_data {
+        pd.record[0].wrm_header_mut().unwrap().data.la_url = Some(String::from("https://example.com/license"));
+    }
+    assert!(modified.is_modified());
+    assert_ne!(modified, *pssh);
+    let reflected = from_bytes(&modified.to_bytes()).unwrap();
+    assert_eq!(reflected[0], modified);
+    // Reverting the modification restores the original octets.
+    modified.pssh_data = pssh.pssh_data.clone();
+    assert!(!modified.is_modified());
+    assert_eq!(modified.to_bytes(), octets);
+
+    // Widevine PSSH data with a protobuf field (number 99) that the decoder doesn't know about.
+    let mut wv = PsshBox::new_widevine();
+    if let PsshData::Widevine(ref mut pd) = wv.pssh_data {
+        pd.provider = Some(String::from("widevine_test"));
+    }
+    assert_eq!(PsshBox::new(WIDEVINE_SYSTEM_ID, 1, vec![], wv.pssh_data.clone()), wv);
+    let mut octets = wv.to_bytes();
+    octets[3] += 3;
+    octets[35] += 3;
+    octets.extend_from_slice(&[0x98, 0x06, 0x01]);
+    let boxes = from_bytes(&octets).unwrap();
+    assert_eq!(boxes[0], wv);
+    assert_eq!(boxes[0].to_bytes(), octets);
+    let mut forgetful = boxes[0].clone();
+    forgetful.forget_original();
+    assert!(forgetful.is_modified());
+    assert_eq!(forgetful.to_bytes(), wv.to_bytes());
+}
+
+
+struct FailingWriter;
+
+impl std::io::Write for FailingWriter {
+    fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+        Err(std::io::Error::other("disk full"))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_write_to() {
+    let boxes = from_base64("AAAAR3Bzc2gAAAAA7e+LqXnWSs6jyCfc1R0h7QAAACcIARIBMBoNd2lkZXZpbmVfdGVzdCIKMjAxNV90ZWFycyoFQVVESU8=")
+        .unwrap();
+    let mut out = b"moof".to_vec();
+    boxes[0].write_to(&mut out).unwrap();
+    assert_eq!(&out[..4], b"moof");
+    assert_eq!(&out[4..], &boxes[0].to_bytes()[..]);
+    assert_eq!(boxes[0].try_to_bytes().unwrap(), boxes[0].to_bytes());
+
+    let mut pr = PsshBox::new_playready();
+    let mut both = boxes.clone();
+    both.add(pr.clone());
+    let mut expected = boxes[0].to_bytes();
+    expected.extend_from_slice(&pr.to_bytes());
+    assert_eq!(both.try_to_bytes().unwrap(), expected);
+
+    let err = boxes[0].write_to(&mut FailingWriter).unwrap_err();
+    assert!(matches!(err, PsshError::Io(_)));
+
+    // A PlayReady record whose UTF-16 XML doesn't fit in its 16-bit length field
+    if let PsshData::PlayReady(ref mut pd) = pr.pssh_data {
+        pd.record[0].wrm_header_mut().unwrap().data.la_url = Some("x".repeat(40_000));
+    }
+    let err = pr.try_to_bytes().unwrap_err();
+    assert!(matches!(err, PsshError::LengthOverflow { max: 65535, .. }));
+    assert!(err.to_string().contains("PlayReady record length"));
+}
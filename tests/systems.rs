@@ -0,0 +1,69 @@
+//! Tests for the registry of DRM systems
+
+use test_log::test;
+use pssh_box::systems::{self, DRM_SYSTEMS};
+use pssh_box::{DRMSystemId, MARLIN_SYSTEM_ID, PLAYREADY_SYSTEM_ID, WIDEVINE_SYSTEM_ID, FAIRPLAY_SYSTEM_ID};
+
+
+#[test]
+fn test_system_lookups() {
+    let wv = systems::from_system_id(WIDEVINE_SYSTEM_ID).unwrap();
+    assert_eq!(wv.name, "Widevine");
+    assert_eq!(wv.scheme_id_uri(), "urn:uuid:edef8ba9-79d6-4ace-a3c8-27dcd51d21ed");
+    assert_eq!(systems::from_name("widevine"), Some(wv));
+    assert_eq!(systems::from_eme_key_system("com.widevine.alpha"), Some(wv));
+    assert_eq!(systems::from_scheme_id_uri("urn:uuid:EDEF8BA9-79D6-4ACE-A3C8-27DCD51D21ED"), Some(wv));
+    assert_eq!(systems::from_hls_keyformat(wv.hls_keyformat.unwrap()), Some(wv));
+    assert_eq!(WIDEVINE_SYSTEM_ID.drm_system(), Some(wv));
+
+    let pr = systems::from_eme_key_system("com.microsoft.playready.recommendation").unwrap();
+    assert_eq!(pr.system_id, PLAYREADY_SYSTEM_ID);
+    assert_eq!(systems::from_hls_keyformat("com.microsoft.playready"), Some(pr));
+
+    let fps = systems::from_hls_keyformat("com.apple.streamingkeydelivery").unwrap();
+    assert_eq!(fps.system_id, FAIRPLAY_SYSTEM_ID);
+    assert_eq!(systems::from_eme_key_system("com.apple.fps"), Some(fps));
+
+    assert!(systems::from_name("NoSuchDRM").is_none());
+    assert!(systems::from_eme_key_system("org.example.drm").is_none());
+    assert!(systems::from_scheme_id_uri("urn:mpeg:dash:mp4protection:2011").is_none());
+    assert!(systems::from_scheme_id_uri("urn:uuid:not-hex").is_none());
+    assert!(systems::from_hls_keyformat("").is_none());
+    let unknown = DRMSystemId::try_from("00000000000000000000000000000000").unwrap();
+    assert!(unknown.drm_system().is_none());
+    assert!(unknown.to_string().starts_with("Unknown/"));
+}
+
+// The urn:uuid scheme identifier for Marlin differs from its system ID.
+#[test]
+fn test_marlin_urn() {
+    let marlin = MARLIN_SYSTEM_ID.drm_system().unwrap();
+    assert_eq!(marlin.name, "Marlin");
+    assert_ne!(marlin.urn_uuid, marlin.system_id);
+    assert_eq!(marlin.scheme_id_uri(), "urn:uuid:5e629af5-38da-4063-8977-97ffbd9902d4");
+    assert_eq!(systems::from_scheme_id_uri("urn:uuid:5E629AF5-38DA-4063-8977-97FFBD9902D4"), Some(marlin));
+    assert!(systems::from_scheme_id_uri("urn:uuid:69f908af-4816-46ea-910c-cd5dcccb0a3a").is_none());
+    // Some PSSH boxes found in practice use the urn:uuid code as their system ID.
+    assert_eq!(systems::from_system_id(marlin.urn_uuid), Some(marlin));
+    assert!(marlin.urn_uuid.to_string().starts_with("Marlin/"));
+}
+
+#[test]
+fn test_registry_consistency() {
+    for (i, s) in DRM_SYSTEMS.iter().enumerate() {
+        assert_eq!(systems::from_system_id(s.system_id), Some(s));
+        assert_eq!(systems::from_name(s.name), Some(s));
+        assert_eq!(systems::from_scheme_id_uri(&s.scheme_id_uri()), Some(s));
+        assert!(s.system_id.to_string().starts_with(&format!("{}/DRMSystemId<", s.name)));
+        for ks in s.eme_key_systems {
+            assert_eq!(systems::from_eme_key_system(ks), Some(s));
+        }
+        if let Some(kf) = s.hls_keyformat {
+            assert_eq!(systems::from_hls_keyformat(kf), Some(s));
+        }
+        for other in &DRM_SYSTEMS[i + 1..] {
+            assert_ne!(s.system_id, other.system_id);
+            assert_ne!(s.name, other.name);
+        }
+    }
+}
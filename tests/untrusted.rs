@@ -0,0 +1,201 @@
+//! Tests that hostile or malformed input is rejected with an error rather than a panic.
+//
+// The fuzz targets in the fuzz/ directory exercise the same entry points with generated input.
+
+use std::io::Cursor;
+use test_log::test;
+use pssh_box::{from_bytes, from_buffer, from_base64, from_hex};
+use pssh_box::{find_iter, find_boxes_buffer, find_boxes_stream};
+use pssh_box::{playready, irdeto, nagra, wiseplay};
+use pssh_box::{from_bytes_with_options, from_buffer_with_options, from_hex_with_options, parse_lenient_with_options};
+use pssh_box::{find_iter_with_options, find_boxes_buffer_with_options, find_boxes_stream_with_options};
+use pssh_box::{ParseOptions, ParseLimits, PsshError, ToBytes};
+
+
+#[test]
+fn test_untrusted_empty() {
+    assert!(from_bytes(&[]).is_err());
+    assert!(from_buffer(&[]).unwrap().is_empty());
+    assert!(from_hex("").is_err());
+    assert!(from_base64("").is_err());
+    assert_eq!(find_iter(&[]).count(), 0);
+    assert_eq!(find_boxes_buffer(&[]).count(), 0);
+    assert_eq!(find_boxes_stream(Cursor::new(Vec::new())).count(), 0);
+}
+
+
+#[test]
+fn test_untrusted_box_size() {
+    // A pssh box type preceded by a box size of 0 and of 4
+    let mut buf = hex::decode("00000000707373680000000000000000000000000000000000000000").unwrap();
+    assert_eq!(find_iter(&buf).count(), 0);
+    assert_eq!(find_boxes_buffer(&buf).count(), 0);
+    assert_eq!(find_boxes_stream(Cursor::new(buf.clone())).count(), 0);
+    buf[3] = 4;
+    assert_eq!(find_iter(&buf).count(), 0);
+    assert_eq!(find_boxes_buffer(&buf).count(), 0);
+    assert_eq!(find_boxes_stream(Cursor::new(buf)).count(), 0);
+
+    // A box size of 0 and of u32::MAX in base64 input that needs decoding window by window.
+    assert!(from_base64("AAAAAHBzc2gAAAAA7e+LqXnWSs6jyCfc1R0h7Q===").is_err());
+    assert!(from_base64("/////3Bzc2gAAAAA7e+LqXnWSs6jyCfc1R0h7Q===").is_err());
+    // Non-ASCII characters which don't fall on the boundaries of our decoding windows
+    assert!(from_base64("AAAA€€€€€€€€€€€€€€€€").is_err());
+    // A box size of 1 followed by a largesize cut short by padding
+    assert!(from_base64("AAAAAXBzc2gAAA==AAAAAAAA").is_err());
+}
+
+
+#[test]
+fn test_untrusted_truncated_boxes() {
+    let b64 = "AAAAQHBzc2gAAAAA7e+LqXnWSs6jyCfc1R0h7QAAACAiGFlPVVRVQkU6NTM5ZjEyZjRhM2IzMTczYkjj3JWbBg==";
+    let buf = base64::Engine::decode(&base64::prelude::BASE64_STANDARD, b64).unwrap();
+    for len in 0..buf.len() {
+        let truncated = &buf[..len];
+        // from_bytes ignores a single trailing octet
+        if len > 1 {
+            assert!(from_bytes(truncated).is_err(), "accepted {len} octet prefix");
+        }
+        assert!(from_buffer(truncated).unwrap().is_empty());
+        assert_eq!(find_boxes_buffer(truncated).count(), 0);
+    }
+}
+
+
+#[test]
+fn test_untrusted_payloads() {
+    // PlayReady record with an odd-length UTF-16 value
+    let pr = hex::decode("0b00000001000100010041").unwrap();
+    assert!(playready::parse_pssh_data(&pr).is_err());
+    assert!(irdeto::parse_pssh_data(&[1, 2, 3]).is_err());
+    // base64url of {"keyId":"x"}, which lacks a contentId
+    assert!(nagra::parse_pssh_data(b"eyJrZXlJZCI6IngifQ").is_err());
+    assert!(nagra::parse_pssh_data(b"\xff\xfe").is_err());
+    assert!(wiseplay::parse_pssh_data(b"{\"kids\":").is_err());
+}
+
+
+// A reader which returns at most chunk octets per read() call.
+struct ChunkedReader {
+    data: Vec<u8>,
+    pos: usize,
+    chunk: usize,
+}
+
+impl std::io::Read for ChunkedReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.chunk.min(buf.len()).min(self.data.len() - self.pos);
+        buf[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+fn limited(limits: ParseLimits) -> ParseOptions {
+    ParseOptions { limits, ..Default::default() }
+}
+
+fn limit_name(e: &PsshError) -> Option<&'static str> {
+    match e {
+        PsshError::LimitExceeded { limit, .. } => Some(limit),
+        _ => None,
+    }
+}
+
+
+#[test]
+fn test_untrusted_limits() {
+    // A v1 box header claiming 2^32-1 KIDs
+    let kids = hex::decode("0000002870737368010000001077efecc0b24d02ace33c1e52e2fb4bffffffff0000000000000000").unwrap();
+    let err = from_bytes(&kids).unwrap_err();
+    assert!(matches!(err, PsshError::LimitExceeded { offset: 28, value: 0xffffffff, max: 4096, .. }));
+    assert_eq!(limit_name(&err), Some("KID count"));
+    let err = from_bytes_with_options(&kids, &limited(ParseLimits::unlimited())).unwrap_err();
+    assert!(matches!(err, PsshError::Truncated { offset: 32, .. }));
+
+    // A v0 box claiming almost 4 GB of PSSH data
+    let data = hex::decode("0000002070737368000000001077efecc0b24d02ace33c1e52e2fb4bfffffff0").unwrap();
+    let err = from_bytes(&data).unwrap_err();
+    assert_eq!(limit_name(&err), Some("PSSH data length"));
+    assert_eq!(err.offset(), Some(28));
+    let small = limited(ParseLimits { max_pssh_data_len: 4, ..Default::default() });
+    let bx = hex::decode("0000002470737368000000001077efecc0b24d02ace33c1e52e2fb4b0000000401020304").unwrap();
+    assert!(from_bytes_with_options(&bx, &small).is_ok());
+    let mut bigger = bx.clone();
+    bigger[3] = 0x25;
+    bigger[31] = 5;
+    bigger.push(5);
+    assert!(from_bytes_with_options(&bigger, &small).is_err());
+}
+
+
+#[test]
+fn test_untrusted_box_count() {
+    let bx = hex::decode("0000002470737368000000001077efecc0b24d02ace33c1e52e2fb4b0000000401020304").unwrap();
+    let three = bx.repeat(3);
+    let two = limited(ParseLimits { max_boxes: 2, ..Default::default() });
+    assert_eq!(from_bytes(&three).unwrap().len(), 3);
+    let err = from_bytes_with_options(&three, &two).unwrap_err();
+    assert!(matches!(err, PsshError::LimitExceeded { offset: 72, value: 3, max: 2, .. }));
+    assert!(from_hex_with_options(&hex::encode(&three), &two).is_err());
+    assert!(from_buffer_with_options(&three, &two).is_err());
+    let (boxes, diagnostics) = parse_lenient_with_options(&three, &two);
+    assert_eq!(boxes.len(), 2);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(limit_name(&diagnostics[0].error), Some("box count"));
+    assert_eq!(find_iter_with_options(&three, &two).count(), 2);
+    assert_eq!(find_boxes_buffer_with_options(&three, &two).count(), 2);
+    let mut stream = find_boxes_stream_with_options(Cursor::new(three), &two);
+    assert!(stream.next().unwrap().is_ok());
+    assert!(stream.next().unwrap().is_ok());
+    let err = stream.next().unwrap().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(err.to_string().contains("box count"));
+    assert!(stream.next().is_none());
+}
+
+
+#[test]
+fn test_untrusted_stream_buffering() {
+    let bx = hex::decode("0000002470737368000000001077efecc0b24d02ace33c1e52e2fb4b0000000401020304").unwrap();
+    // A box header claiming 1 MB, followed by 2 MB of other data and a valid box
+    let mut data = hex::decode("000f424070737368").unwrap();
+    data.extend_from_slice(&vec![0x42; 2_000_000]);
+    data.extend_from_slice(&bx);
+    let options = limited(ParseLimits { max_buffered: 64 * 1024, ..Default::default() });
+    let results: Vec<_> = find_boxes_stream_with_options(Cursor::new(data.clone()), &options).collect();
+    assert_eq!(results.len(), 2);
+    let err = results[0].as_ref().unwrap_err();
+    let inner = err.get_ref().and_then(|e| e.downcast_ref::<PsshError>()).unwrap();
+    assert!(matches!(inner, PsshError::LimitExceeded { offset: 0, value: 1_000_000, .. }));
+    assert_eq!(results[1].as_ref().unwrap().to_bytes(), bx);
+    // The candidate is rejected from its header, without reading the 64 kB limit first.
+    let mut reader = ChunkedReader { data: data.clone(), pos: 0, chunk: 16 };
+    let mut stream = find_boxes_stream_with_options(&mut reader, &options);
+    assert!(stream.next().unwrap().is_err());
+    drop(stream);
+    assert_eq!(reader.pos, 16);
+    // With the default limits, the 1 MB candidate is buffered and rejected when parsed.
+    let boxes: Vec<_> = find_boxes_stream(Cursor::new(data)).collect::<Result<_, _>>().unwrap();
+    assert_eq!(boxes.len(), 1);
+}
+
+
+#[test]
+fn test_untrusted_stream_chunks() {
+    let b64 = "AAAAQHBzc2gAAAAA7e+LqXnWSs6jyCfc1R0h7QAAACAiGFlPVVRVQkU6NTM5ZjEyZjRhM2IzMTczYkjj3JWbBg==";
+    let wv = base64::Engine::decode(&base64::prelude::BASE64_STANDARD, b64).unwrap();
+    let mut data = b"ftyp\0\0\0\x10pssh moov".to_vec();
+    data.extend_from_slice(&wv);
+    data.extend_from_slice(&[0; 9000]);
+    data.extend_from_slice(&wv);
+    data.extend_from_slice(b"mdat");
+    data.extend_from_slice(&wv);
+    let expected: Vec<_> = find_boxes_buffer(&data).collect();
+    assert_eq!(expected.len(), 3);
+    for chunk in [1, 3, 7, 64, 8192] {
+        let reader = ChunkedReader { data: data.clone(), pos: 0, chunk };
+        let boxes: Vec<_> = find_boxes_stream(reader).collect::<Result<_, _>>().unwrap();
+        assert_eq!(boxes, expected, "with chunk size {chunk}");
+    }
+}
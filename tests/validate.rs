@@ -0,0 +1,119 @@
+//! Tests for the semantic validation of PSSH boxes
+
+use test_log::test;
+use pssh_box::{from_base64, PsshBox, PsshBoxVec, PsshData, DRMKeyId, Finding, Lint, Severity};
+use pssh_box::playready::ProtectInfo;
+use pssh_box::widevine::widevine_pssh_data::{Algorithm, ProtectionScheme};
+use pssh_box::WIDEVINE_SYSTEM_ID;
+
+
+fn lints(findings: &[Finding]) -> Vec<Lint> {
+    findings.iter().map(|f| f.lint.clone()).collect()
+}
+
+fn widevine_with_kids(header: &[DRMKeyId], pssh_data: &[DRMKeyId]) -> PsshBox {
+    let mut bx = PsshBox::new_widevine();
+    for kid in header {
+        bx.add_key_id(*kid);
+    }
+    if let PsshData::Widevine(ref mut wv) = bx.pssh_data {
+        wv.algorithm = Some(Algorithm::Aesctr as i32);
+        wv.key_id = pssh_data.iter().map(|k| k.as_bytes().to_vec()).collect();
+    }
+    bx
+}
+
+#[test]
+fn test_validate_clean() {
+    let boxes = from_base64("AAACxHBzc2gAAAAAmgTweZhAQoarkuZb4IhflQAAAqSkAgAAAQABAJoCPABXAFIATQBIAEUAQQBEAEUAUgAgAHgAbQBsAG4AcwA9ACIAaAB0AHQAcAA6AC8ALwBzAGMAaABlAG0AYQBzAC4AbQBpAGMAcgBvAHMAbwBmAHQALgBjAG8AbQAvAEQAUgBNAC8AMgAwADAANwAvADAAMwAvAFAAbABhAHkAUgBlAGEAZAB5AEgAZQBhAGQAZQByACIAIAB2AGUAcgBzAGkAbwBuAD0AIgA0AC4AMAAuADAALgAwACIAPgA8AEQAQQBUAEEAPgA8AFAAUgBPAFQARQBDAFQASQBOAEYATwA+ADwASwBFAFkATABFAE4APgAxADYAPAAvAEsARQBZAEwARQBOAD4APABBAEwARwBJAEQAPgBBAEUAUwBDAFQAUgA8AC8AQQBMAEcASQBEAD4APAAvAFAAUgBPAFQARQBDAFQASQBOAEYATwA+ADwASwBJAEQAPgB4AG8AeQB1AHYAMgBhAEUAcQA2ADQASwBqAFAAUgBEAHQANgBTAHcAQwBBAD0APQA8AC8ASwBJAEQAPgA8AEwAQQBfAFUAUgBMAD4AaAB0AHQAcABzADoALwAvAGQAcgBtAC4AcgBlAGQAZQBmAGkAbgBlAC4AcABsAC8AUABsAGEAeQBSAGUAYQBkAHkALwByAGkAZwBoAHQAcwBtAGEAbgBhAGcAZQByAC4AYQBzAG0AeAA/AHQAeQBwAGUAPQBkAGEAcwBoADwALwBMAEEAXwBVAFIATAA+ADwAQwBIAEUAQwBLAFMAVQBNAD4ALwA4AEkANABYAGEAUAB0ADIASgA4AD0APAAvAEMASABFAEMASwBTAFUATQA+ADwALwBEAEEAVABBAD4APAAvAFcAUgBNAEgARQBBAEQARQBSAD4A")
+        .unwrap();
+    assert_eq!(boxes[0].validate(), vec![]);
+    assert_eq!(boxes.validate(), vec![]);
+
+    let kid = DRMKeyId::from_hex("00112233445566778899aabbccddeeff").unwrap();
+    assert_eq!(widevine_with_kids(&[kid], &[kid]).validate(), vec![]);
+    assert_eq!(widevine_with_kids(&[kid], &[]).validate(), vec![]);
+    assert_eq!(widevine_with_kids(&[], &[kid]).validate(), vec![]);
+}
+
+#[test]
+fn test_validate_box() {
+    let kid1 = DRMKeyId::from_hex("00112233445566778899aabbccddeeff").unwrap();
+    let kid2 = DRMKeyId::from_hex("ffeeddccbbaa99887766554433221100").unwrap();
+    let bx = widevine_with_kids(&[kid1], &[kid1, kid2]);
+    let findings = bx.validate();
+    assert_eq!(lints(&findings), vec![Lint::KeyIdMismatch { header: vec![kid1], pssh_data: vec![kid1, kid2] }]);
+    assert_eq!(findings[0].severity, Severity::Error);
+    assert_eq!(findings[0].box_index, None);
+    assert!(findings[0].to_string().starts_with("error: key IDs in box header"));
+
+    let mut bx = widevine_with_kids(&[], &[kid1]);
+    bx.version = 0;
+    bx.flags = 2;
+    if let PsshData::Widevine(ref mut wv) = bx.pssh_data {
+        wv.key_id.push(vec![1, 2, 3]);
+        wv.algorithm = Some(Algorithm::Unencrypted as i32);
+    }
+    assert_eq!(lints(&bx.validate()), vec![
+        Lint::FlagsOnV0 { flags: 2 },
+        Lint::WidevineKeyIdLength { index: 1, length: 3 },
+        Lint::WidevineUnencrypted,
+    ]);
+    assert_eq!(Lint::WidevineUnencrypted.severity(), Severity::Warning);
+
+    // An empty PlayReady header of version 4.0.0.0 lacks the KID and PROTECTINFO elements.
+    let mut bx = PsshBox::new_playready();
+    if let PsshData::PlayReady(ref mut pr) = bx.pssh_data {
+        pr.record[0].wrm_header_mut().unwrap().data.la_url = Some(String::from("http://example.com/rightsmanager.asmx"));
+    }
+    assert_eq!(lints(&bx.validate()), vec![
+        Lint::PlayReadyMissingElement { version: String::from("4.0.0.0"), element: "KID" },
+        Lint::PlayReadyMissingElement { version: String::from("4.0.0.0"), element: "PROTECTINFO" },
+        Lint::InsecureLicenseUrl { url: String::from("http://example.com/rightsmanager.asmx") },
+    ]);
+
+    // A v1 box with a PlayReady 4.2 header, whose KIDs are in PROTECTINFO/KIDS.
+    let mut bx = from_base64("AAAFyHBzc2gBAAAAmgTweZhAQoarkuZb4IhflQAAAAPoLxhMOqpXtKzoYGteP+utCHvPxvelVxa4QGqm66M2ng1rQCONoV51r2h1xRTFm2MAAAV0dAUAAAEAAQBqBTwAVwBSAE0ASABFAEEARABFAFIAIAB4AG0AbABuAHMAPQAiAGgAdAB0AHAAOgAvAC8AcwBjAGgAZQBtAGEAcwAuAG0AaQBjAHIAbwBzAG8AZgB0AC4AYwBvAG0ALwBEAFIATQAvADIAMAAwADcALwAwADMALwBQAGwAYQB5AFIAZQBhAGQAeQBIAGUAYQBkAGUAcgAiACAAdgBlAHIAcwBpAG8AbgA9ACIANAAuADIALgAwAC4AMAAiAD4APABEAEEAVABBAD4APABQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEsASQBEAFMAPgA8AEsASQBEACAAQQBMAEcASQBEAD0AIgBBAEUAUwBDAFQAUgAiACAAQwBIAEUAQwBLAFMAVQBNAD0AIgArAE4AVgA5AC8AOABqAGIAZgByAHcAPQAiACAAVgBBAEwAVQBFAD0AIgBUAEIAZwB2ADYASwBvADYAdABGAGUAcwA2AEcAQgByAFgAagAvAHIAcgBRAD0APQAiAD4APAAvAEsASQBEAD4APABLAEkARAAgAEEATABHAEkARAA9ACIAQQBFAFMAQwBUAFIAIgAgAEMASABFAEMASwBTAFUATQA9ACIAWgAxADAAaQBPAFkAWQB6AEgAMwBrAD0AIgAgAFYAQQBMAFUARQA9ACIAeABzADkANwBDAEsAWAAzAEYAbABlADQAUQBHAHEAbQA2ADYATQAyAG4AZwA9AD0AIgA+ADwALwBLAEkARAA+ADwASwBJAEQAIABBAEwARwBJAEQAPQAiAEEARQBTAEMAVABSACIAIABDAEgARQBDAEsAUwBVAE0APQAiAE8ARQB1AE0AeQBEAGUAUQAxAHMAOAA9ACIAIABWAEEATABVAEUAPQAiAEkAMABCAHIARABhAEcATgBkAFYANgB2AGEASABYAEYARgBNAFcAYgBZAHcAPQA9ACIAPgA8AC8ASwBJAEQAPgA8AC8ASwBJAEQAUwA+ADwALwBQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEwAQQBfAFUAUgBMAD4AaAB0AHQAcABzADoALwAvAHQAZQBzAHQALgBwAGwAYQB5AHIAZQBhAGQAeQAuAG0AaQBjAHIAbwBzAG8AZgB0AC4AYwBvAG0ALwBzAGUAcgB2AGkAYwBlAC8AcgBpAGcAaAB0AHMAbQBhAG4AYQBnAGUAcgAuAGEAcwBtAHgAPwBjAGYAZwA9ACgAawBpAGQAOgBUAEIAZwB2ADYASwBvADYAdABGAGUAcwA2AEcAQgByAFgAagAvAHIAcgBRAD0APQAsAGMAbwBuAHQAZQBuAHQAawBlAHkAOgB3AHYAcgAyAGIAaQBoAFMAegBFAHgASwBkAFIAOABLAEsAcABRAGYAMgB3AD0APQApACwAKABrAGkAZAA6AHgAcwA5ADcAQwBLAFgAMwBGAGwAZQA0AFEARwBxAG0ANgA2AE0AMgBuAGcAPQA9ACwAYwBvAG4AdABlAG4AdABrAGUAeQA6AGcAbwBIAE8AagBiAGsASQBOAHAAZgBaAGQAdwAyAEgAMgA1AFkAbwBOAFEAPQA9ACkALAAoAGsAaQBkADoASQAwAEIAcgBEAGEARwBOAGQAVgA2AHYAYQBIAFgARgBGAE0AVwBiAFkAdwA9AD0ALABjAG8AbgB0AGUAbgB0AGsAZQB5ADoAVwBDADEAcgBjAFcARQBiADQARQB5AEkANABpAHEAcQBFAEUAUQBlAEwAQQA9AD0AKQA8AC8ATABBAF8AVQBSAEwAPgA8AC8ARABBAFQAQQA+ADwALwBXAFIATQBIAEUAQQBEAEUAUgA+AA==").unwrap()[0].clone();
+    assert_eq!(bx.validate(), vec![]);
+    let kids = bx.key_ids.clone();
+    bx.key_ids.truncate(2);
+    assert_eq!(lints(&bx.validate()), vec![Lint::KeyIdMismatch { header: kids[..2].to_vec(), pssh_data: kids }]);
+}
+
+#[test]
+fn test_validate_boxes() {
+    let kid = DRMKeyId::from_hex("00112233445566778899aabbccddeeff").unwrap();
+    let mut widevine = widevine_with_kids(&[kid], &[kid]);
+    if let PsshData::Widevine(ref mut wv) = widevine.pssh_data {
+        wv.protection_scheme = Some(ProtectionScheme::Cbcs as i32);
+    }
+    let mut playready = PsshBox::new_playready();
+    if let PsshData::PlayReady(ref mut pr) = playready.pssh_data {
+        let data = &mut pr.record[0].wrm_header_mut().unwrap().data;
+        data.kids.push(Default::default());
+        data.protect_info = Some(ProtectInfo {
+            keylen: Some(16),
+            algid: Some(String::from("AESCTR")),
+            ..Default::default()
+        });
+    }
+    let mut v0 = widevine.clone();
+    v0.version = 0;
+    let mut boxes = PsshBoxVec::new();
+    boxes.add(widevine);
+    boxes.add(playready);
+    boxes.add(v0);
+    let findings = boxes.validate();
+    assert_eq!(lints(&findings), vec![
+        Lint::DuplicateSystem { system_id: WIDEVINE_SYSTEM_ID, indices: vec![0, 2] },
+        Lint::ProtectionSchemeMismatch { algid: String::from("AESCTR"), scheme: String::from("cbcs") },
+    ]);
+    assert!(findings.iter().all(|f| f.box_index.is_none()));
+
+    let mut boxes = PsshBoxVec::new();
+    boxes.add(PsshBox::new_widevine());
+    boxes.add(PsshBox::new_playready());
+    let findings = boxes.validate();
+    assert!(findings.iter().all(|f| f.box_index == Some(1)));
+    assert!(findings[0].to_string().starts_with("error: box 1: PlayReady header"));
+}